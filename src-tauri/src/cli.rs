@@ -0,0 +1,97 @@
+use crate::midi::{NoteMode, NullSink};
+use crate::state::AppState;
+
+/// Options for headless playback, e.g.
+/// `wwm-player.exe --play song.mid --mode pentatonic --transpose -2 --speed 0.9`
+pub struct CliOptions {
+    pub path: String,
+    pub mode: Option<NoteMode>,
+    pub transpose: Option<i32>,
+    pub speed: Option<f64>,
+}
+
+fn parse_note_mode(name: &str) -> Option<NoteMode> {
+    match name.to_lowercase().replace(['-', '_'], "").as_str() {
+        "closest" => Some(NoteMode::Closest),
+        "quantize" => Some(NoteMode::Quantize),
+        "transposeonly" => Some(NoteMode::TransposeOnly),
+        "pentatonic" => Some(NoteMode::Pentatonic),
+        "chromatic" => Some(NoteMode::Chromatic),
+        "raw" => Some(NoteMode::Raw),
+        "mono" => Some(NoteMode::Mono),
+        "contour" => Some(NoteMode::Contour),
+        _ => None,
+    }
+}
+
+/// Parse command line arguments. Returns None when `--play` isn't given,
+/// in which case the normal window should start.
+pub fn parse_args<I: Iterator<Item = String>>(args: I) -> Option<Result<CliOptions, String>> {
+    let args: Vec<String> = args.collect();
+    if !args.iter().any(|arg| arg == "--play") {
+        return None;
+    }
+
+    Some(parse_play_args(&args))
+}
+
+fn parse_play_args(args: &[String]) -> Result<CliOptions, String> {
+    let mut path = None;
+    let mut mode = None;
+    let mut transpose = None;
+    let mut speed = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let mut value = || iter.next().ok_or_else(|| format!("Missing value for {}", arg));
+        match arg.as_str() {
+            "--play" => path = Some(value()?.clone()),
+            "--mode" => {
+                let name = value()?;
+                mode = Some(parse_note_mode(name).ok_or(format!("Unknown mode: {}", name))?);
+            }
+            "--transpose" => {
+                let semitones = value()?;
+                transpose = Some(semitones.parse().map_err(|_| format!("Invalid transpose: {}", semitones))?);
+            }
+            "--speed" => {
+                let multiplier = value()?;
+                speed = Some(multiplier.parse().map_err(|_| format!("Invalid speed: {}", multiplier))?);
+            }
+            other => return Err(format!("Unknown argument: {}", other)),
+        }
+    }
+
+    Ok(CliOptions {
+        path: path.ok_or("Missing file for --play")?,
+        mode,
+        transpose,
+        speed,
+    })
+}
+
+/// Load and play a file to completion without the webview
+pub fn run(options: CliOptions) -> Result<(), String> {
+    let mut app_state = AppState::new();
+
+    app_state.load_midi(&options.path, 0)?;
+    // After loading, so an explicit mode wins over the song's remembered one
+    if let Some(mode) = options.mode {
+        app_state.set_note_mode(mode);
+    }
+    if let Some(semitones) = options.transpose {
+        app_state.set_session_transpose(semitones);
+    }
+    if let Some(speed) = options.speed {
+        app_state.set_playback_speed(speed)?;
+    }
+
+    if let Err(e) = crate::keyboard::focus_black_desert_window() {
+        eprintln!("Could not focus game window: {}", e);
+    }
+    std::thread::sleep(app_state.get_focus_delay());
+
+    app_state.start_playback_with(NullSink)?;
+    app_state.wait_for_playback();
+    Ok(())
+}
@@ -0,0 +1,108 @@
+use serde::Serialize;
+
+/// Advisory warning about a hotkey that may also trigger something in the game or Windows
+#[derive(Debug, Clone, Serialize)]
+pub struct HotkeyWarning {
+    pub binding: String,
+    pub warning: String,
+}
+
+// Keys (uppercase, without modifiers) that games or Windows commonly use themselves
+const RISKY_KEYS: &[(&str, &str)] = &[
+    ("ESC", "Opens the game menu"),
+    ("TAB", "Common game bind (map, scoreboard)"),
+    ("ENTER", "Opens chat in most games"),
+    ("SPACE", "Jump / interact in most games"),
+    ("PRINTSCREEN", "Used for screenshots"),
+    ("F1", "Function keys are common game binds (help, UI panels)"),
+    ("F2", "Function keys are common game binds (help, UI panels)"),
+    ("F3", "Function keys are common game binds (help, UI panels)"),
+    ("F4", "Function keys are common game binds (help, UI panels)"),
+    ("F5", "Function keys are common game binds (help, UI panels)"),
+    ("F6", "Function keys are common game binds (help, UI panels)"),
+    ("F7", "Function keys are common game binds (help, UI panels)"),
+    ("F8", "Function keys are common game binds (help, UI panels)"),
+    ("F9", "Function keys are common game binds (help, UI panels)"),
+    ("F10", "Function keys are common game binds (help, UI panels)"),
+    ("F11", "Often toggles fullscreen"),
+    ("F12", "Often takes a screenshot (e.g. Steam overlay)"),
+];
+
+// Full combos with a well-known system meaning
+const RISKY_COMBOS: &[(&str, &str)] = &[
+    ("ALT+F4", "Closes the game window"),
+    ("ALT+TAB", "Switches windows"),
+    ("ALT+ENTER", "Toggles fullscreen in many games"),
+    ("CTRL+ALT+DELETE", "Reserved by Windows"),
+    ("SHIFT+TAB", "Opens the Steam overlay"),
+];
+
+/// Split "ctrl + Shift+f9" into sorted uppercase modifiers and the key
+fn parse_binding(binding: &str) -> Option<(Vec<String>, String)> {
+    let mut parts: Vec<String> = binding
+        .split('+')
+        .map(|part| part.trim().to_ascii_uppercase())
+        .filter(|part| !part.is_empty())
+        .map(|part| match part.as_str() {
+            "CONTROL" => "CTRL".to_string(),
+            "RETURN" => "ENTER".to_string(),
+            "ESCAPE" => "ESC".to_string(),
+            _ => part,
+        })
+        .collect();
+    let key = parts.pop()?;
+    // Same order as the combo table, so "Shift+Ctrl+X" matches "CTRL+SHIFT+X"
+    let order = ["CTRL", "ALT", "SHIFT", "WIN"];
+    parts.sort_by_key(|m| order.iter().position(|o| o == m).unwrap_or(order.len()));
+    Some((parts, key))
+}
+
+/// Flag hotkeys likely to collide with game or system binds. Advisory only.
+pub fn check_hotkey_conflicts(bindings: &[String]) -> Vec<HotkeyWarning> {
+    let note_keys = crate::midi::all_instrument_keys();
+    let mut warnings = Vec::new();
+    let mut seen: Vec<String> = Vec::new();
+    let mut warn = |binding: &str, warning: &str| {
+        warnings.push(HotkeyWarning {
+            binding: binding.to_string(),
+            warning: warning.to_string(),
+        });
+    };
+
+    for binding in bindings {
+        let (modifiers, key) = match parse_binding(binding) {
+            Some(parsed) => parsed,
+            None => {
+                warn(binding, "Empty binding");
+                continue;
+            }
+        };
+        let combo = modifiers.iter().cloned().chain(std::iter::once(key.clone())).collect::<Vec<_>>().join("+");
+
+        if seen.contains(&combo) {
+            warn(binding, "Bound more than once");
+        }
+        seen.push(combo.clone());
+
+        if let Some((_, reason)) = RISKY_COMBOS.iter().find(|(risky, _)| *risky == combo) {
+            warn(binding, reason);
+        }
+        if modifiers.is_empty() {
+            if let Some((_, reason)) = RISKY_KEYS.iter().find(|(risky, _)| *risky == key) {
+                warn(binding, reason);
+            }
+            if note_keys.iter().any(|note_key| note_key.eq_ignore_ascii_case(&key)) {
+                warn(binding, "Also a note key: playback presses it");
+            } else if key.len() == 1 {
+                warn(binding, "Single character keys are typed into chat");
+            }
+        }
+        if modifiers.iter().any(|m| m == "WIN") {
+            warn(binding, "Windows key combos are usually reserved by Windows");
+        } else if modifiers.iter().any(|m| m == "ALT") {
+            warn(binding, "Alt combos can open the game's menu bar or toggle fullscreen");
+        }
+    }
+
+    warnings
+}
@@ -1,4 +1,5 @@
 use enigo::{Enigo, Key, Keyboard, Settings, Direction};
+use tauri::Emitter;
 use std::sync::Mutex;
 
 #[cfg(target_os = "windows")]
@@ -52,30 +53,140 @@ unsafe extern "system" fn enum_windows_proc(hwnd: HWND, lparam: LPARAM) -> BOOL
     BOOL(1)
 }
 
+// Input simulation, or the reason it couldn't start (no display, missing permissions, ...)
 lazy_static::lazy_static! {
-    static ref ENIGO: Mutex<Enigo> = Mutex::new(
-        Enigo::new(&Settings::default()).expect("Failed to initialize Enigo")
-    );
+    static ref ENIGO: Mutex<Result<Enigo, String>> = Mutex::new(create_enigo());
+    // Key presses captured since start_recording, None when not recording
+    static ref RECORDING: Mutex<Option<Recording>> = Mutex::new(None);
 }
 
-pub fn key_down(key: &str) {
-    let mut enigo = ENIGO.lock().unwrap();
+/// One key press or release captured while recording
+#[derive(Debug, Clone)]
+pub struct RecordedKey {
+    // Since recording started
+    pub time_ms: u64,
+    pub key: String,
+    pub down: bool,
+}
+
+struct Recording {
+    started: std::time::Instant,
+    keys: Vec<RecordedKey>,
+}
+
+/// Capture every key press and release from now on (restarts a recording in progress)
+pub fn start_recording() {
+    *RECORDING.lock().unwrap() = Some(Recording {
+        started: std::time::Instant::now(),
+        keys: Vec::new(),
+    });
+}
+
+/// End the recording and return what was captured, or None if none was running
+pub fn stop_recording() -> Option<Vec<RecordedKey>> {
+    RECORDING.lock().unwrap().take().map(|recording| recording.keys)
+}
+
+pub fn is_recording() -> bool {
+    RECORDING.lock().unwrap().is_some()
+}
+
+fn record(key: &str, down: bool) {
+    if let Some(recording) = RECORDING.lock().unwrap().as_mut() {
+        recording.keys.push(RecordedKey {
+            time_ms: recording.started.elapsed().as_millis() as u64,
+            key: key.to_string(),
+            down,
+        });
+    }
+}
+
+fn create_enigo() -> Result<Enigo, String> {
+    Enigo::new(&Settings::default()).map_err(|e| format!("Failed to initialize keyboard input: {}", e))
+}
+
+// A panic while holding the lock shouldn't disable input for the rest of the session
+fn lock_enigo() -> std::sync::MutexGuard<'static, Result<Enigo, String>> {
+    ENIGO.lock().unwrap_or_else(|e| e.into_inner())
+}
 
-    if let Some(k) = string_to_key(key) {
-        let _ = enigo.key(k, Direction::Press);
+/// Why keyboard input is unavailable, if it is
+pub fn keyboard_error() -> Option<String> {
+    lock_enigo().as_ref().err().cloned()
+}
+
+/// Try to start input simulation again (e.g. after the user granted permissions)
+pub fn reinit_keyboard() -> Result<(), String> {
+    let mut enigo = lock_enigo();
+    *enigo = create_enigo();
+    enigo.as_ref().map(|_| ()).map_err(|e| e.clone())
+}
+
+// Test mode: keys are echoed to the UI as `test-key-echo` instead of pressed, and the game
+// counts as focused, so playback can be tried without the game running
+static TEST_MODE: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+pub fn set_test_mode(enabled: bool) {
+    TEST_MODE.store(enabled, std::sync::atomic::Ordering::SeqCst);
+}
+
+pub fn is_test_mode() -> bool {
+    TEST_MODE.load(std::sync::atomic::Ordering::SeqCst)
+}
+
+fn echo_key(key: &str, down: bool) {
+    if let Some(app_handle) = crate::GLOBAL_APP_HANDLE.get() {
+        let _ = app_handle.emit("test-key-echo", serde_json::json!({ "key": key, "down": down }));
+    }
+}
+
+pub fn key_down(key: &str) {
+    record(key, true);
+    if is_test_mode() {
+        echo_key(key, true);
+        return;
+    }
+    // No-op while input simulation is unavailable
+    if let (Ok(enigo), Some(k)) = (lock_enigo().as_mut(), string_to_key(key)) {
+        check_key_result(key, enigo.key(k, Direction::Press));
     }
 }
 
 pub fn key_up(key: &str) {
-    let mut enigo = ENIGO.lock().unwrap();
+    record(key, false);
+    if is_test_mode() {
+        echo_key(key, false);
+        return;
+    }
+    if let (Ok(enigo), Some(k)) = (lock_enigo().as_mut(), string_to_key(key)) {
+        check_key_result(key, enigo.key(k, Direction::Release));
+    }
+}
 
-    if let Some(k) = string_to_key(key) {
-        let _ = enigo.key(k, Direction::Release);
+// Set after a failed key send, so a run of failures is reported once instead of per key
+static KEY_ERROR_REPORTED: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+fn check_key_result(key: &str, result: enigo::InputResult<()>) {
+    use std::sync::atomic::Ordering;
+    match result {
+        Ok(()) => KEY_ERROR_REPORTED.store(false, Ordering::SeqCst),
+        Err(e) => {
+            if !KEY_ERROR_REPORTED.swap(true, Ordering::SeqCst) {
+                crate::report_error("playback-error", &format!("Failed to send key {}: {}", key, e));
+            }
+        }
     }
 }
 
 
 
+/// Release every instrument key unconditionally (safety net for stuck keys)
+pub fn panic_release() {
+    for key in crate::midi::all_instrument_keys().into_iter().chain(crate::midi::hand_split_keys()) {
+        key_up(key);
+    }
+}
+
 fn string_to_key(key: &str) -> Option<Key> {
     match key.to_lowercase().as_str() {
         // Low octave
@@ -105,12 +216,41 @@ fn string_to_key(key: &str) -> Option<Key> {
         "y" => Some(Key::Unicode('y')),
         "u" => Some(Key::Unicode('u')),
 
+        // Second hand cluster: number row and numpad
+        digit if digit.len() == 1 && digit.chars().all(|c| c.is_ascii_digit()) => {
+            digit.chars().next().map(Key::Unicode)
+        }
+        numpad if numpad.starts_with("num") => numpad_key(&numpad[3..]),
+
         _ => None,
     }
 }
 
+// Numpad keys by virtual key code, so they aren't confused with the number row
+#[cfg(target_os = "windows")]
+fn numpad_key(name: &str) -> Option<Key> {
+    let vk = match name {
+        "*" => 0x6A,
+        "+" => 0x6B,
+        "-" => 0x6D,
+        "." => 0x6E,
+        "/" => 0x6F,
+        digit if digit.len() == 1 => 0x60 + digit.chars().next()?.to_digit(10)?,
+        _ => return None,
+    };
+    Some(Key::Other(vk))
+}
+
+#[cfg(not(target_os = "windows"))]
+fn numpad_key(_name: &str) -> Option<Key> {
+    None
+}
+
 #[cfg(target_os = "windows")]
 pub fn is_black_desert_focused() -> Result<bool, String> {
+    if is_test_mode() {
+        return Ok(true);
+    }
     unsafe {
         let hwnd = GetForegroundWindow();
         if hwnd.0.is_null() {
@@ -128,6 +268,9 @@ pub fn is_black_desert_focused() -> Result<bool, String> {
 
 #[cfg(target_os = "windows")]
 pub fn focus_black_desert_window() -> Result<(), String> {
+    if is_test_mode() {
+        return Ok(());
+    }
     unsafe {
         let mut data = EnumData { target: None };
         EnumWindows(Some(enum_windows_proc), LPARAM(&mut data as *mut _ as isize))
@@ -150,12 +293,43 @@ pub fn focus_black_desert_window() -> Result<(), String> {
     Ok(())
 }
 
-/// Click at a specific screen position
+/// Puts the cursor back where it was when dropped, so clicking the instrument UI
+/// doesn't yank the player's camera/aim (restores even if the click fails)
+#[cfg(target_os = "windows")]
+struct CursorRestore(Option<windows::Win32::Foundation::POINT>);
+
 #[cfg(target_os = "windows")]
-pub fn mouse_click(x: i32, y: i32) {
+impl CursorRestore {
+    fn save() -> Self {
+        use windows::Win32::UI::WindowsAndMessaging::GetCursorPos;
+
+        let mut point = windows::Win32::Foundation::POINT::default();
+        let saved = unsafe { GetCursorPos(&mut point) }.ok().map(|_| point);
+        CursorRestore(saved)
+    }
+}
+
+#[cfg(target_os = "windows")]
+impl Drop for CursorRestore {
+    fn drop(&mut self) {
+        use windows::Win32::UI::WindowsAndMessaging::SetCursorPos;
+
+        if let Some(point) = self.0 {
+            unsafe {
+                let _ = SetCursorPos(point.x, point.y);
+            }
+        }
+    }
+}
+
+/// Click at a specific screen position, then put the cursor back
+#[cfg(target_os = "windows")]
+pub fn mouse_click(x: i32, y: i32) -> Result<(), String> {
     use windows::Win32::UI::WindowsAndMessaging::GetSystemMetrics;
     use windows::Win32::UI::WindowsAndMessaging::{SM_CXSCREEN, SM_CYSCREEN};
 
+    let _cursor = CursorRestore::save();
+
     unsafe {
         // Get screen dimensions for absolute positioning
         let screen_width = GetSystemMetrics(SM_CXSCREEN);
@@ -211,8 +385,13 @@ pub fn mouse_click(x: i32, y: i32) {
             },
         ];
 
-        SendInput(&inputs, std::mem::size_of::<INPUT>() as i32);
+        let sent = SendInput(&inputs, std::mem::size_of::<INPUT>() as i32);
+        if sent as usize != inputs.len() {
+            return Err(format!("Mouse click blocked ({} of {} inputs sent)", sent, inputs.len()));
+        }
     }
+
+    Ok(())
 }
 
 /// Mouse down at a specific screen position (for held notes)
@@ -286,7 +465,9 @@ pub fn mouse_up() {
 }
 
 #[cfg(not(target_os = "windows"))]
-pub fn mouse_click(_x: i32, _y: i32) {}
+pub fn mouse_click(_x: i32, _y: i32) -> Result<(), String> {
+    Ok(())
+}
 
 #[cfg(not(target_os = "windows"))]
 pub fn mouse_down(_x: i32, _y: i32) {}
@@ -0,0 +1,159 @@
+use std::sync::Mutex;
+
+use crate::midi::{EventType, MidiData, TempoMap, TimedEvent};
+
+// Default playback speed for text key sheets
+pub const DEFAULT_NOTES_PER_SECOND: f64 = 4.0;
+
+lazy_static::lazy_static! {
+    static ref NOTES_PER_SECOND: Mutex<f64> = Mutex::new(DEFAULT_NOTES_PER_SECOND);
+}
+
+pub fn set_notes_per_second(notes_per_second: f64) {
+    *NOTES_PER_SECOND.lock().unwrap() = notes_per_second.clamp(0.5, 20.0);
+}
+
+pub fn get_notes_per_second() -> f64 {
+    *NOTES_PER_SECOND.lock().unwrap()
+}
+
+/// Whether a path is a text key sheet rather than a MIDI file
+pub fn is_key_sheet(path: &str) -> bool {
+    std::path::Path::new(path)
+        .extension()
+        .and_then(|s| s.to_str())
+        .map(|ext| ext.eq_ignore_ascii_case("txt"))
+        .unwrap_or(false)
+}
+
+/// One step of a key sheet: the keys pressed together (empty for a rest)
+fn tokenize(text: &str) -> Result<Vec<Vec<&'static str>>, String> {
+    let known_keys = crate::midi::all_instrument_keys();
+    let lookup = |c: char| -> Option<&'static str> {
+        let c = c.to_ascii_lowercase();
+        known_keys.iter().copied().find(|key| key.len() == 1 && key.starts_with(c))
+    };
+
+    let mut steps = Vec::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            // "[zc]" plays the keys inside together as one step
+            '[' | '(' => {
+                let close = if c == '[' { ']' } else { ')' };
+                let mut chord = Vec::new();
+                for inner in chars.by_ref() {
+                    if inner == close {
+                        break;
+                    }
+                    if let Some(key) = lookup(inner) {
+                        chord.push(key);
+                    }
+                }
+                steps.push(chord);
+            }
+            // Spaces and dashes are rests
+            ' ' | '-' => steps.push(Vec::new()),
+            // Bar lines and line breaks are only for readability
+            '|' | '\n' | '\r' | '\t' => {}
+            _ => {
+                if let Some(key) = lookup(c) {
+                    steps.push(vec![key]);
+                }
+            }
+        }
+    }
+
+    if steps.iter().all(|step| step.is_empty()) {
+        return Err("Key sheet contains no playable keys".to_string());
+    }
+    Ok(steps)
+}
+
+/// Parse a text key sheet (e.g. "zxcv aa s|d") into explicit key events, one step per note
+pub fn load_key_sheet(path: &str) -> Result<MidiData, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let steps = tokenize(&text)?;
+
+    let step_ms = 1000.0 / get_notes_per_second();
+    let mut events = Vec::new();
+    for (index, step) in steps.iter().enumerate() {
+        let press_ms = (index as f64 * step_ms) as u64;
+        let release_ms = ((index + 1) as f64 * step_ms) as u64;
+        for &key in step {
+            events.push(TimedEvent {
+                time_ms: press_ms,
+                event_type: EventType::KeyDown(key),
+                note: 0,
+                channel: 0,
+                track: 0,
+            });
+            events.push(TimedEvent {
+                time_ms: release_ms,
+                event_type: EventType::KeyUp(key),
+                note: 0,
+                channel: 0,
+                track: 0,
+            });
+        }
+    }
+    crate::midi::sort_events(&mut events);
+    let duration = crate::midi::events_duration(&events);
+
+    // One step per beat, so musical position counts steps
+    let tempo_map = TempoMap::constant(step_ms * 1000.0);
+
+    Ok(MidiData {
+        events,
+        duration,
+        transpose: 0,
+        tempo_map,
+        markers: Vec::new(),
+        volumes: crate::midi::VolumeTimeline::default(),
+    })
+}
+
+// Steps per line when writing a key sheet, only for readability
+const STEPS_PER_LINE: usize = 32;
+
+/// Write recorded key presses as a text key sheet, snapping each press to a step at the
+/// current key sheet speed so the sheet replays with the same rhythm. Returns the press count.
+pub fn write_key_sheet(path: &str, keys: &[crate::keyboard::RecordedKey]) -> Result<usize, String> {
+    let presses: Vec<&crate::keyboard::RecordedKey> = keys
+        .iter()
+        .filter(|recorded| recorded.down && recorded.key.chars().count() == 1)
+        .collect();
+    let first_ms = presses.first().ok_or("Nothing was recorded")?.time_ms;
+
+    let step_ms = 1000.0 / get_notes_per_second();
+    let mut steps: Vec<Vec<&str>> = Vec::new();
+    for press in &presses {
+        let index = ((press.time_ms - first_ms) as f64 / step_ms).round() as usize;
+        if steps.len() <= index {
+            steps.resize(index + 1, Vec::new());
+        }
+        if !steps[index].contains(&press.key.as_str()) {
+            steps[index].push(&press.key);
+        }
+    }
+
+    let mut text = String::new();
+    for (index, step) in steps.iter().enumerate() {
+        if index > 0 && index % STEPS_PER_LINE == 0 {
+            text.push('\n');
+        }
+        match step.len() {
+            0 => text.push('-'),
+            1 => text.push_str(step[0]),
+            _ => {
+                text.push('[');
+                text.push_str(&step.concat());
+                text.push(']');
+            }
+        }
+    }
+    text.push('\n');
+
+    std::fs::write(path, text).map_err(|e| format!("Failed to write key sheet: {}", e))?;
+    Ok(presses.len())
+}
@@ -2,298 +2,1833 @@
 
 use std::sync::{Arc, Mutex};
 use std::thread;
-use tauri::{AppHandle, Emitter, State, Window};
+use tauri::{AppHandle, Emitter, Manager, State, Window};
 use serde::{Serialize, Deserialize};
 use windows::Win32::UI::Input::KeyboardAndMouse::{
-    RegisterHotKey, MOD_NOREPEAT, VK_END, VK_F9, VK_F10, VK_F11, VK_F12,
+    GetAsyncKeyState, RegisterHotKey, HOT_KEY_MODIFIERS, MOD_CONTROL, MOD_NOREPEAT, MOD_SHIFT, VIRTUAL_KEY,
+    VK_CONTROL, VK_END, VK_F8, VK_F9, VK_F10, VK_F11, VK_F12, VK_NEXT, VK_PRIOR, VK_SHIFT,
 };
 use windows::Win32::UI::WindowsAndMessaging::{
     GetMessageW, SetWindowsHookExW, CallNextHookEx,
     MSG, WM_HOTKEY, WM_KEYDOWN, WM_SYSKEYDOWN, HHOOK, KBDLLHOOKSTRUCT, WH_KEYBOARD_LL,
 };
-use windows::Win32::Foundation::LPARAM;
+use windows::Win32::Foundation::{CloseHandle, HANDLE, LPARAM};
+use windows::Win32::Security::{GetTokenInformation, TokenElevation, TOKEN_ELEVATION, TOKEN_QUERY};
+use windows::Win32::System::Threading::{GetCurrentProcess, OpenProcessToken};
+
+// Global app handle for the low-level hook callback, set once before the hook is installed
+static GLOBAL_APP_HANDLE: std::sync::OnceLock<AppHandle> = std::sync::OnceLock::new();
+
+/// Report a failure from a background thread: logged, and sent to the UI as `event`
+/// (`playback-error` or `hotkey-error`) once the app handle is set
+fn report_error(event: &str, message: &str) {
+    eprintln!("{}", message);
+    if let Some(app_handle) = GLOBAL_APP_HANDLE.get() {
+        let _ = app_handle.emit(event, message);
+    }
+}
+
+mod midi;
+mod keyboard;
+mod state;
+mod settings;
+mod scanner;
+mod cli;
+mod keysheet;
+mod metadata;
+mod shuffle;
+mod midi_out;
+mod hotkeys;
+mod pack;
+
+use state::{AppState, PlaybackState};
+
+#[derive(Debug, Serialize, Deserialize)]
+struct MidiFile {
+    name: String,
+    path: String,
+    duration: f64,
+    format_note: Option<String>,
+    // False if the file couldn't be parsed; `error` says why
+    parse_ok: bool,
+    error: Option<String>,
+    // User tags/rating/notes, filled in by load_midi_files
+    metadata: Option<metadata::FileMetadata>,
+    // Library folder the file was found in
+    library: String,
+}
+
+impl MidiFile {
+    // Build file info for a .mid or .txt song on disk
+    fn from_path(path: &std::path::Path) -> Self {
+        let name = path.file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("Unknown")
+            .to_string();
+        let path_string = path.to_string_lossy().to_string();
+
+        // Get actual duration from MIDI file, keeping broken files listed but flagged
+        let (duration, error) = match midi::get_midi_duration(&path_string) {
+            Ok(duration) => (duration, None),
+            Err(e) => (0.0, Some(e)),
+        };
+        let format_note = midi::get_format_note(&path_string);
+        let library = path.parent()
+            .map(|dir| dir.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        MidiFile {
+            name,
+            path: path_string,
+            duration,
+            format_note,
+            parse_ok: error.is_none(),
+            error,
+            metadata: None,
+            library,
+        }
+    }
+}
+
+// Songs the album can hold: MIDI files and text key sheets
+fn is_song_file(path: &std::path::Path) -> bool {
+    matches!(
+        path.extension().and_then(|s| s.to_str()).map(|ext| ext.to_lowercase()).as_deref(),
+        Some("mid") | Some("txt")
+    )
+}
+
+/// Sort order for the album file list
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+enum SortMode {
+    NameAsc,
+    NameDesc,
+    DurationAsc,
+    DurationDesc,
+    DateAdded,
+    Custom,
+}
+
+fn sort_midi_files(files: &mut [MidiFile], sort: SortMode, custom_order: &[String]) {
+    match sort {
+        SortMode::NameAsc => files.sort_by_key(|f| f.name.to_lowercase()),
+        SortMode::NameDesc => {
+            files.sort_by_key(|f| f.name.to_lowercase());
+            files.reverse();
+        }
+        SortMode::DurationAsc => files.sort_by(|a, b| a.duration.total_cmp(&b.duration)),
+        SortMode::DurationDesc => files.sort_by(|a, b| b.duration.total_cmp(&a.duration)),
+        SortMode::DateAdded => files.sort_by_cached_key(|f| {
+            std::fs::metadata(&f.path)
+                .and_then(|m| m.modified())
+                .unwrap_or(std::time::SystemTime::UNIX_EPOCH)
+        }),
+        SortMode::Custom => {
+            // Files in the saved order come first, anything new goes after by name
+            files.sort_by_cached_key(|f| {
+                let position = custom_order.iter()
+                    .position(|p| *p == f.path)
+                    .unwrap_or(usize::MAX);
+                (position, f.name.to_lowercase())
+            });
+        }
+    }
+}
+
+// Hotkey IDs
+const HOTKEY_PAUSE_RESUME: i32 = 1;
+const HOTKEY_STOP_END: i32 = 2;
+const HOTKEY_STOP_F12: i32 = 3;
+const HOTKEY_PREV_F10: i32 = 4;
+const HOTKEY_NEXT_F11: i32 = 5;
+const HOTKEY_OCTAVE_UP: i32 = 6;
+const HOTKEY_OCTAVE_DOWN: i32 = 7;
+const HOTKEY_PANIC: i32 = 8;
+const HOTKEY_STEP_F8: i32 = 9;
+
+// Load MIDI files from every library folder
+#[tauri::command]
+async fn load_midi_files(
+    sort: Option<SortMode>,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<Vec<MidiFile>, String> {
+    let libraries = library_dirs(&state.lock().unwrap())?;
+
+    let mut files = Vec::new();
+
+    for library in libraries {
+        if !library.exists() {
+            continue;
+        }
+        // One unreadable library shouldn't hide the others
+        let entries = match std::fs::read_dir(&library) {
+            Ok(entries) => entries,
+            Err(e) => {
+                eprintln!("Failed to read library {}: {}", library.display(), e);
+                continue;
+            }
+        };
+
+        for entry in entries {
+            if let Ok(entry) = entry {
+                let path = entry.path();
+                if is_song_file(&path) {
+                    files.push(MidiFile::from_path(&path));
+                }
+            }
+        }
+    }
+
+    let app_state = state.lock().unwrap();
+    for file in &mut files {
+        file.metadata = app_state.get_file_metadata(&file.path);
+    }
+
+    if let Some(sort) = sort {
+        sort_midi_files(&mut files, sort, &app_state.get_album_order());
+    }
+
+    Ok(files)
+}
+
+#[tauri::command]
+async fn play_midi(
+    path: String,
+    sequence: Option<usize>,
+    state: State<'_, Arc<Mutex<AppState>>>,
+    window: Window
+) -> Result<(), String> {
+    let mut app_state = state.lock().unwrap();
+    let focused = check_focus_policy(&app_state)?;
+    app_state.stop_playback();
+    app_state.load_midi(&path, sequence.unwrap_or(0))?;
+    start_loaded_song(app_state, focused, state.inner(), window, 0)
+}
+
+// Play a file after a count-in of `delay_ms` (playback-starting counts down the ms left),
+// or starting `-delay_ms` into the song when negative, e.g. to line up with a backing track
+#[tauri::command]
+async fn play_midi_delayed(
+    path: String,
+    delay_ms: i64,
+    sequence: Option<usize>,
+    state: State<'_, Arc<Mutex<AppState>>>,
+    window: Window
+) -> Result<(), String> {
+    let mut app_state = state.lock().unwrap();
+    let focused = check_focus_policy(&app_state)?;
+    app_state.stop_playback();
+    app_state.load_midi(&path, sequence.unwrap_or(0))?;
+    start_loaded_song(app_state, focused, state.inner(), window, delay_ms)
+}
+
+// Play two files together as one song (experimental); returns a warning if the
+// combined parts have more notes at once than can be played cleanly
+#[tauri::command]
+async fn play_duet(
+    path_a: String,
+    path_b: String,
+    offset_ms: Option<u64>,
+    state: State<'_, Arc<Mutex<AppState>>>,
+    window: Window
+) -> Result<Option<String>, String> {
+    let mut app_state = state.lock().unwrap();
+    let focused = check_focus_policy(&app_state)?;
+    app_state.stop_playback();
+    let warning = app_state.load_duet(&path_a, &path_b, offset_ms.unwrap_or(0))?;
+    start_loaded_song(app_state, focused, state.inner(), window, 0)?;
+    Ok(warning)
+}
+
+// Load a song without playing it, so the UI can confirm or override the auto-transpose first
+#[tauri::command]
+async fn load_song(
+    path: String,
+    sequence: Option<usize>,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<midi::TransposeDetection, String> {
+    let mut app_state = state.lock().unwrap();
+    app_state.stop_playback();
+    app_state.load_midi(&path, sequence.unwrap_or(0))?;
+    app_state.get_transpose_detection()
+}
+
+#[tauri::command]
+async fn get_transpose_detection(
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<midi::TransposeDetection, String> {
+    state.lock().unwrap().get_transpose_detection()
+}
+
+// Play the song already loaded by load_song
+#[tauri::command]
+async fn play_loaded(
+    state: State<'_, Arc<Mutex<AppState>>>,
+    window: Window
+) -> Result<(), String> {
+    let mut app_state = state.lock().unwrap();
+    let focused = check_focus_policy(&app_state)?;
+    app_state.stop_playback();
+    start_loaded_song(app_state, focused, state.inner(), window, 0)
+}
+
+// Whether the game is focused, or an error if the policy refuses to play without focus
+fn check_focus_policy(app_state: &AppState) -> Result<bool, String> {
+    let focused = keyboard::is_black_desert_focused().unwrap_or(true);
+    if app_state.get_on_not_focused() == settings::NotFocusedPolicy::Refuse && !focused {
+        return Err("Game window is not focused".to_string());
+    }
+    Ok(focused)
+}
+
+// Start the loaded song following the not-focused policy, then hand focus to the game
+fn start_loaded_song(
+    mut app_state: std::sync::MutexGuard<'_, AppState>,
+    focused: bool,
+    state: &Arc<Mutex<AppState>>,
+    window: Window,
+    delay_ms: i64
+) -> Result<(), String> {
+    // Warn the UI when a noticeable part of the song has to be octave-folded
+    if let Some(report) = app_state.get_range_report() {
+        if report.folded_notes > 0 {
+            let _ = window.emit("range-warning", &report);
+        }
+    }
+
+    // Waiting for focus takes the place of a count-in
+    if app_state.get_on_not_focused() == settings::NotFocusedPolicy::Pause && !focused {
+        app_state.start_playback_paused(window.clone(), delay_ms)?;
+        drop(app_state);
+        let _ = window.emit("focus-lost", ());
+        resume_when_game_focused(Arc::clone(state), window);
+        return Ok(());
+    }
+
+    if delay_ms == 0 {
+        app_state.start_playback(window)?;
+    } else {
+        app_state.start_playback_delayed(window, delay_ms)?;
+    }
+    let focus_mode = app_state.get_auto_focus_mode();
+    let focus_delay = app_state.get_focus_delay();
+    drop(app_state);
+
+    auto_focus_game(focus_mode, focus_delay);
+    Ok(())
+}
+
+// Raise the game window after a start, as far as the auto-focus mode allows
+fn auto_focus_game(mode: settings::AutoFocusMode, focus_delay: std::time::Duration) {
+    if mode == settings::AutoFocusMode::Never {
+        return;
+    }
+    if !focus_delay.is_zero() {
+        std::thread::sleep(focus_delay);
+    }
+    if mode == settings::AutoFocusMode::IfNotFocused && keyboard::is_black_desert_focused().unwrap_or(false) {
+        return;
+    }
+    let _ = keyboard::focus_black_desert_window();
+}
+
+// How often a paused start checks whether the game got focus
+const AWAIT_FOCUS_POLL_MS: u64 = 250;
+
+/// Resume a playback started paused once the game window is focused.
+/// Gives up if playback is stopped or resumed by hand in the meantime.
+fn resume_when_game_focused(state: Arc<Mutex<AppState>>, window: Window) {
+    thread::spawn(move || loop {
+        thread::sleep(std::time::Duration::from_millis(AWAIT_FOCUS_POLL_MS));
+        if !keyboard::is_black_desert_focused().unwrap_or(true) {
+            let app_state = state.lock().unwrap();
+            if !app_state.is_playing() || !app_state.get_playback_state().is_paused {
+                break;
+            }
+            continue;
+        }
+        if state.lock().unwrap().resume_if_paused() {
+            let _ = window.emit("focus-regained", ());
+        }
+        break;
+    });
+}
+
+// Default length of a file list preview
+const DEFAULT_PREVIEW_SECONDS: f64 = 10.0;
+
+// Audition the start of a file without loading it as the current song
+#[tauri::command]
+async fn preview_midi(
+    path: String,
+    seconds: Option<f64>,
+    state: State<'_, Arc<Mutex<AppState>>>,
+    window: Window
+) -> Result<(), String> {
+    let mut app_state = state.lock().unwrap();
+    app_state.preview_midi(&path, seconds.unwrap_or(DEFAULT_PREVIEW_SECONDS), midi::PreviewSink(window))?;
+    let focus_mode = app_state.get_auto_focus_mode();
+    let focus_delay = app_state.get_focus_delay();
+    drop(app_state);
+
+    auto_focus_game(focus_mode, focus_delay);
+    Ok(())
+}
+
+#[tauri::command]
+async fn stop_preview(state: State<'_, Arc<Mutex<AppState>>>) -> Result<(), String> {
+    state.lock().unwrap().stop_preview();
+    Ok(())
+}
+
+// Load a song for manual stepping instead of timed playback
+#[tauri::command]
+async fn start_manual_step(
+    path: String,
+    sequence: Option<usize>,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<PlaybackState, String> {
+    let mut app_state = state.lock().unwrap();
+    app_state.stop_playback();
+    app_state.load_midi(&path, sequence.unwrap_or(0))?;
+    Ok(app_state.get_playback_state())
+}
+
+#[tauri::command]
+async fn step_next_note(
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<PlaybackState, String> {
+    let mut app_state = state.lock().unwrap();
+    app_state.step_next_note()?;
+    Ok(app_state.get_playback_state())
+}
+
+#[tauri::command]
+async fn reset_manual_step(
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<PlaybackState, String> {
+    let mut app_state = state.lock().unwrap();
+    app_state.reset_manual_step();
+    Ok(app_state.get_playback_state())
+}
+
+#[tauri::command]
+async fn pause_resume(
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<PlaybackState, String> {
+    let mut app_state = state.lock().unwrap();
+    app_state.toggle_pause();
+    let playback_state = app_state.get_playback_state();
+    Ok(playback_state)
+}
+
+#[tauri::command]
+async fn stop_playback(
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<(), String> {
+    let mut app_state = state.lock().unwrap();
+    app_state.stop_playback();
+    Ok(())
+}
+
+// Last resort when playback stops sending heartbeats
+#[tauri::command]
+async fn force_stop(
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<(), String> {
+    state.lock().unwrap().force_stop();
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_playback_status(
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<PlaybackState, String> {
+    let app_state = state.lock().unwrap();
+    Ok(app_state.get_playback_state())
+}
+
+#[tauri::command]
+async fn set_loop_mode(
+    enabled: bool,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<(), String> {
+    let mut app_state = state.lock().unwrap();
+    app_state.set_loop_mode(enabled);
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_loop_count(
+    count: u32,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<(), String> {
+    let mut app_state = state.lock().unwrap();
+    app_state.set_loop_count(count);
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_loop_gap_ms(
+    gap_ms: u64,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<(), String> {
+    let mut app_state = state.lock().unwrap();
+    app_state.set_loop_gap_ms(gap_ms);
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_seamless_loop(
+    enabled: bool,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<(), String> {
+    let mut app_state = state.lock().unwrap();
+    app_state.set_seamless_loop(enabled);
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_autoplay_next(
+    enabled: bool,
+    gap_ms: Option<u64>,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<(), String> {
+    let mut app_state = state.lock().unwrap();
+    app_state.set_autoplay_next(enabled, gap_ms)
+}
+
+#[tauri::command]
+async fn get_autoplay_next(
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<state::AutoplaySettings, String> {
+    let app_state = state.lock().unwrap();
+    Ok(app_state.get_autoplay_next())
+}
+
+#[tauri::command]
+async fn set_remember_last_song(
+    enabled: bool,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<(), String> {
+    state.lock().unwrap().set_remember_last_song(enabled)
+}
+
+// Where the last session left off, or None (nothing saved, turned off, or the file is gone)
+#[tauri::command]
+async fn get_resume_state(
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<Option<state::ResumeState>, String> {
+    Ok(state.lock().unwrap().get_resume_state())
+}
+
+// Play the last session's song from where it left off; returns the start position
+#[tauri::command]
+async fn resume_last(
+    state: State<'_, Arc<Mutex<AppState>>>,
+    window: Window
+) -> Result<f64, String> {
+    let mut app_state = state.lock().unwrap();
+    let focused = check_focus_policy(&app_state)?;
+    let position = app_state.load_resume_point()?;
+    start_loaded_song(app_state, focused, state.inner(), window, 0)?;
+    Ok(position)
+}
+
+#[tauri::command]
+async fn set_shuffle(
+    enabled: bool,
+    current: usize,
+    count: usize,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<(), String> {
+    let mut app_state = state.lock().unwrap();
+    app_state.set_shuffle(enabled, current, count);
+    Ok(())
+}
+
+#[tauri::command]
+async fn next_track_index(
+    current: usize,
+    count: usize,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<usize, String> {
+    let mut app_state = state.lock().unwrap();
+    Ok(app_state.next_track_index(current, count))
+}
+
+#[tauri::command]
+async fn previous_track_index(
+    current: usize,
+    count: usize,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<usize, String> {
+    let mut app_state = state.lock().unwrap();
+    Ok(app_state.previous_track_index(current, count))
+}
+
+#[tauri::command]
+async fn set_latency_offset_ms(
+    offset_ms: i64,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<(), String> {
+    let mut app_state = state.lock().unwrap();
+    app_state.set_latency_offset(offset_ms)?;
+    println!("Latency offset set to: {}ms", app_state.get_latency_offset());
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_latency_offset_ms(
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<i64, String> {
+    let app_state = state.lock().unwrap();
+    Ok(app_state.get_latency_offset())
+}
+
+#[tauri::command]
+async fn set_practice_ramp(
+    start: f64,
+    step: f64,
+    target: f64,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<(), String> {
+    state.lock().unwrap().set_practice_ramp(start, step, target)?;
+    println!("Practice ramp: {}x +{}x per loop up to {}x", start, step, target);
+    Ok(())
+}
+
+#[tauri::command]
+async fn clear_practice_ramp(state: State<'_, Arc<Mutex<AppState>>>) -> Result<(), String> {
+    state.lock().unwrap().clear_practice_ramp();
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_humanize(
+    amount_ms: u64,
+    seed: Option<u64>,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<(), String> {
+    let mut app_state = state.lock().unwrap();
+    app_state.set_humanize(amount_ms, seed);
+    println!("Humanize set to: +-{}ms", amount_ms);
+    Ok(())
+}
+
+// Reduce chords to one note. Root finds the chord's root from its intervals (so an inverted
+// chord keeps its root rather than the bass note) and falls back to the lowest note; use
+// Bottom for simply the lowest note
+#[tauri::command]
+async fn set_chord_reduction(
+    mode: midi::ChordMode,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<(), String> {
+    let mut app_state = state.lock().unwrap();
+    app_state.set_chord_reduction(mode);
+    println!("Chord reduction set to: {:?}", mode);
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_min_note_duration_ms(
+    min_ms: u64,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<(), String> {
+    let mut app_state = state.lock().unwrap();
+    app_state.set_min_note_duration_ms(min_ms);
+    println!("Minimum note duration set to: {}ms", min_ms);
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_min_channel_volume(
+    min_volume: u8,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<(), String> {
+    let mut app_state = state.lock().unwrap();
+    app_state.set_min_channel_volume(min_volume);
+    println!("Minimum channel volume set to: {}", min_volume);
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_time_quantize(
+    grid_ms: u64,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<(), String> {
+    let mut app_state = state.lock().unwrap();
+    app_state.set_time_quantize(grid_ms);
+    println!("Time quantize set to: {}ms", grid_ms);
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_swing(
+    ratio: f64,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<(), String> {
+    let mut app_state = state.lock().unwrap();
+    app_state.set_swing(ratio)?;
+    println!("Swing ratio set to: {:.2}", ratio);
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_progress_interval(
+    interval_ms: u64,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<(), String> {
+    let mut app_state = state.lock().unwrap();
+    app_state.set_progress_interval(interval_ms);
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_key_sheet_speed(
+    notes_per_second: f64,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<(), String> {
+    let mut app_state = state.lock().unwrap();
+    app_state.set_key_sheet_speed(notes_per_second)
+}
+
+#[tauri::command]
+async fn set_register_weight(
+    weight: u32,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<(), String> {
+    let mut app_state = state.lock().unwrap();
+    app_state.set_register_weight(weight)
+}
+
+#[tauri::command]
+async fn get_register_weight() -> Result<u32, String> {
+    Ok(midi::get_register_weight())
+}
+
+// Escape hatch for a confusing mix of tunables; safe to call while playing
+#[tauri::command]
+async fn reset_settings(
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<(), String> {
+    let mut app_state = state.lock().unwrap();
+    app_state.reset_settings()?;
+    println!("All settings reset to defaults");
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_debug_logging(enabled: bool) -> Result<(), String> {
+    midi::set_debug_logging(enabled);
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_call_and_response(
+    enabled: bool,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<(), String> {
+    let mut app_state = state.lock().unwrap();
+    app_state.set_call_and_response(enabled);
+    Ok(())
+}
+
+#[tauri::command]
+async fn pause_at_markers(
+    enabled: bool,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<(), String> {
+    let mut app_state = state.lock().unwrap();
+    app_state.set_pause_at_markers(enabled);
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_trim_leading_silence(
+    enabled: bool,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<(), String> {
+    let mut app_state = state.lock().unwrap();
+    app_state.set_trim_leading_silence(enabled);
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_markers(
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<Vec<midi::Marker>, String> {
+    let app_state = state.lock().unwrap();
+    Ok(app_state.get_markers())
+}
+
+#[tauri::command]
+async fn get_last_playback_stats(
+    window: Window,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<midi::PlaybackStats, String> {
+    let stats = state.lock().unwrap().get_playback_stats();
+    let _ = window.emit("playback-stats", stats);
+    Ok(stats)
+}
+
+// Returns the overrides that were kept (unknown keys are dropped)
+#[tauri::command]
+async fn set_note_overrides(
+    map: std::collections::HashMap<u8, String>,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<std::collections::HashMap<u8, String>, String> {
+    state.lock().unwrap().set_note_overrides(map)
+}
+
+#[tauri::command]
+async fn get_note_overrides(
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<std::collections::HashMap<u8, String>, String> {
+    Ok(state.lock().unwrap().get_note_overrides())
+}
+
+#[tauri::command]
+async fn bass_tremolo(
+    enabled: bool,
+    rate_ms: u64,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<(), String> {
+    let mut app_state = state.lock().unwrap();
+    app_state.set_bass_tremolo(enabled, rate_ms);
+    println!("Bass tremolo {} ({}ms)", if enabled { "enabled" } else { "disabled" }, rate_ms);
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_max_nps(
+    rate: u32,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<(), String> {
+    let mut app_state = state.lock().unwrap();
+    app_state.set_max_nps(rate);
+    if rate == 0 {
+        println!("Notes per second cap disabled");
+    } else {
+        println!("Notes per second capped at {}", rate);
+    }
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_detache(
+    enabled: bool,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<(), String> {
+    let mut app_state = state.lock().unwrap();
+    app_state.set_detache(enabled);
+    println!("Articulation: {}", if enabled { "detache" } else { "legato" });
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_note_off_delay_ms(
+    delay_ms: u64,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<(), String> {
+    let mut app_state = state.lock().unwrap();
+    app_state.set_note_off_delay_ms(delay_ms);
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_pitch_bend(
+    enabled: bool,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<(), String> {
+    let mut app_state = state.lock().unwrap();
+    app_state.set_pitch_bend(enabled);
+    println!("Pitch bend {}", if enabled { "enabled" } else { "disabled" });
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_pause_on_focus_loss(
+    enabled: bool,
+    resume_on_focus: Option<bool>,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<(), String> {
+    let mut app_state = state.lock().unwrap();
+    app_state.set_pause_on_focus_loss(enabled, resume_on_focus.unwrap_or(false));
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_focus_delay(
+    delay_ms: u64,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<(), String> {
+    let mut app_state = state.lock().unwrap();
+    app_state.set_focus_delay(delay_ms);
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_note_mode(
+    mode: midi::NoteMode,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<(), String> {
+    let mut app_state = state.lock().unwrap();
+    app_state.choose_note_mode(mode)?;
+    println!("Note mode set to: {:?}", mode);
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_song_preferences(
+    enabled: bool,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<(), String> {
+    let mut app_state = state.lock().unwrap();
+    app_state.set_song_preferences(enabled)
+}
+
+#[tauri::command]
+async fn get_song_preferences(
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<bool, String> {
+    let app_state = state.lock().unwrap();
+    Ok(app_state.song_preferences_enabled())
+}
+
+#[tauri::command]
+async fn get_note_mode(
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<midi::NoteMode, String> {
+    let app_state = state.lock().unwrap();
+    Ok(app_state.get_note_mode())
+}
+
+#[tauri::command]
+async fn set_output_mode(
+    mode: midi_out::OutputMode,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<(), String> {
+    let mut app_state = state.lock().unwrap();
+    app_state.set_output_mode(mode);
+    println!("Output mode set to: {:?}", mode);
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_output_mode(
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<midi_out::OutputMode, String> {
+    let app_state = state.lock().unwrap();
+    Ok(app_state.get_output_mode())
+}
+
+#[tauri::command]
+async fn list_midi_outputs() -> Result<Vec<String>, String> {
+    Ok(midi_out::list_midi_outputs())
+}
+
+#[tauri::command]
+async fn set_midi_output(index: u32) -> Result<String, String> {
+    let name = midi_out::set_midi_output(index)?;
+    println!("MIDI output set to: {}", name);
+    Ok(name)
+}
+
+#[tauri::command]
+async fn set_octave_shift(
+    shift: i8,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<(), String> {
+    let mut app_state = state.lock().unwrap();
+    app_state.set_octave_shift(shift);
+    println!("Octave shift set to: {}", shift);
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_octave_shifts(
+    low: i8,
+    mid: i8,
+    high: i8,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<(), String> {
+    let mut app_state = state.lock().unwrap();
+    app_state.set_octave_shifts(low, mid, high);
+    println!("Octave band shifts set to: {:?}", app_state.get_octave_shifts());
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_octave_shift(
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<i8, String> {
+    let app_state = state.lock().unwrap();
+    Ok(app_state.get_octave_shift())
+}
+
+#[tauri::command]
+async fn octave_up(
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<PlaybackState, String> {
+    let mut app_state = state.lock().unwrap();
+    app_state.nudge_octave_shift(1);
+    println!("Octave shift set to: {}", app_state.get_octave_shift());
+    Ok(app_state.get_playback_state())
+}
+
+#[tauri::command]
+async fn octave_down(
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<PlaybackState, String> {
+    let mut app_state = state.lock().unwrap();
+    app_state.nudge_octave_shift(-1);
+    println!("Octave shift set to: {}", app_state.get_octave_shift());
+    Ok(app_state.get_playback_state())
+}
+
+#[tauri::command]
+async fn set_transpose(
+    semitones: i32,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<PlaybackState, String> {
+    let mut app_state = state.lock().unwrap();
+    app_state.set_transpose(semitones)?;
+    println!("Transpose set to: {}", app_state.get_transpose());
+    Ok(app_state.get_playback_state())
+}
+
+#[tauri::command]
+async fn nudge_transpose(
+    delta: i32,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<PlaybackState, String> {
+    let mut app_state = state.lock().unwrap();
+    app_state.nudge_transpose(delta);
+    println!("Live transpose set to: {}", app_state.get_live_transpose());
+    Ok(app_state.get_playback_state())
+}
+
+#[tauri::command]
+async fn clear_transpose_override(
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<PlaybackState, String> {
+    let mut app_state = state.lock().unwrap();
+    app_state.clear_transpose_override()?;
+    Ok(app_state.get_playback_state())
+}
+
+#[tauri::command]
+async fn list_sequences(path: String) -> Result<Vec<midi::SequenceInfo>, String> {
+    midi::list_sequences(&path)
+}
+
+#[tauri::command]
+async fn list_tracks(path: String) -> Result<Vec<midi::TrackInfo>, String> {
+    midi::list_tracks(&path)
+}
+
+#[tauri::command]
+async fn set_active_tracks(
+    indices: Vec<usize>,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<(), String> {
+    let mut app_state = state.lock().unwrap();
+    app_state.set_active_tracks(indices)
+}
+
+#[tauri::command]
+async fn check_note_range(
+    path: String,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<Option<midi::RangeReport>, String> {
+    let app_state = state.lock().unwrap();
+    app_state.preview_range_report(&path)
+}
+
+#[tauri::command]
+async fn analyze_midi(path: String) -> Result<midi::MidiAnalysis, String> {
+    midi::analyze_midi(&path)
+}
+
+#[tauri::command]
+async fn playability_report(
+    path: String,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<Vec<midi::ProblemSegment>, String> {
+    let app_state = state.lock().unwrap();
+    app_state.playability_report(&path)
+}
+
+// Rests at least this long split sections when the caller doesn't say
+const DEFAULT_SECTION_GAP_MS: u64 = 2000;
+
+#[tauri::command]
+async fn detect_sections(path: String, min_gap_ms: Option<u64>) -> Result<Vec<midi::Section>, String> {
+    midi::detect_sections(&path, min_gap_ms.unwrap_or(DEFAULT_SECTION_GAP_MS))
+}
+
+#[tauri::command]
+async fn auto_fit_octave(
+    path: String,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<midi::OctaveFit, String> {
+    let fit = midi::auto_fit_octave(&path)?;
+    let mut app_state = state.lock().unwrap();
+    app_state.set_octave_shift(fit.octave_shift);
+    println!("Octave shift auto-fit to: {}", fit.octave_shift);
+    Ok(fit)
+}
+
+#[tauri::command]
+async fn get_note_key_map(
+    note_mode: midi::NoteMode,
+    transpose: i32,
+    octave_shift: i8
+) -> Result<Vec<(u8, String)>, String> {
+    Ok(midi::note_key_map(note_mode, transpose, octave_shift))
+}
+
+#[tauri::command]
+async fn set_on_not_focused(
+    policy: settings::NotFocusedPolicy,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<(), String> {
+    state.lock().unwrap().set_on_not_focused(policy)
+}
+
+#[tauri::command]
+async fn get_on_not_focused(state: State<'_, Arc<Mutex<AppState>>>) -> Result<settings::NotFocusedPolicy, String> {
+    Ok(state.lock().unwrap().get_on_not_focused())
+}
+
+#[tauri::command]
+async fn set_auto_focus_mode(
+    mode: settings::AutoFocusMode,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<(), String> {
+    state.lock().unwrap().set_auto_focus_mode(mode)
+}
+
+#[tauri::command]
+async fn get_auto_focus_mode(state: State<'_, Arc<Mutex<AppState>>>) -> Result<settings::AutoFocusMode, String> {
+    Ok(state.lock().unwrap().get_auto_focus_mode())
+}
+
+// Capture every key the app presses until stop_recording
+#[tauri::command]
+async fn start_recording() -> Result<(), String> {
+    keyboard::start_recording();
+    Ok(())
+}
+
+// Save what was pressed since start_recording as a .txt key sheet; returns the press count
+#[tauri::command]
+async fn stop_recording(path: String) -> Result<usize, String> {
+    // Check before ending the recording, so a bad path doesn't lose it
+    if !keysheet::is_key_sheet(&path) {
+        return Err("Recordings are saved as .txt key sheets".to_string());
+    }
+    let keys = keyboard::stop_recording().ok_or("Not recording")?;
+    keysheet::write_key_sheet(&path, &keys)
+}
+
+#[tauri::command]
+async fn is_recording() -> Result<bool, String> {
+    Ok(keyboard::is_recording())
+}
+
+// Echo keys to the UI instead of pressing them, for trying the app without the game
+#[tauri::command]
+async fn test_mode(enabled: bool) -> Result<(), String> {
+    keyboard::set_test_mode(enabled);
+    Ok(())
+}
+
+#[tauri::command]
+async fn is_game_focused() -> Result<bool, String> {
+    keyboard::is_black_desert_focused().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn test_all_keys() -> Result<(), String> {
+    // Focus game window first
+    let _ = keyboard::focus_black_desert_window();
+    std::thread::sleep(std::time::Duration::from_millis(500));
+
+    // Test every key of the active instrument, lowest row first
+    for key in midi::active_profile().keys() {
+        keyboard::key_down(key);
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        keyboard::key_up(key);
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_instrument_profile(
+    name: String,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<(), String> {
+    let mut app_state = state.lock().unwrap();
+    app_state.set_instrument_profile(&name)
+}
+
+#[tauri::command]
+async fn set_hand_split(
+    split: Option<midi::HandSplit>,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<(), String> {
+    let mut app_state = state.lock().unwrap();
+    app_state.set_hand_split(split)
+}
+
+#[tauri::command]
+async fn list_split_keys() -> Result<Vec<String>, String> {
+    Ok(midi::SPLIT_KEYS.iter().map(|key| key.to_string()).collect())
+}
+
+#[tauri::command]
+async fn list_instrument_profiles() -> Result<Vec<String>, String> {
+    Ok(midi::INSTRUMENT_PROFILES.iter().map(|p| p.name.to_string()).collect())
+}
+
+#[tauri::command]
+async fn get_keyboard_error() -> Result<Option<String>, String> {
+    Ok(keyboard::keyboard_error())
+}
+
+#[tauri::command]
+async fn reinit_keyboard() -> Result<(), String> {
+    keyboard::reinit_keyboard()
+}
+
+#[tauri::command]
+async fn panic_release() -> Result<(), String> {
+    keyboard::panic_release();
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_interaction_mode(window: Window, interactive: bool) -> Result<(), String> {
+    window.set_ignore_cursor_events(!interactive).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[tauri::command]
+async fn focus_game_window() -> Result<(), String> {
+    keyboard::focus_black_desert_window().map_err(|e| e.to_string())
+}
+
+// The album folder next to the executable (may not exist yet)
+fn album_dir() -> Result<std::path::PathBuf, String> {
+    let exe_path = std::env::current_exe().map_err(|e| format!("Failed to locate executable: {}", e))?;
+    let exe_dir = exe_path.parent().ok_or("Failed to get executable directory")?;
+    Ok(exe_dir.join("album"))
+}
+
+// Folders songs are listed from: the configured libraries, or the album folder
+fn library_dirs(app_state: &AppState) -> Result<Vec<std::path::PathBuf>, String> {
+    let paths = app_state.get_library_paths();
+    if paths.is_empty() {
+        return Ok(vec![album_dir()?]);
+    }
+    Ok(paths.into_iter().map(std::path::PathBuf::from).collect())
+}
+
+// The library imports go into (may not exist yet)
+fn default_library_dir(app_state: &AppState) -> Result<std::path::PathBuf, String> {
+    let libraries = library_dirs(app_state)?;
+    app_state
+        .get_default_library()
+        .map(std::path::PathBuf::from)
+        .filter(|default| libraries.contains(default))
+        .or_else(|| libraries.into_iter().next())
+        .ok_or_else(|| "No library folder configured".to_string())
+}
+
+// The default library, created if it doesn't exist yet
+fn ensure_default_library(app_state: &AppState) -> Result<std::path::PathBuf, String> {
+    let library = default_library_dir(app_state)?;
+    if !library.exists() {
+        std::fs::create_dir_all(&library).map_err(|e| e.to_string())?;
+    }
+    Ok(library)
+}
+
+#[tauri::command]
+async fn get_album_path(state: State<'_, Arc<Mutex<AppState>>>) -> Result<String, String> {
+    Ok(default_library_dir(&state.lock().unwrap())?.to_string_lossy().to_string())
+}
+
+#[tauri::command]
+async fn set_library_paths(
+    paths: Vec<String>,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<(), String> {
+    state.lock().unwrap().set_library_paths(paths)
+}
+
+#[tauri::command]
+async fn get_library_paths(state: State<'_, Arc<Mutex<AppState>>>) -> Result<Vec<String>, String> {
+    let app_state = state.lock().unwrap();
+    Ok(library_dirs(&app_state)?
+        .into_iter()
+        .map(|dir| dir.to_string_lossy().to_string())
+        .collect())
+}
+
+// Pick which library imports go into; must be one of the library folders
+#[tauri::command]
+async fn set_default_library(
+    path: String,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<(), String> {
+    let mut app_state = state.lock().unwrap();
+    if !library_dirs(&app_state)?.contains(&std::path::PathBuf::from(&path)) {
+        return Err(format!("'{}' is not a library folder", path));
+    }
+    app_state.set_default_library(Some(path))
+}
+
+#[tauri::command]
+async fn open_album_folder(state: State<'_, Arc<Mutex<AppState>>>) -> Result<(), String> {
+    let album_path = ensure_default_library(&state.lock().unwrap())?;
+
+    #[cfg(target_os = "windows")]
+    let opener = "explorer";
+    #[cfg(target_os = "macos")]
+    let opener = "open";
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    let opener = "xdg-open";
+
+    std::process::Command::new(opener)
+        .arg(&album_path)
+        .spawn()
+        .map_err(|e| format!("Failed to open album folder: {}", e))?;
+    Ok(())
+}
+
+// Destination for a new file in the default library, rejecting duplicates
+fn album_import_destination(app_state: &AppState, filename: &std::ffi::OsStr) -> Result<std::path::PathBuf, String> {
+    let album_path = ensure_default_library(app_state)?;
+
+    let dest_path = album_path.join(filename);
+
+    // Check if file already exists
+    if dest_path.exists() {
+        return Err(format!("File '{}' already exists in album", filename.to_string_lossy()));
+    }
+
+    Ok(dest_path)
+}
+
+#[tauri::command]
+async fn get_file_metadata(
+    path: String,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<Option<metadata::FileMetadata>, String> {
+    let app_state = state.lock().unwrap();
+    Ok(app_state.get_file_metadata(&path))
+}
+
+#[tauri::command]
+async fn set_file_metadata(
+    path: String,
+    metadata: metadata::FileMetadata,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<(), String> {
+    let mut app_state = state.lock().unwrap();
+    app_state.set_file_metadata(&path, metadata)
+}
+
+#[tauri::command]
+async fn set_album_order(
+    paths: Vec<String>,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<(), String> {
+    let mut app_state = state.lock().unwrap();
+    app_state.set_album_order(paths)
+}
+
+#[tauri::command]
+async fn import_midi_from_bytes(
+    name: String,
+    data: Vec<u8>,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<MidiFile, String> {
+    // Reject anything that isn't a valid Standard MIDI File before touching the album
+    midly::Smf::parse(&data).map_err(|e| format!("Not a valid MIDI file: {}", e))?;
+
+    // Only allow a bare file name, always stored as .mid
+    let name = name.trim();
+    let stem = name.strip_suffix(".mid").unwrap_or(name);
+    let filename = format!("{}.mid", stem);
+    let filename_path = std::path::Path::new(&filename);
+    if stem.is_empty() || filename_path.file_name() != Some(filename_path.as_os_str()) {
+        return Err("Invalid filename".to_string());
+    }
+
+    let dest_path = album_import_destination(&state.lock().unwrap(), filename_path.as_os_str())?;
+    std::fs::write(&dest_path, &data).map_err(|e| format!("Failed to write file: {}", e))?;
 
-// Global app handle for low-level hook callback
-static mut GLOBAL_APP_HANDLE: Option<AppHandle> = None;
+    Ok(MidiFile::from_path(&dest_path))
+}
 
-mod midi;
-mod keyboard;
-mod state;
+#[tauri::command]
+async fn validate_midi(path: String) -> Result<midi::MidiValidation, String> {
+    midi::validate_midi(&path)
+}
 
-use state::{AppState, PlaybackState};
+#[tauri::command]
+async fn import_midi_file(
+    source_path: String,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<MidiFile, String> {
+    let source = std::path::Path::new(&source_path);
 
-#[derive(Debug, Serialize, Deserialize)]
-struct MidiFile {
-    name: String,
-    path: String,
-    duration: f64,
+    // Verify it's a .mid file
+    if source.extension().and_then(|s| s.to_str()) != Some("mid") {
+        return Err("File must be a .mid file".to_string());
+    }
+    // Refuse renamed non-MIDI files now rather than failing at play time
+    midi::validate_midi(&source_path)?;
+
+    // Get filename and create destination path
+    let filename = source.file_name().ok_or("Invalid filename")?;
+    let dest_path = album_import_destination(&state.lock().unwrap(), filename)?;
+
+    // Copy file to the default library
+    std::fs::copy(&source, &dest_path).map_err(|e| format!("Failed to copy file: {}", e))?;
+
+    // Get duration and return file info
+    Ok(MidiFile::from_path(&dest_path))
 }
 
-// Hotkey IDs
-const HOTKEY_PAUSE_RESUME: i32 = 1;
-const HOTKEY_STOP_END: i32 = 2;
-const HOTKEY_STOP_F12: i32 = 3;
-const HOTKEY_PREV_F10: i32 = 4;
-const HOTKEY_NEXT_F11: i32 = 5;
+/// Result of importing a zip song pack
+#[derive(Debug, Serialize)]
+struct PackImportSummary {
+    imported: Vec<MidiFile>,
+    // (entry name, reason)
+    skipped: Vec<(String, String)>,
+}
 
-// Load MIDI files from album folder
+// Import every MIDI file from a zip song pack, skipping anything invalid or already in the album
 #[tauri::command]
-async fn load_midi_files() -> Result<Vec<MidiFile>, String> {
-    let exe_path = std::env::current_exe().map_err(|e| e.to_string())?;
-    let exe_dir = exe_path.parent().ok_or("Failed to get executable directory")?;
-    let album_path = exe_dir.join("album");
+async fn import_midi_pack(
+    zip_path: String,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<PackImportSummary, String> {
+    let mut summary = PackImportSummary {
+        imported: Vec::new(),
+        skipped: Vec::new(),
+    };
+
+    for entry in pack::read_zip_entries(&zip_path)? {
+        // Packs often nest songs in folders; import them flat
+        let base_name = entry.name.rsplit(['/', '\\']).next().unwrap_or(&entry.name).to_string();
+        let path = std::path::Path::new(&base_name);
+        let is_midi = path
+            .extension()
+            .and_then(|s| s.to_str())
+            .map(|ext| ext.eq_ignore_ascii_case("mid") || ext.eq_ignore_ascii_case("midi"))
+            .unwrap_or(false);
+        if !is_midi {
+            summary.skipped.push((entry.name, "Not a MIDI file".to_string()));
+            continue;
+        }
 
-    let mut files = Vec::new();
+        let result = entry.data.and_then(|data| {
+            midly::Smf::parse(&data).map_err(|e| format!("Not a valid MIDI file: {}", e))?;
+            // The album only lists .mid files
+            let filename = path.with_extension("mid");
+            let dest_path = album_import_destination(&state.lock().unwrap(), filename.as_os_str())?;
+            std::fs::write(&dest_path, &data).map_err(|e| format!("Failed to write file: {}", e))?;
+            Ok(MidiFile::from_path(&dest_path))
+        });
+        match result {
+            Ok(file) => summary.imported.push(file),
+            Err(reason) => summary.skipped.push((entry.name, reason)),
+        }
+    }
+
+    Ok(summary)
+}
 
-    if album_path.exists() {
-        let entries = std::fs::read_dir(album_path).map_err(|e| e.to_string())?;
+// Resolve a path to a song file that must live directly inside a library folder
+fn resolve_album_file(app_state: &AppState, path: &str) -> Result<std::path::PathBuf, String> {
+    let libraries: Vec<std::path::PathBuf> = library_dirs(app_state)?
+        .iter()
+        .filter_map(|library| library.canonicalize().ok())
+        .collect();
 
-        for entry in entries {
-            if let Ok(entry) = entry {
-                let path = entry.path();
-                if path.extension().and_then(|s| s.to_str()) == Some("mid") {
-                    let name = path.file_stem()
-                        .and_then(|s| s.to_str())
-                        .unwrap_or("Unknown")
-                        .to_string();
-
-                    // Get actual duration from MIDI file
-                    let duration = midi::get_midi_duration(&path.to_string_lossy())
-                        .unwrap_or(0.0);
-
-                    files.push(MidiFile {
-                        name,
-                        path: path.to_string_lossy().to_string(),
-                        duration,
-                    });
-                }
-            }
-        }
+    let file_path = std::path::Path::new(path)
+        .canonicalize()
+        .map_err(|e| format!("File not found: {}", e))?;
+
+    // Reject anything outside the libraries (e.g. "..\" traversal)
+    if !file_path.parent().map(|parent| libraries.iter().any(|library| library == parent)).unwrap_or(false) {
+        return Err("File is not in a library folder".to_string());
     }
 
-    Ok(files)
+    if !is_song_file(&file_path) {
+        return Err("File must be a .mid or .txt file".to_string());
+    }
+
+    Ok(file_path)
+}
+
+// Canonical form of a path for comparisons (as given if it doesn't exist).
+// Windows paths are case-insensitive, so they're compared lowercased.
+fn normalize_path(path: &std::path::Path) -> std::path::PathBuf {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    #[cfg(target_os = "windows")]
+    let path = std::path::PathBuf::from(path.to_string_lossy().to_lowercase());
+    path
+}
+
+// Check whether the given file is the one currently playing
+fn is_current_file(app_state: &AppState, file_path: &std::path::Path) -> bool {
+    let playback_state = app_state.get_playback_state();
+    playback_state.is_playing && is_same_file(playback_state.current_file.as_deref(), file_path)
+}
+
+fn is_same_file(current: Option<&str>, file_path: &std::path::Path) -> bool {
+    current
+        .map(|current| normalize_path(std::path::Path::new(current)) == normalize_path(file_path))
+        .unwrap_or(false)
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+struct FilePlayingStatus {
+    playing: bool,
+    paused: bool,
 }
 
 #[tauri::command]
-async fn play_midi(
+async fn is_file_playing(
     path: String,
-    state: State<'_, Arc<Mutex<AppState>>>,
-    window: Window
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<FilePlayingStatus, String> {
+    let playback_state = state.lock().unwrap().get_playback_state();
+    let playing = playback_state.is_playing
+        && is_same_file(playback_state.current_file.as_deref(), std::path::Path::new(&path));
+    Ok(FilePlayingStatus {
+        playing,
+        paused: playing && playback_state.is_paused,
+    })
+}
+
+#[tauri::command]
+async fn delete_midi_file(
+    path: String,
+    state: State<'_, Arc<Mutex<AppState>>>
 ) -> Result<(), String> {
-    let mut app_state = state.lock().unwrap();
-    app_state.stop_playback();
-    app_state.load_midi(&path)?;
-    app_state.start_playback(window)?;
-    drop(app_state);
+    let file_path = resolve_album_file(&state.lock().unwrap(), &path)?;
 
-    std::thread::sleep(std::time::Duration::from_millis(100));
-    let _ = keyboard::focus_black_desert_window();
+    if is_current_file(&state.lock().unwrap(), &file_path) {
+        return Err("Cannot delete the file that is currently playing".to_string());
+    }
 
-    Ok(())
+    std::fs::remove_file(&file_path).map_err(|e| format!("Failed to delete file: {}", e))?;
+    state.lock().unwrap().move_file_metadata(&path, None)
 }
 
 #[tauri::command]
-async fn pause_resume(
+async fn rename_midi_file(
+    path: String,
+    new_name: String,
     state: State<'_, Arc<Mutex<AppState>>>
-) -> Result<PlaybackState, String> {
+) -> Result<MidiFile, String> {
+    let file_path = resolve_album_file(&state.lock().unwrap(), &path)?;
+
+    if is_current_file(&state.lock().unwrap(), &file_path) {
+        return Err("Cannot rename the file that is currently playing".to_string());
+    }
+
+    // Allow the name with or without extension, but never a path; the file type stays the same
+    let extension = file_path.extension().and_then(|s| s.to_str()).unwrap_or("mid").to_lowercase();
+    let new_name = new_name.trim();
+    let filename = if new_name.to_lowercase().ends_with(&format!(".{}", extension)) {
+        new_name.to_string()
+    } else {
+        format!("{}.{}", new_name, extension)
+    };
+    let new_filename = std::path::Path::new(&filename);
+    if new_name.is_empty() || new_filename.file_name() != Some(new_filename.as_os_str()) {
+        return Err("Invalid filename".to_string());
+    }
+
+    // Build from the original (non-canonical) path so it matches what load_midi_files returns
+    let dest_path = std::path::Path::new(&path).parent().ok_or("Invalid path")?.join(new_filename);
+    if dest_path.exists() {
+        return Err(format!("File '{}' already exists in album", filename));
+    }
+
+    std::fs::rename(&file_path, &dest_path).map_err(|e| format!("Failed to rename file: {}", e))?;
+
+    let mut file = MidiFile::from_path(&dest_path);
     let mut app_state = state.lock().unwrap();
-    app_state.toggle_pause();
-    let playback_state = app_state.get_playback_state();
-    Ok(playback_state)
+    app_state.move_file_metadata(&path, Some(&file.path))?;
+    file.metadata = app_state.get_file_metadata(&file.path);
+    Ok(file)
 }
 
 #[tauri::command]
-async fn stop_playback(
-    state: State<'_, Arc<Mutex<AppState>>>
-) -> Result<(), String> {
-    let mut app_state = state.lock().unwrap();
-    app_state.stop_playback();
-    Ok(())
+async fn scan_button_positions() -> Result<scanner::ScanResult, String> {
+    // Run off the async runtime so cancel_scan can be handled while scanning
+    tauri::async_runtime::spawn_blocking(scanner::scan_button_positions)
+        .await
+        .map_err(|e| e.to_string())?
 }
 
 #[tauri::command]
-async fn get_playback_status(
-    state: State<'_, Arc<Mutex<AppState>>>
-) -> Result<PlaybackState, String> {
-    let app_state = state.lock().unwrap();
-    Ok(app_state.get_playback_state())
+async fn capture_screenshot() -> Result<scanner::Screenshot, String> {
+    tauri::async_runtime::spawn_blocking(scanner::capture_screenshot)
+        .await
+        .map_err(|e| e.to_string())?
 }
 
 #[tauri::command]
-async fn set_loop_mode(
-    enabled: bool,
-    state: State<'_, Arc<Mutex<AppState>>>
-) -> Result<(), String> {
-    let mut app_state = state.lock().unwrap();
-    app_state.set_loop_mode(enabled);
-    Ok(())
+async fn scan_from_image(path: String) -> Result<scanner::ScanResult, String> {
+    tauri::async_runtime::spawn_blocking(move || scanner::scan_from_image(&path))
+        .await
+        .map_err(|e| e.to_string())?
 }
 
 #[tauri::command]
-async fn set_note_mode(
-    mode: midi::NoteMode,
+async fn auto_scan(settle_ms: Option<u64>) -> Result<scanner::ScanResult, String> {
+    let settle_ms = settle_ms.unwrap_or(scanner::AUTO_SCAN_SETTLE_MS);
+    tauri::async_runtime::spawn_blocking(move || scanner::auto_scan(settle_ms))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+async fn set_scan_params(
+    params: scanner::ScanParams,
     state: State<'_, Arc<Mutex<AppState>>>
 ) -> Result<(), String> {
     let mut app_state = state.lock().unwrap();
-    app_state.set_note_mode(mode);
-    println!("Note mode set to: {:?}", mode);
-    Ok(())
+    app_state.set_scan_params(params)
 }
 
 #[tauri::command]
-async fn get_note_mode(
-    state: State<'_, Arc<Mutex<AppState>>>
-) -> Result<midi::NoteMode, String> {
-    let app_state = state.lock().unwrap();
-    Ok(app_state.get_note_mode())
+async fn get_scan_params() -> Result<scanner::ScanParams, String> {
+    Ok(scanner::get_scan_params())
 }
 
 #[tauri::command]
-async fn set_octave_shift(
-    shift: i8,
+async fn set_scan_region(
+    x: i32,
+    y: i32,
+    w: i32,
+    h: i32,
     state: State<'_, Arc<Mutex<AppState>>>
 ) -> Result<(), String> {
     let mut app_state = state.lock().unwrap();
-    app_state.set_octave_shift(shift);
-    println!("Octave shift set to: {}", shift);
-    Ok(())
+    app_state.set_scan_region(Some(scanner::ScanRegion { x, y, width: w, height: h }))
 }
 
 #[tauri::command]
-async fn get_octave_shift(
+async fn clear_scan_region(
     state: State<'_, Arc<Mutex<AppState>>>
-) -> Result<i8, String> {
-    let app_state = state.lock().unwrap();
-    Ok(app_state.get_octave_shift())
+) -> Result<(), String> {
+    let mut app_state = state.lock().unwrap();
+    app_state.set_scan_region(None)
 }
 
 #[tauri::command]
-async fn is_game_focused() -> Result<bool, String> {
-    keyboard::is_black_desert_focused().map_err(|e| e.to_string())
+async fn get_scan_region() -> Result<Option<scanner::ScanRegion>, String> {
+    Ok(scanner::get_scan_region())
 }
 
 #[tauri::command]
-async fn test_all_keys() -> Result<(), String> {
-    // Focus game window first
-    let _ = keyboard::focus_black_desert_window();
-    std::thread::sleep(std::time::Duration::from_millis(500));
-
-    // Test all 21 keys: Low (Z-M), Mid (A-J), High (Q-U)
-    let keys = ["z", "x", "c", "v", "b", "n", "m", "a", "s", "d", "f", "g", "h", "j", "q", "w", "e", "r", "t", "y", "u"];
-    for key in keys {
-        keyboard::key_down(key);
-        std::thread::sleep(std::time::Duration::from_millis(100));
-        keyboard::key_up(key);
-        std::thread::sleep(std::time::Duration::from_millis(50));
-    }
-
+async fn cancel_scan() -> Result<(), String> {
+    scanner::cancel_scan();
     Ok(())
 }
 
 #[tauri::command]
-async fn set_interaction_mode(window: Window, interactive: bool) -> Result<(), String> {
-    window.set_ignore_cursor_events(!interactive).map_err(|e| e.to_string())?;
+async fn seek(
+    position: f64,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<(), String> {
+    let mut app_state = state.lock().unwrap();
+    app_state.seek(position)?;
     Ok(())
 }
 
+// Jump to the next or previous note instead of a fixed time; returns the new position
 #[tauri::command]
-async fn focus_game_window() -> Result<(), String> {
-    keyboard::focus_black_desert_window().map_err(|e| e.to_string())
+async fn seek_to_note(
+    direction: midi::NoteDirection,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<f64, String> {
+    state.lock().unwrap().seek_to_note(direction)
 }
 
+// Seek by musical beat (0 = start) rather than by time; returns the new position in seconds
 #[tauri::command]
-async fn import_midi_file(source_path: String) -> Result<MidiFile, String> {
-    let source = std::path::Path::new(&source_path);
+async fn seek_to_beat(
+    beat: f64,
+    state: State<'_, Arc<Mutex<AppState>>>
+) -> Result<f64, String> {
+    state.lock().unwrap().seek_to_beat(beat)
+}
 
-    // Verify it's a .mid file
-    if source.extension().and_then(|s| s.to_str()) != Some("mid") {
-        return Err("File must be a .mid file".to_string());
-    }
 
-    // Get album folder path
-    let exe_path = std::env::current_exe().map_err(|e| e.to_string())?;
-    let exe_dir = exe_path.parent().ok_or("Failed to get executable directory")?;
-    let album_path = exe_dir.join("album");
+/// A global hotkey registered with RegisterHotKey and the action it triggers
+struct HotkeyBinding {
+    id: i32,
+    modifiers: u32,
+    vk: u32,
+    name: &'static str,
+    action: &'static str,
+}
 
-    // Create album folder if it doesn't exist
-    if !album_path.exists() {
-        std::fs::create_dir_all(&album_path).map_err(|e| e.to_string())?;
-    }
+const HOTKEY_BINDINGS: [HotkeyBinding; 9] = [
+    HotkeyBinding { id: HOTKEY_PAUSE_RESUME, modifiers: 0, vk: VK_F9.0 as u32, name: "F9 (Pause/Resume)", action: "pause_resume" },
+    HotkeyBinding { id: HOTKEY_STOP_END, modifiers: 0, vk: VK_END.0 as u32, name: "End (Stop)", action: "stop" },
+    HotkeyBinding { id: HOTKEY_STOP_F12, modifiers: 0, vk: VK_F12.0 as u32, name: "F12 (Stop)", action: "stop" },
+    HotkeyBinding { id: HOTKEY_PREV_F10, modifiers: 0, vk: VK_F10.0 as u32, name: "F10 (Previous)", action: "previous" },
+    HotkeyBinding { id: HOTKEY_NEXT_F11, modifiers: 0, vk: VK_F11.0 as u32, name: "F11 (Next)", action: "next" },
+    HotkeyBinding { id: HOTKEY_OCTAVE_UP, modifiers: 0, vk: VK_PRIOR.0 as u32, name: "Page Up (Octave Up)", action: "octave_up" },
+    HotkeyBinding { id: HOTKEY_OCTAVE_DOWN, modifiers: 0, vk: VK_NEXT.0 as u32, name: "Page Down (Octave Down)", action: "octave_down" },
+    HotkeyBinding { id: HOTKEY_PANIC, modifiers: MOD_CONTROL.0 | MOD_SHIFT.0, vk: VK_END.0 as u32, name: "Ctrl+Shift+End (Release All Keys)", action: "panic" },
+    HotkeyBinding { id: HOTKEY_STEP_F8, modifiers: 0, vk: VK_F8.0 as u32, name: "F8 (Step Next Note)", action: "step_next" },
+];
+
+/// Registration outcome of one hotkey, sent to the UI as `hotkeys-registered`
+#[derive(Debug, Clone, Serialize)]
+struct HotkeyStatus {
+    name: &'static str,
+    action: &'static str,
+    registered: bool,
+    // Handled by the low-level keyboard hook instead because registration failed
+    hook_fallback: bool,
+}
 
-    // Get filename and create destination path
-    let filename = source.file_name().ok_or("Invalid filename")?;
-    let dest_path = album_path.join(filename);
+/// A key handled by the low-level keyboard hook
+#[derive(Debug, Clone, Copy)]
+struct HookBinding {
+    vk: u32,
+    // Ctrl/Shift state that must be held exactly (None = any)
+    modifiers: Option<u32>,
+    action: &'static str,
+}
 
-    // Check if file already exists
-    if dest_path.exists() {
-        return Err(format!("File '{}' already exists in album", filename.to_string_lossy()));
-    }
+// Virtual key codes for [ and ]
+const VK_OEM_4: u32 = 0xDB; // [ key
+const VK_OEM_6: u32 = 0xDD; // ] key
+const VK_INSERT: u32 = 0x2D; // Insert key
+const VK_OEM_PLUS: u32 = 0xBB; // = / + key
+const VK_OEM_MINUS: u32 = 0xBD; // - key
+
+lazy_static::lazy_static! {
+    // Keys only the hook handles; hotkeys that fail to register are added at startup
+    static ref HOOK_BINDINGS: Mutex<Vec<HookBinding>> = Mutex::new(vec![
+        HookBinding { vk: VK_OEM_4, modifiers: None, action: "mode_prev" },
+        HookBinding { vk: VK_OEM_6, modifiers: None, action: "mode_next" },
+        HookBinding { vk: VK_INSERT, modifiers: None, action: "toggle_mini" },
+        HookBinding { vk: VK_OEM_PLUS, modifiers: None, action: "transpose_up" },
+        HookBinding { vk: VK_OEM_MINUS, modifiers: None, action: "transpose_down" },
+    ]);
+    static ref HOTKEY_STATUS: Mutex<Vec<HotkeyStatus>> = Mutex::new(Vec::new());
+}
 
-    // Copy file to album folder
-    std::fs::copy(&source, &dest_path).map_err(|e| format!("Failed to copy file: {}", e))?;
+// Whether the low-level keyboard hook was installed
+static HOOK_ACTIVE: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
 
-    // Get duration and return file info
-    let name = source.file_stem()
-        .and_then(|s| s.to_str())
-        .unwrap_or("Unknown")
-        .to_string();
-
-    let duration = midi::get_midi_duration(&dest_path.to_string_lossy())
-        .unwrap_or(0.0);
-
-    Ok(MidiFile {
-        name,
-        path: dest_path.to_string_lossy().to_string(),
-        duration,
-    })
+/// Global hook diagnostics, sent to the UI as `hook-status`.
+/// Debug builds skip the admin manifest, so the hook may not see keys sent to the game.
+#[derive(Debug, Clone, Copy, Serialize)]
+struct HookStatus {
+    hook_active: bool,
+    elevated: bool,
 }
 
-#[tauri::command]
-async fn seek(
-    position: f64,
-    state: State<'_, Arc<Mutex<AppState>>>,
-    window: Window
-) -> Result<(), String> {
-    let mut app_state = state.lock().unwrap();
-    app_state.seek(position, window)?;
-    Ok(())
+fn hook_status() -> HookStatus {
+    HookStatus {
+        hook_active: HOOK_ACTIVE.load(std::sync::atomic::Ordering::SeqCst),
+        elevated: is_process_elevated(),
+    }
 }
 
-
-fn register_global_hotkeys() -> Vec<(&'static str, bool)> {
-    let mut results = Vec::new();
-
+/// Whether this process runs with an elevated (administrator) token
+fn is_process_elevated() -> bool {
     unsafe {
-        // F9 - Pause/Resume
-        let result = RegisterHotKey(None, HOTKEY_PAUSE_RESUME, MOD_NOREPEAT, VK_F9.0 as u32);
-        results.push(("F9 (Pause/Resume)", result.is_ok()));
-
-        // End - Stop
-        let result = RegisterHotKey(None, HOTKEY_STOP_END, MOD_NOREPEAT, VK_END.0 as u32);
-        results.push(("End (Stop)", result.is_ok()));
+        let mut token = HANDLE::default();
+        if OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token).is_err() {
+            return false;
+        }
+        let mut elevation = TOKEN_ELEVATION::default();
+        let mut size = 0u32;
+        let result = GetTokenInformation(
+            token,
+            TokenElevation,
+            Some(&mut elevation as *mut TOKEN_ELEVATION as *mut std::ffi::c_void),
+            std::mem::size_of::<TOKEN_ELEVATION>() as u32,
+            &mut size,
+        );
+        let _ = CloseHandle(token);
+        result.is_ok() && elevation.TokenIsElevated != 0
+    }
+}
 
-        // F12 - Stop (may fail if another app has it registered)
-        let result = RegisterHotKey(None, HOTKEY_STOP_F12, MOD_NOREPEAT, VK_F12.0 as u32);
-        results.push(("F12 (Stop)", result.is_ok()));
+fn register_global_hotkeys() -> Vec<HotkeyStatus> {
+    let mut results = Vec::new();
 
-        // F10 - Previous
-        let result = RegisterHotKey(None, HOTKEY_PREV_F10, MOD_NOREPEAT, VK_F10.0 as u32);
-        results.push(("F10 (Previous)", result.is_ok()));
+    for binding in &HOTKEY_BINDINGS {
+        let modifiers = HOT_KEY_MODIFIERS(binding.modifiers) | MOD_NOREPEAT;
+        let registered = unsafe { RegisterHotKey(None, binding.id, modifiers, binding.vk) }.is_ok();
+
+        // Other apps may own the combo; the hook still sees the key, so handle it there
+        if !registered {
+            HOOK_BINDINGS.lock().unwrap().push(HookBinding {
+                vk: binding.vk,
+                modifiers: Some(binding.modifiers),
+                action: binding.action,
+            });
+        }
 
-        // F11 - Next
-        let result = RegisterHotKey(None, HOTKEY_NEXT_F11, MOD_NOREPEAT, VK_F11.0 as u32);
-        results.push(("F11 (Next)", result.is_ok()));
+        results.push(HotkeyStatus {
+            name: binding.name,
+            action: binding.action,
+            registered,
+            hook_fallback: !registered,
+        });
     }
 
     results
 }
 
-// Virtual key codes for [ and ]
-const VK_OEM_4: u32 = 0xDB; // [ key
-const VK_OEM_6: u32 = 0xDD; // ] key
-const VK_INSERT: u32 = 0x2D; // Insert key
+// Ctrl/Shift currently held, as MOD_* bits
+fn held_modifiers() -> u32 {
+    let is_down = |vk: VIRTUAL_KEY| unsafe { GetAsyncKeyState(vk.0 as i32) } < 0;
+    let mut modifiers = 0;
+    if is_down(VK_CONTROL) {
+        modifiers |= MOD_CONTROL.0;
+    }
+    if is_down(VK_SHIFT) {
+        modifiers |= MOD_SHIFT.0;
+    }
+    modifiers
+}
+
+fn dispatch_hotkey_action(app_handle: &AppHandle, action: &str) {
+    // Panic is handled here so it works even if the UI or playback is stuck
+    if action == "panic" {
+        keyboard::panic_release();
+    } else {
+        let _ = app_handle.emit("global-shortcut", action);
+    }
+}
 
-// Low-level keyboard hook callback for F12, mode switching, and mini mode
+// Low-level keyboard hook callback for keys in HOOK_BINDINGS (mode switching, mini mode,
+// live transpose and any hotkey that failed to register)
 unsafe extern "system" fn low_level_keyboard_proc(
     ncode: i32,
     wparam: windows::Win32::Foundation::WPARAM,
@@ -304,22 +1839,16 @@ unsafe extern "system" fn low_level_keyboard_proc(
         let is_keydown = wparam.0 as u32 == WM_KEYDOWN || wparam.0 as u32 == WM_SYSKEYDOWN;
 
         if is_keydown {
-            if let Some(ref app_handle) = GLOBAL_APP_HANDLE {
-                // Check if F12 was pressed
-                if kb_struct.vkCode == VK_F12.0 as u32 {
-                    let _ = app_handle.emit("global-shortcut", "stop");
-                }
-                // Check if [ was pressed - previous mode
-                else if kb_struct.vkCode == VK_OEM_4 {
-                    let _ = app_handle.emit("global-shortcut", "mode_prev");
-                }
-                // Check if ] was pressed - next mode
-                else if kb_struct.vkCode == VK_OEM_6 {
-                    let _ = app_handle.emit("global-shortcut", "mode_next");
-                }
-                // Check if Insert was pressed - toggle mini mode
-                else if kb_struct.vkCode == VK_INSERT {
-                    let _ = app_handle.emit("global-shortcut", "toggle_mini");
+            if let Some(app_handle) = GLOBAL_APP_HANDLE.get() {
+                let action = HOOK_BINDINGS.lock().unwrap()
+                    .iter()
+                    .find(|binding| {
+                        binding.vk == kb_struct.vkCode
+                            && binding.modifiers.is_none_or(|modifiers| modifiers == held_modifiers())
+                    })
+                    .map(|binding| binding.action);
+                if let Some(action) = action {
+                    dispatch_hotkey_action(app_handle, action);
                 }
             }
         }
@@ -328,10 +1857,32 @@ unsafe extern "system" fn low_level_keyboard_proc(
     CallNextHookEx(HHOOK::default(), ncode, wparam, lparam)
 }
 
+#[tauri::command]
+async fn check_hotkey_conflicts(bindings: Vec<String>) -> Result<Vec<hotkeys::HotkeyWarning>, String> {
+    Ok(hotkeys::check_hotkey_conflicts(&bindings))
+}
+
+#[tauri::command]
+async fn get_hotkey_status() -> Result<Vec<HotkeyStatus>, String> {
+    Ok(HOTKEY_STATUS.lock().unwrap().clone())
+}
+
+#[tauri::command]
+async fn is_elevated() -> Result<bool, String> {
+    Ok(is_process_elevated())
+}
+
+#[tauri::command]
+async fn get_hook_status() -> Result<HookStatus, String> {
+    Ok(hook_status())
+}
+
 fn start_hotkey_listener(app_handle: AppHandle) {
-    // Store app handle globally for the low-level hook callback
-    unsafe {
-        GLOBAL_APP_HANDLE = Some(app_handle.clone());
+    // Store app handle globally for the low-level hook callback. This happens before the
+    // listener thread installs the hook, so the very first key press is delivered.
+    if GLOBAL_APP_HANDLE.set(app_handle.clone()).is_err() {
+        eprintln!("Hotkey listener already started");
+        return;
     }
 
     thread::spawn(move || {
@@ -340,16 +1891,20 @@ fn start_hotkey_listener(app_handle: AppHandle) {
 
         // Log results
         println!("=== Global Hotkey Registration ===");
-        for (name, success) in &hotkey_results {
-            if *success {
-                println!("  ✓ {}", name);
+        for status in &hotkey_results {
+            if status.registered {
+                println!("  ✓ {}", status.name);
             } else {
-                println!("  ✗ {} (failed - may be in use by another app)", name);
+                println!("  ✗ {} (failed - may be in use by another app, using keyboard hook)", status.name);
             }
         }
         println!("==================================");
 
-        // Install low-level keyboard hook for F12 as fallback
+        // Let the UI warn about hotkeys that only work through the hook
+        let _ = app_handle.emit("hotkeys-registered", &hotkey_results);
+        *HOTKEY_STATUS.lock().unwrap() = hotkey_results;
+
+        // Install low-level keyboard hook for hook-only keys and failed hotkeys
         unsafe {
             let hook = SetWindowsHookExW(
                 WH_KEYBOARD_LL,
@@ -358,13 +1913,29 @@ fn start_hotkey_listener(app_handle: AppHandle) {
                 0,
             );
 
-            if hook.is_err() {
-                eprintln!("Failed to install low-level keyboard hook for F12");
+            if let Err(e) = &hook {
+                let unregistered: Vec<&str> = HOTKEY_STATUS.lock().unwrap()
+                    .iter()
+                    .filter(|status| !status.registered)
+                    .map(|status| status.name)
+                    .collect();
+                let mut message = format!("Failed to install low-level keyboard hook: {}", e);
+                if !unregistered.is_empty() {
+                    message.push_str(&format!(". These hotkeys will not work: {}", unregistered.join(", ")));
+                }
+                report_error("hotkey-error", &message);
             } else {
-                println!("  ✓ Low-level keyboard hook installed (F12 fallback)");
+                println!("  ✓ Low-level keyboard hook installed");
             }
+            HOOK_ACTIVE.store(hook.is_ok(), std::sync::atomic::Ordering::SeqCst);
         }
 
+        let status = hook_status();
+        if !status.elevated {
+            println!("  ! Not running as administrator: global hotkeys may not work in-game");
+        }
+        let _ = app_handle.emit("hook-status", status);
+
         // Run message loop to receive hotkey and hook messages
         unsafe {
             let mut msg: MSG = std::mem::zeroed();
@@ -375,7 +1946,7 @@ fn start_hotkey_listener(app_handle: AppHandle) {
                 let result = GetMessageW(&mut msg, None, 0, 0);
 
                 if result.0 == -1 {
-                    eprintln!("GetMessageW error");
+                    report_error("hotkey-error", "Hotkey listener stopped: GetMessageW failed");
                     break;
                 }
                 if result.0 == 0 {
@@ -385,16 +1956,9 @@ fn start_hotkey_listener(app_handle: AppHandle) {
 
                 if msg.message == WM_HOTKEY {
                     let hotkey_id = msg.wParam.0 as i32;
-
-                    let action = match hotkey_id {
-                        HOTKEY_PAUSE_RESUME => "pause_resume",
-                        HOTKEY_STOP_END | HOTKEY_STOP_F12 => "stop",
-                        HOTKEY_PREV_F10 => "previous",
-                        HOTKEY_NEXT_F11 => "next",
-                        _ => continue,
-                    };
-
-                    let _ = app_handle.emit("global-shortcut", action);
+                    if let Some(binding) = HOTKEY_BINDINGS.iter().find(|binding| binding.id == hotkey_id) {
+                        dispatch_hotkey_action(&app_handle, binding.action);
+                    }
                 }
 
                 // Dispatch other messages (needed for low-level hook to work)
@@ -405,34 +1969,248 @@ fn start_hotkey_listener(app_handle: AppHandle) {
     });
 }
 
+// How often the playing position is saved as the resume point
+const RESUME_SAVE_INTERVAL_MS: u64 = 5000;
+
+// Keep the resume point up to date while songs play, so it survives a crash or restart
+fn start_resume_saver(app_handle: AppHandle) {
+    thread::spawn(move || {
+        let state = Arc::clone(app_handle.state::<Arc<Mutex<AppState>>>().inner());
+        loop {
+            thread::sleep(std::time::Duration::from_millis(RESUME_SAVE_INTERVAL_MS));
+            if let Err(e) = state.lock().unwrap().save_resume_point() {
+                eprintln!("Failed to save resume point: {}", e);
+            }
+        }
+    });
+}
+
+// How often the album watcher looks at the library folders. Each look lists and stats every
+// song, so keep it well apart; a few seconds' delay before the list refreshes is fine
+const ALBUM_WATCH_INTERVAL_MS: u64 = 3000;
+
+// Cleared on exit to stop the album watcher
+static ALBUM_WATCHER_RUNNING: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+// Song files in the libraries with their size and modification time, to spot changes
+fn library_snapshot(
+    libraries: &[std::path::PathBuf],
+) -> std::collections::BTreeSet<(std::path::PathBuf, u64, Option<std::time::SystemTime>)> {
+    libraries
+        .iter()
+        .filter_map(|library| std::fs::read_dir(library).ok())
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| is_song_file(path))
+        .map(|path| {
+            let metadata = std::fs::metadata(&path).ok();
+            let size = metadata.as_ref().map_or(0, |m| m.len());
+            let modified = metadata.and_then(|m| m.modified().ok());
+            (path, size, modified)
+        })
+        .collect()
+}
+
+// Emit album-changed when songs are added, removed or rewritten in a library folder, so the
+// UI can reload the list. Polls rather than using OS notifications, and reports a burst of
+// changes (e.g. copying many files in) once, after it has settled.
+fn start_album_watcher(app_handle: AppHandle) {
+    use std::sync::atomic::Ordering;
+    if ALBUM_WATCHER_RUNNING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    thread::spawn(move || {
+        let state = Arc::clone(app_handle.state::<Arc<Mutex<AppState>>>().inner());
+        let libraries = || library_dirs(&state.lock().unwrap()).unwrap_or_default();
+
+        let mut last = library_snapshot(&libraries());
+        let mut changed = false;
+        while ALBUM_WATCHER_RUNNING.load(Ordering::SeqCst) {
+            thread::sleep(std::time::Duration::from_millis(ALBUM_WATCH_INTERVAL_MS));
+            let snapshot = library_snapshot(&libraries());
+            if snapshot != last {
+                last = snapshot;
+                changed = true;
+            } else if changed {
+                changed = false;
+                let _ = app_handle.emit("album-changed", ());
+            }
+        }
+    });
+}
+
 fn main() {
+    // Headless playback from the command line, without launching the webview
+    if let Some(options) = cli::parse_args(std::env::args().skip(1)) {
+        let result = options.and_then(cli::run);
+        if let Err(e) = &result {
+            eprintln!("{}", e);
+        }
+        std::process::exit(if result.is_ok() { 0 } else { 1 });
+    }
+
     let app_state = Arc::new(Mutex::new(AppState::new()));
 
     tauri::Builder::default()
         .plugin(tauri_plugin_dialog::init())
         .manage(app_state)
         .setup(|app| {
+            // Initialize input simulation up front; the UI can query get_keyboard_error and retry
+            if let Some(e) = keyboard::keyboard_error() {
+                eprintln!("{}", e);
+            }
             start_hotkey_listener(app.handle().clone());
+            start_album_watcher(app.handle().clone());
+            start_resume_saver(app.handle().clone());
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
             load_midi_files,
             play_midi,
+            play_midi_delayed,
+            play_duet,
+            load_song,
+            get_transpose_detection,
+            play_loaded,
+            preview_midi,
+            stop_preview,
+            start_manual_step,
+            step_next_note,
+            reset_manual_step,
             pause_resume,
             stop_playback,
+            force_stop,
             get_playback_status,
             set_loop_mode,
+            set_loop_count,
+            set_loop_gap_ms,
+            set_seamless_loop,
+            set_autoplay_next,
+            get_autoplay_next,
+            set_remember_last_song,
+            get_resume_state,
+            resume_last,
+            set_shuffle,
+            next_track_index,
+            previous_track_index,
+            set_focus_delay,
+            set_latency_offset_ms,
+            get_latency_offset_ms,
+            set_humanize,
+            set_practice_ramp,
+            clear_practice_ramp,
+            set_chord_reduction,
+            set_min_note_duration_ms,
+            set_min_channel_volume,
+            set_time_quantize,
+            set_swing,
+            set_pitch_bend,
+            set_detache,
+            set_note_off_delay_ms,
+            set_max_nps,
+            bass_tremolo,
+            set_note_overrides,
+            get_note_overrides,
+            pause_at_markers,
+            set_call_and_response,
+            set_debug_logging,
+            set_register_weight,
+            reset_settings,
+            get_register_weight,
+            set_trim_leading_silence,
+            get_markers,
+            get_last_playback_stats,
+            set_key_sheet_speed,
+            set_progress_interval,
+            set_pause_on_focus_loss,
             set_note_mode,
             get_note_mode,
+            set_song_preferences,
+            get_song_preferences,
+            set_output_mode,
+            get_output_mode,
+            list_midi_outputs,
+            set_midi_output,
             set_octave_shift,
             get_octave_shift,
+            set_octave_shifts,
+            octave_up,
+            octave_down,
+            set_transpose,
+            clear_transpose_override,
+            nudge_transpose,
+            check_note_range,
+            analyze_midi,
+            auto_fit_octave,
+            playability_report,
+            detect_sections,
+            get_note_key_map,
+            list_sequences,
+            list_tracks,
+            set_active_tracks,
             is_game_focused,
+            start_recording,
+            stop_recording,
+            is_recording,
+            set_on_not_focused,
+            get_on_not_focused,
+            set_auto_focus_mode,
+            get_auto_focus_mode,
             test_all_keys,
+            test_mode,
+            panic_release,
+            get_keyboard_error,
+            reinit_keyboard,
+            set_instrument_profile,
+            list_instrument_profiles,
+            set_hand_split,
+            list_split_keys,
             set_interaction_mode,
             focus_game_window,
             seek,
+            seek_to_note,
+            seek_to_beat,
+            validate_midi,
             import_midi_file,
+            import_midi_pack,
+            import_midi_from_bytes,
+            set_album_order,
+            get_file_metadata,
+            set_file_metadata,
+            get_album_path,
+            set_library_paths,
+            get_library_paths,
+            set_default_library,
+            open_album_folder,
+            delete_midi_file,
+            is_file_playing,
+            rename_midi_file,
+            scan_button_positions,
+            scan_from_image,
+            capture_screenshot,
+            auto_scan,
+            cancel_scan,
+            set_scan_params,
+            get_scan_params,
+            set_scan_region,
+            clear_scan_region,
+            get_scan_region,
+            get_hotkey_status,
+            is_elevated,
+            get_hook_status,
+            check_hotkey_conflicts,
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            if let tauri::RunEvent::Exit = event {
+                ALBUM_WATCHER_RUNNING.store(false, std::sync::atomic::Ordering::SeqCst);
+                // Catch the position since the last periodic save
+                if let Err(e) = app.state::<Arc<Mutex<AppState>>>().lock().unwrap().save_resume_point() {
+                    eprintln!("Failed to save resume point: {}", e);
+                }
+            }
+        });
 }
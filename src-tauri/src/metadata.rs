@@ -0,0 +1,47 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use serde::{Serialize, Deserialize};
+
+/// User tags, rating and notes for one song
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FileMetadata {
+    pub tags: Vec<String>,
+    pub rating: Option<u8>,
+    pub notes: String,
+    // Note mode to switch to when this song loads (None = keep the current mode)
+    pub note_mode: Option<crate::midi::NoteMode>,
+    // Hand-picked key per MIDI pitch, pressed instead of the mode's mapping
+    pub note_overrides: HashMap<u8, String>,
+}
+
+/// Metadata for all songs keyed by file path, stored as metadata.json next to the executable
+pub type MetadataStore = HashMap<String, FileMetadata>;
+
+fn metadata_path() -> Result<PathBuf, String> {
+    let exe_path = std::env::current_exe().map_err(|e| e.to_string())?;
+    let exe_dir = exe_path.parent().ok_or("Failed to get executable directory")?;
+    Ok(exe_dir.join("metadata.json"))
+}
+
+/// Load the metadata store, starting empty if missing or invalid
+pub fn load_metadata() -> MetadataStore {
+    let path = match metadata_path() {
+        Ok(path) => path,
+        Err(_) => return MetadataStore::new(),
+    };
+
+    match std::fs::read_to_string(&path) {
+        Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+            eprintln!("Failed to parse file metadata, starting empty: {}", e);
+            MetadataStore::new()
+        }),
+        Err(_) => MetadataStore::new(),
+    }
+}
+
+pub fn save_metadata(store: &MetadataStore) -> Result<(), String> {
+    let path = metadata_path()?;
+    let contents = serde_json::to_string_pretty(store).map_err(|e| e.to_string())?;
+    std::fs::write(&path, contents).map_err(|e| format!("Failed to save file metadata: {}", e))
+}
@@ -1,6 +1,6 @@
-use midly::{Smf, TrackEventKind, MidiMessage};
-use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
-use std::sync::Arc;
+use midly::{Smf, TrackEventKind, MetaMessage, MidiMessage};
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
 use std::time::{Duration, Instant};
 use tauri::{Window, Emitter};
 use serde::{Serialize, Deserialize};
@@ -15,6 +15,8 @@ pub enum NoteMode {
     Pentatonic = 3,   // Map to pentatonic scale (5 notes)
     Chromatic = 4,    // Detailed chromatic mapping
     Raw = 5,          // Raw 1:1 mapping, no transpose
+    Mono = 6,         // Closest mapping, but only one key held at a time
+    Contour = 7,      // Closest mapping, nudged so rising/falling lines don't repeat a key
 }
 
 impl From<u8> for NoteMode {
@@ -26,16 +28,405 @@ impl From<u8> for NoteMode {
             3 => NoteMode::Pentatonic,
             4 => NoteMode::Chromatic,
             5 => NoteMode::Raw,
+            6 => NoteMode::Mono,
+            7 => NoteMode::Contour,
             _ => NoteMode::Closest,
         }
     }
 }
 
+/// Chord reduction - which single note is kept when several notes start together
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[repr(u8)]
+pub enum ChordMode {
+    Off = 0,    // Play every note of the chord
+    Top = 1,    // Highest note (the melody of most piano scores)
+    Bottom = 2, // Lowest note
+    Root = 3,   // Chord root by its intervals, the lowest note when there's no clear root
+}
+
+impl From<u8> for ChordMode {
+    fn from(value: u8) -> Self {
+        match value {
+            1 => ChordMode::Top,
+            2 => ChordMode::Bottom,
+            3 => ChordMode::Root,
+            _ => ChordMode::Off,
+        }
+    }
+}
+
+/// Wakes the playback thread early when pause/stop/seek state changes,
+/// so it can sleep until the next event instead of polling
+#[derive(Default)]
+pub struct PlaybackSignal {
+    lock: Mutex<()>,
+    cvar: Condvar,
+}
+
+impl PlaybackSignal {
+    pub fn notify(&self) {
+        let _guard = self.lock.lock().unwrap();
+        self.cvar.notify_all();
+    }
+
+    fn wait(&self, timeout: Duration) {
+        let guard = self.lock.lock().unwrap();
+        let _ = self.cvar.wait_timeout(guard, timeout);
+    }
+}
+
+// How often the count-in before a delayed start reports the time left
+const COUNT_IN_TICK_MS: u64 = 100;
+
+/// Wait `delay_ms` before a delayed start, reporting the time left to the sink (ending with 0)
+/// along with a heartbeat per tick, since play_midi's heartbeat hasn't started yet.
+/// Returns early if playback is stopped meanwhile.
+pub fn count_in(delay_ms: u64, is_playing: &AtomicBool, signal: &PlaybackSignal, sink: &impl PlaybackSink) {
+    let start = Instant::now();
+    let delay = Duration::from_millis(delay_ms);
+    let mut beat = 0;
+    while is_playing.load(Ordering::SeqCst) {
+        let remaining = delay.saturating_sub(start.elapsed());
+        beat += 1;
+        sink.heartbeat(beat);
+        sink.starting(remaining.as_millis() as u64);
+        if remaining.is_zero() {
+            break;
+        }
+        signal.wait(remaining.min(Duration::from_millis(COUNT_IN_TICK_MS)));
+    }
+}
+
+/// Caps new key presses per second over a sliding one-second window (0 = no cap)
+struct PressLimiter {
+    max_nps: Arc<std::sync::atomic::AtomicU32>,
+    presses: std::collections::VecDeque<Instant>,
+}
+
+impl PressLimiter {
+    /// Whether another press fits under the cap right now; counts it if so
+    fn allow(&mut self) -> bool {
+        let max_nps = self.max_nps.load(Ordering::SeqCst) as usize;
+        if max_nps == 0 {
+            return true;
+        }
+        let now = Instant::now();
+        while self.presses.front().is_some_and(|&t| now.duration_since(t) >= Duration::from_secs(1)) {
+            self.presses.pop_front();
+        }
+        if self.presses.len() >= max_nps {
+            return false;
+        }
+        self.presses.push_back(now);
+        true
+    }
+}
+
+/// Raises the Windows timer resolution to 1ms while playback is running
+struct TimerResolutionGuard;
+
+impl TimerResolutionGuard {
+    fn new() -> Self {
+        #[cfg(target_os = "windows")]
+        unsafe {
+            windows::Win32::Media::timeBeginPeriod(1);
+        }
+        TimerResolutionGuard
+    }
+}
+
+impl Drop for TimerResolutionGuard {
+    fn drop(&mut self) {
+        #[cfg(target_os = "windows")]
+        unsafe {
+            windows::Win32::Media::timeEndPeriod(1);
+        }
+    }
+}
+
+// Semitones covered by a full pitch bend (General MIDI default range)
+const PITCH_BEND_RANGE: f64 = 2.0;
+
+/// Whole-semitone offset for a bend value; bends under half a semitone stay on the same note
+fn pitch_bend_semitones(bend: i16) -> i32 {
+    (bend as f64 / 8192.0 * PITCH_BEND_RANGE).round() as i32
+}
+
+// How often the progress thread checks whether the game still has focus
+const FOCUS_CHECK_INTERVAL_MS: u64 = 500;
+
+// Longest single wait between events, so current_position stays fresh for the progress thread
+const MAX_WAIT_SLICE_MS: u64 = 50;
+
+// Position sent to the UI. The last event can land slightly past the end; don't let the
+// slider overshoot
+fn reported_position(position: f64, duration: f64) -> f64 {
+    position.clamp(0.0, duration)
+}
+
+/// Value of the seek request when no seek is pending
+pub const NO_SEEK: u64 = u64::MAX;
+
 #[derive(Debug, Clone)]
 pub struct MidiData {
     pub events: Vec<TimedEvent>,
     pub duration: f64,
     pub transpose: i32,
+    pub tempo_map: TempoMap,
+    // Marker/cue point meta events, sorted by time
+    pub markers: Vec<Marker>,
+    // Channel volume (CC7) changes, for gating quiet channels
+    pub volumes: VolumeTimeline,
+}
+
+/// Key event tally for one playback, for diagnosing dropped notes
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct PlaybackStats {
+    // Notes (or key sheet keys) that started
+    pub note_ons: u64,
+    // Actual key presses sent to the game
+    pub keys_pressed: u64,
+    // Notes that landed on a key already held down, so no new press was sent
+    pub keys_skipped: u64,
+    // Notes dropped by the notes-per-second cap
+    pub throttled: u64,
+}
+
+/// A named point in the song (MIDI marker or cue point)
+#[derive(Debug, Clone, Serialize)]
+pub struct Marker {
+    pub time_ms: u64,
+    pub name: String,
+}
+
+/// Channel volume (CC7) over time, per channel
+#[derive(Debug, Clone, Default)]
+pub struct VolumeTimeline {
+    // (time in ms, volume) per channel, sorted by time
+    channels: [Vec<(u64, u8)>; 16],
+}
+
+impl VolumeTimeline {
+    /// Volume of a channel before its first CC7 (the General MIDI default)
+    pub const DEFAULT_VOLUME: u8 = 100;
+
+    fn push(&mut self, channel: u8, time_ms: u64, volume: u8) {
+        self.channels[(channel & 0x0F) as usize].push((time_ms, volume));
+    }
+
+    // Stable, so of two changes at the same time the later one in the file wins
+    fn sort(&mut self) {
+        for changes in &mut self.channels {
+            changes.sort_by_key(|&(time_ms, _)| time_ms);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.channels.iter().all(|changes| changes.is_empty())
+    }
+
+    /// Volume of `channel` at `time_ms`; a change at exactly that time already applies
+    pub fn volume_at(&self, channel: u8, time_ms: u64) -> u8 {
+        let changes = &self.channels[(channel & 0x0F) as usize];
+        let idx = changes.partition_point(|&(change_ms, _)| change_ms <= time_ms);
+        if idx == 0 {
+            Self::DEFAULT_VOLUME
+        } else {
+            changes[idx - 1].1
+        }
+    }
+
+    fn map_times(&self, f: impl Fn(u64) -> u64) -> VolumeTimeline {
+        VolumeTimeline {
+            channels: std::array::from_fn(|channel| {
+                self.channels[channel].iter().map(|&(time_ms, volume)| (f(time_ms), volume)).collect()
+            }),
+        }
+    }
+
+    // Both timelines together, with `other` starting `offset_ms` later
+    fn merged(&self, other: &VolumeTimeline, offset_ms: u64) -> VolumeTimeline {
+        let mut merged = self.clone();
+        for (channel, changes) in other.channels.iter().enumerate() {
+            for &(time_ms, volume) in changes {
+                merged.push(channel as u8, time_ms + offset_ms, volume);
+            }
+        }
+        merged.sort();
+        merged
+    }
+}
+
+impl MidiData {
+    /// Rescale all event times for a playback speed multiplier (2.0 = twice as fast)
+    pub fn with_speed(&self, speed: f64) -> MidiData {
+        MidiData {
+            events: self.events
+                .iter()
+                .map(|event| TimedEvent {
+                    time_ms: (event.time_ms as f64 / speed).round() as u64,
+                    ..event.clone()
+                })
+                .collect(),
+            duration: self.duration / speed,
+            transpose: self.transpose,
+            tempo_map: self.tempo_map.scaled(speed),
+            markers: self.markers
+                .iter()
+                .map(|marker| Marker {
+                    time_ms: (marker.time_ms as f64 / speed).round() as u64,
+                    name: marker.name.clone(),
+                })
+                .collect(),
+            volumes: self.volumes.map_times(|time_ms| (time_ms as f64 / speed).round() as u64),
+        }
+    }
+
+    /// Time of the first note press, if the song has any
+    pub fn first_note_ms(&self) -> Option<u64> {
+        self.events
+            .iter()
+            .find(|e| matches!(e.event_type, EventType::NoteOn | EventType::KeyDown(_)))
+            .map(|e| e.time_ms)
+    }
+
+    /// Shift everything earlier so the first note lands at t=0. Setup events
+    /// (bends, markers) in the lead-in are kept at the start.
+    pub fn without_leading_silence(&self) -> MidiData {
+        let lead_in_ms = self.first_note_ms().unwrap_or(0);
+        MidiData {
+            events: self.events
+                .iter()
+                .map(|event| TimedEvent {
+                    time_ms: event.time_ms.saturating_sub(lead_in_ms),
+                    ..event.clone()
+                })
+                .collect(),
+            duration: (self.duration - lead_in_ms as f64 / 1000.0).max(0.0),
+            transpose: self.transpose,
+            tempo_map: self.tempo_map.shifted(lead_in_ms as f64),
+            markers: self.markers
+                .iter()
+                .map(|marker| Marker {
+                    time_ms: marker.time_ms.saturating_sub(lead_in_ms),
+                    name: marker.name.clone(),
+                })
+                .collect(),
+            volumes: self.volumes.map_times(|time_ms| time_ms.saturating_sub(lead_in_ms)),
+        }
+    }
+
+    /// Only the first `seconds` of the song, e.g. for a preview. Notes still held at the cut
+    /// are released when playback ends.
+    pub fn truncated(&self, seconds: f64) -> MidiData {
+        let limit_ms = (seconds.max(0.0) * 1000.0) as u64;
+        MidiData {
+            events: self.events.iter().filter(|event| event.time_ms < limit_ms).cloned().collect(),
+            duration: self.duration.min(seconds.max(0.0)),
+            transpose: self.transpose,
+            tempo_map: self.tempo_map.clone(),
+            markers: self.markers.iter().filter(|marker| marker.time_ms < limit_ms).cloned().collect(),
+            volumes: self.volumes.clone(),
+        }
+    }
+}
+
+/// Receives playback updates. Implemented for the Tauri window so the UI gets events;
+/// `NullSink` discards everything for headless playback.
+pub trait PlaybackSink: Clone + Send + 'static {
+    fn progress(&self, position: f64);
+    fn ended(&self);
+    fn active_keys(&self, keys: &[String]);
+    fn focus_changed(&self, _focused: bool) {}
+    fn marker_reached(&self, _marker: &Marker, _paused: bool) {}
+    fn section_complete(&self, _pass: u32) {}
+    fn debug_mapping(&self, _mapping: &MappingDebug) {}
+    fn heartbeat(&self, _beat: u64) {}
+    fn stats(&self, _stats: &PlaybackStats) {}
+    fn starting(&self, _remaining_ms: u64) {}
+}
+
+impl PlaybackSink for Window {
+    fn progress(&self, position: f64) {
+        let _ = self.emit("playback-progress", position);
+    }
+
+    fn ended(&self) {
+        let _ = self.emit("playback-ended", ());
+    }
+
+    fn active_keys(&self, keys: &[String]) {
+        let _ = self.emit("active-keys", keys);
+    }
+
+    fn focus_changed(&self, focused: bool) {
+        let event = if focused { "focus-regained" } else { "focus-lost" };
+        let _ = self.emit(event, ());
+    }
+
+    fn marker_reached(&self, marker: &Marker, paused: bool) {
+        let _ = self.emit("marker-reached", serde_json::json!({
+            "name": marker.name,
+            "time": marker.time_ms as f64 / 1000.0,
+            "paused": paused,
+        }));
+    }
+
+    fn section_complete(&self, pass: u32) {
+        let _ = self.emit("section-complete", pass);
+    }
+
+    fn debug_mapping(&self, mapping: &MappingDebug) {
+        let _ = self.emit("debug-mapping", mapping);
+    }
+
+    fn heartbeat(&self, beat: u64) {
+        let _ = self.emit("heartbeat", beat);
+    }
+
+    fn stats(&self, stats: &PlaybackStats) {
+        let _ = self.emit("playback-stats", stats);
+    }
+
+    fn starting(&self, remaining_ms: u64) {
+        let _ = self.emit("playback-starting", remaining_ms);
+    }
+}
+
+/// Reports a preview under its own events, so the UI doesn't treat it as the loaded song
+#[derive(Clone)]
+pub struct PreviewSink(pub Window);
+
+impl PlaybackSink for PreviewSink {
+    fn progress(&self, position: f64) {
+        let _ = self.0.emit("preview-progress", position);
+    }
+
+    fn ended(&self) {
+        let _ = self.0.emit("preview-ended", ());
+    }
+
+    fn active_keys(&self, keys: &[String]) {
+        self.0.active_keys(keys);
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NullSink;
+
+impl PlaybackSink for NullSink {
+    fn progress(&self, _position: f64) {}
+    fn ended(&self) {}
+    fn active_keys(&self, _keys: &[String]) {}
+}
+
+/// Bar/beat/BPM at a point in the song
+#[derive(Debug, Clone, Copy)]
+pub struct MusicalPosition {
+    pub bar: u32,  // 1-based
+    pub beat: u32, // 1-based
+    pub bpm: f64,
 }
 
 #[derive(Debug, Clone)]
@@ -43,130 +434,755 @@ pub struct TimedEvent {
     pub time_ms: u64,
     pub event_type: EventType,
     pub note: u8,
+    pub channel: u8,
+    // Index of the file track the event came from (0 for key sheets and markers)
+    pub track: usize,
 }
 
+impl TimedEvent {
+    // Identifies a sounding note during playback. The track is part of it, so the same
+    // pitch sounding on two tracks at once is held and released independently.
+    fn note_key(&self) -> NoteKey {
+        (self.track, self.channel, self.note)
+    }
+}
+
+// (track, channel, note) of a sounding note
+type NoteKey = (usize, u8, u8);
+
 #[derive(Debug, Clone)]
 pub enum EventType {
     NoteOn,
     NoteOff,
+    PitchBend(i16), // Signed bend amount, 0 = centered
+    // Explicit game keys from a text key sheet, bypassing note mapping
+    KeyDown(&'static str),
+    KeyUp(&'static str),
+    // Index into MidiData::markers
+    Marker(usize),
+}
+
+// What an event presses or releases, to pair releases with their presses
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Held {
+    Note(NoteKey),
+    Key(&'static str),
+}
+
+impl TimedEvent {
+    // The note or key this event presses (true) or releases (false)
+    fn held(&self) -> Option<(Held, bool)> {
+        match self.event_type {
+            EventType::NoteOn => Some((Held::Note(self.note_key()), true)),
+            EventType::NoteOff => Some((Held::Note(self.note_key()), false)),
+            EventType::KeyDown(key) => Some((Held::Key(key), true)),
+            EventType::KeyUp(key) => Some((Held::Key(key), false)),
+            _ => None,
+        }
+    }
+}
+
+/// Stable sort by time. At identical timestamps, releases of notes pressed earlier go first
+/// (a repeated note lets go of the key before it's hit again), then bends and markers (a note
+/// starts on its bent key), then presses. A note pressed and released at the same time
+/// (zero length, common for drums and grace notes) stays press-then-release.
+pub fn sort_events(events: &mut [TimedEvent]) {
+    events.sort_by_key(|e| e.time_ms);
+
+    // Presses still open from earlier timestamps
+    let mut open: std::collections::HashMap<Held, u32> = std::collections::HashMap::new();
+    let mut start = 0;
+    while start < events.len() {
+        let time_ms = events[start].time_ms;
+        let end = start + events[start..].iter().take_while(|e| e.time_ms == time_ms).count();
+        let group = &mut events[start..end];
+        start = end;
+
+        let ranks: Vec<u8> = group
+            .iter()
+            .map(|event| match event.held() {
+                Some((held, false)) => match open.get_mut(&held) {
+                    Some(count) if *count > 0 => {
+                        *count -= 1;
+                        0
+                    }
+                    // Ends a press from this same timestamp
+                    _ => 3,
+                },
+                Some((_, true)) => 2,
+                None => 1,
+            })
+            .collect();
+        // Presses open now, less the ones released again at this same timestamp
+        for event in group.iter() {
+            if let Some((held, true)) = event.held() {
+                *open.entry(held).or_insert(0) += 1;
+            }
+        }
+        for (event, &rank) in group.iter().zip(&ranks) {
+            if let (Some((held, false)), 3) = (event.held(), rank) {
+                if let Some(count) = open.get_mut(&held) {
+                    *count = count.saturating_sub(1);
+                }
+            }
+        }
+
+        let mut ranked: Vec<(u8, TimedEvent)> = ranks.into_iter().zip(group.iter().cloned()).collect();
+        ranked.sort_by_key(|(rank, _)| *rank);
+        for (slot, (_, event)) in group.iter_mut().zip(ranked) {
+            *slot = event;
+        }
+    }
 }
 
 // 21-key mode: Basic keys for 3 octaves (7 notes each)
-const LOW_KEYS: [&str; 7] = ["z", "x", "c", "v", "b", "n", "m"];
-const MID_KEYS: [&str; 7] = ["a", "s", "d", "f", "g", "h", "j"];
-const HIGH_KEYS: [&str; 7] = ["q", "w", "e", "r", "t", "y", "u"];
+pub const LOW_KEYS: [&str; 7] = ["z", "x", "c", "v", "b", "n", "m"];
+pub const MID_KEYS: [&str; 7] = ["a", "s", "d", "f", "g", "h", "j"];
+pub const HIGH_KEYS: [&str; 7] = ["q", "w", "e", "r", "t", "y", "u"];
+
+// Keys available for the second hand's cluster: the number row and the numpad
+pub const SPLIT_KEYS: [&str; 25] = [
+    "1", "2", "3", "4", "5", "6", "7", "8", "9", "0",
+    "num0", "num1", "num2", "num3", "num4", "num5", "num6", "num7", "num8", "num9",
+    "num/", "num*", "num-", "num+", "num.",
+];
+
+
+const MAJOR_SCALE: [i32; 7] = [0, 2, 4, 5, 7, 9, 11];
+
+/// Key layout and tuning for one of the game's instruments
+#[derive(Debug)]
+pub struct InstrumentProfile {
+    pub name: &'static str,
+    // One row of keys per octave, lowest first, with one key per scale degree
+    pub rows: &'static [&'static [&'static str]],
+    pub scale_intervals: &'static [i32],
+    // MIDI note of the first key in the lowest row
+    pub root_note: i32,
+}
 
+impl InstrumentProfile {
+    pub fn keys(&self) -> Vec<&'static str> {
+        self.rows.concat()
+    }
 
-const SCALE_INTERVALS: [i32; 7] = [0, 2, 4, 5, 7, 9, 11];
-const ROOT_NOTE: i32 = 60; // C4
+    /// Lowest and highest playable MIDI notes
+    pub fn range(&self) -> (i32, i32) {
+        let top_interval = self.scale_intervals.last().copied().unwrap_or(0);
+        (self.root_note, self.root_note + 12 * (self.rows.len() as i32 - 1) + top_interval)
+    }
 
-/// Quick function to get MIDI duration without full processing
-pub fn get_midi_duration(path: &str) -> Result<f64, String> {
-    let data = std::fs::read(path).map_err(|e| e.to_string())?;
-    let smf = Smf::parse(&data).map_err(|e| e.to_string())?;
+    fn row(&self, octave: usize) -> &'static [&'static str] {
+        self.rows[octave.min(self.rows.len() - 1)]
+    }
+
+    // Key for a scale degree within an octave row (rows shorter than 7 keys clamp to their last key)
+    fn key(&self, octave: usize, degree: usize) -> String {
+        let row = self.row(octave);
+        row[degree.min(row.len() - 1)].to_string()
+    }
+}
+
+pub const INSTRUMENT_PROFILES: &[InstrumentProfile] = &[
+    // Three-octave instruments (zither, pipa, ...): the full 21-key layout
+    InstrumentProfile {
+        name: "zither",
+        rows: &[&LOW_KEYS, &MID_KEYS, &HIGH_KEYS],
+        scale_intervals: &MAJOR_SCALE,
+        root_note: 48, // C3
+    },
+    // Two-octave wind instruments use the middle and high rows
+    InstrumentProfile {
+        name: "flute",
+        rows: &[&MID_KEYS, &HIGH_KEYS],
+        scale_intervals: &MAJOR_SCALE,
+        root_note: 60, // C4
+    },
+    // Single row of pads
+    InstrumentProfile {
+        name: "drum",
+        rows: &[&MID_KEYS],
+        scale_intervals: &MAJOR_SCALE,
+        root_note: 60, // C4
+    },
+];
+
+// Index into INSTRUMENT_PROFILES used by all note mapping
+static ACTIVE_PROFILE: AtomicUsize = AtomicUsize::new(0);
+
+/// Two-handed play: notes at or above `split_note` go to a second key cluster.
+/// `keys` lists that cluster in the same order as the profile's keys (lowest row first).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HandSplit {
+    pub split_note: u8,
+    pub keys: Vec<String>,
+}
+
+// Active split point (sounding MIDI pitch) and second cluster, if any
+static HAND_SPLIT: Mutex<Option<(i32, Vec<&'static str>)>> = Mutex::new(None);
+
+/// Turn the hand split on (validating its keys against SPLIT_KEYS) or off with None
+pub fn set_hand_split(split: Option<&HandSplit>) -> Result<(), String> {
+    let split = match split {
+        Some(split) => split,
+        None => {
+            *HAND_SPLIT.lock().unwrap() = None;
+            return Ok(());
+        }
+    };
 
-    let ticks_per_quarter = match smf.header.timing {
-        midly::Timing::Metrical(tpq) => tpq.as_int() as f64,
-        _ => 480.0,
+    let expected = active_profile().keys().len();
+    if split.keys.len() != expected {
+        return Err(format!("The second key set needs {} keys, got {}", expected, split.keys.len()));
+    }
+    let keys = split.keys
+        .iter()
+        .map(|key| {
+            let key = key.trim().to_lowercase();
+            SPLIT_KEYS
+                .iter()
+                .copied()
+                .find(|split_key| *split_key == key)
+                .ok_or_else(|| format!("'{}' can't be used in the second key set", key))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    *HAND_SPLIT.lock().unwrap() = Some((split.split_note as i32, keys));
+    Ok(())
+}
+
+/// Keys of the active second cluster, so they can be released with the rest
+pub fn hand_split_keys() -> Vec<&'static str> {
+    HAND_SPLIT.lock().unwrap().as_ref().map(|(_, keys)| keys.clone()).unwrap_or_default()
+}
+
+// Move a key to the same position in the second cluster when the note is above the split
+fn apply_hand_split(key: String, sounding: i32) -> String {
+    let split = HAND_SPLIT.lock().unwrap();
+    let keys = match split.as_ref() {
+        Some((split_note, keys)) if sounding >= *split_note => keys,
+        _ => return key,
     };
+    active_profile()
+        .keys()
+        .iter()
+        .position(|primary| *primary == key)
+        .and_then(|index| keys.get(index))
+        .map(|second| second.to_string())
+        .unwrap_or(key)
+}
 
-    let mut tempo_changes: Vec<(u64, f64)> = Vec::new();
-    let mut max_ticks: u64 = 0;
+pub fn active_profile() -> &'static InstrumentProfile {
+    &INSTRUMENT_PROFILES[ACTIVE_PROFILE.load(Ordering::SeqCst)]
+}
 
-    // Collect tempo changes and find max ticks
-    for track in &smf.tracks {
-        let mut track_time_ticks: u64 = 0;
-        for event in track {
-            track_time_ticks += event.delta.as_int() as u64;
-            if let TrackEventKind::Meta(midly::MetaMessage::Tempo(t)) = event.kind {
-                tempo_changes.push((track_time_ticks, t.as_int() as f64));
+pub fn set_instrument_profile(name: &str) -> Result<(), String> {
+    let index = INSTRUMENT_PROFILES
+        .iter()
+        .position(|profile| profile.name.eq_ignore_ascii_case(name))
+        .ok_or_else(|| format!("Unknown instrument profile: {}", name))?;
+    ACTIVE_PROFILE.store(index, Ordering::SeqCst);
+    Ok(())
+}
+
+/// Every key used by any profile, for releasing everything regardless of the active one
+pub fn all_instrument_keys() -> Vec<&'static str> {
+    let mut keys: Vec<&'static str> = INSTRUMENT_PROFILES
+        .iter()
+        .flat_map(|profile| profile.keys())
+        .collect();
+    keys.sort();
+    keys.dedup();
+    keys
+}
+
+/// Tempo map with cumulative times, so tick -> ms lookups are a binary search
+/// instead of a linear walk over every tempo change
+#[derive(Debug, Clone)]
+pub struct TempoMap {
+    ticks_per_quarter: f64,
+    // (start tick, ms elapsed at start tick, tempo in microseconds per quarter)
+    segments: Vec<(u64, f64, f64)>,
+    // (tick, numerator, denominator), sorted by tick
+    time_signatures: Vec<(u64, u8, u8)>,
+}
+
+impl TempoMap {
+    /// Fixed tempo with no time signature changes (tempo in microseconds per quarter)
+    pub fn constant(tempo: f64) -> Self {
+        TempoMap {
+            ticks_per_quarter: 480.0,
+            segments: vec![(0, 0.0, tempo)],
+            time_signatures: Vec::new(),
+        }
+    }
+
+    fn from_tracks(timing: midly::Timing, tracks: &[midly::Track]) -> Self {
+        let ticks_per_quarter = match timing {
+            midly::Timing::Metrical(tpq) => tpq.as_int() as f64,
+            _ => 480.0, // Default
+        };
+
+        // Collect all tempo and time signature changes from all tracks
+        let mut tempo_changes: Vec<(u64, f64)> = Vec::new();
+        let mut time_signatures: Vec<(u64, u8, u8)> = Vec::new();
+        for track in tracks {
+            let mut track_time_ticks: u64 = 0;
+            for event in track {
+                track_time_ticks += event.delta.as_int() as u64;
+                match event.kind {
+                    TrackEventKind::Meta(midly::MetaMessage::Tempo(t)) => {
+                        tempo_changes.push((track_time_ticks, t.as_int() as f64));
+                    }
+                    TrackEventKind::Meta(midly::MetaMessage::TimeSignature(numerator, denom_pow, _, _)) => {
+                        // Denominator is stored as a power of two
+                        let denominator = 1u8.checked_shl(denom_pow as u32).unwrap_or(4);
+                        time_signatures.push((track_time_ticks, numerator.max(1), denominator.max(1)));
+                    }
+                    _ => {}
+                }
             }
         }
-        if track_time_ticks > max_ticks {
-            max_ticks = track_time_ticks;
+        tempo_changes.sort_by_key(|(time, _)| *time);
+        time_signatures.sort_by_key(|(time, _, _)| *time);
+
+        // Precompute the elapsed time at the start of each tempo segment
+        let mut segments = Vec::with_capacity(tempo_changes.len() + 1);
+        segments.push((0u64, 0.0, 500_000.0)); // Default 120 BPM
+        for (change_tick, new_tempo) in tempo_changes {
+            let &(last_tick, last_ms, current_tempo) = segments.last().unwrap();
+            let delta_ticks = change_tick - last_tick;
+            let ms = last_ms + delta_ticks as f64 / ticks_per_quarter * current_tempo / 1000.0;
+            segments.push((change_tick, ms, new_tempo));
         }
+
+        TempoMap { ticks_per_quarter, segments, time_signatures }
     }
-    tempo_changes.sort_by_key(|(time, _)| *time);
 
-    // Convert max ticks to milliseconds
-    let mut result_ms = 0.0;
-    let mut last_tick = 0u64;
-    let mut current_tempo = 500_000.0; // Default 120 BPM
+    /// Convert milliseconds back to an absolute tick position
+    fn ms_to_ticks(&self, ms: f64) -> f64 {
+        let idx = self.segments.partition_point(|&(_, start_ms, _)| start_ms <= ms);
+        let (start_tick, start_ms, tempo) = self.segments[idx.saturating_sub(1)];
+        start_tick as f64 + (ms - start_ms).max(0.0) * 1000.0 / tempo * self.ticks_per_quarter
+    }
 
-    for &(change_tick, new_tempo) in &tempo_changes {
-        if change_tick >= max_ticks {
-            break;
+    /// Convert a fractional tick position to milliseconds
+    fn fractional_ticks_to_ms(&self, ticks: f64) -> f64 {
+        let idx = self.segments.partition_point(|&(tick, _, _)| (tick as f64) < ticks);
+        let (start_tick, start_ms, tempo) = self.segments[idx.saturating_sub(1)];
+        start_ms + (ticks - start_tick as f64).max(0.0) / self.ticks_per_quarter * tempo / 1000.0
+    }
+
+    /// Length of one beat in ticks at a tick position (from the time signature denominator)
+    fn beat_ticks_at(&self, ticks: f64) -> f64 {
+        let idx = self.time_signatures.partition_point(|&(tick, _, _)| (tick as f64) <= ticks);
+        let denominator = match idx {
+            0 => 4,
+            _ => self.time_signatures[idx - 1].2,
+        };
+        self.ticks_per_quarter * 4.0 / denominator as f64
+    }
+
+    /// Move an event time to its swung position. Each beat is split in two halves and the
+    /// midpoint is pushed to `ratio` of the beat; the warp is monotonic so order is preserved.
+    pub fn swing_time_ms(&self, time_ms: u64, ratio: f64) -> u64 {
+        let ticks = self.ms_to_ticks(time_ms as f64);
+        let beat_ticks = self.beat_ticks_at(ticks);
+        let beat_start = (ticks / beat_ticks).floor() * beat_ticks;
+        let phase = (ticks - beat_start) / beat_ticks;
+
+        let swung_phase = if phase < 0.5 {
+            phase / 0.5 * ratio
+        } else {
+            ratio + (phase - 0.5) / 0.5 * (1.0 - ratio)
+        };
+
+        self.fractional_ticks_to_ms(beat_start + swung_phase * beat_ticks).round() as u64
+    }
+
+    /// Same tempo map played `speed` times faster
+    fn scaled(&self, speed: f64) -> TempoMap {
+        TempoMap {
+            ticks_per_quarter: self.ticks_per_quarter,
+            segments: self.segments
+                .iter()
+                .map(|&(tick, ms, tempo)| (tick, ms / speed, tempo / speed))
+                .collect(),
+            time_signatures: self.time_signatures.clone(),
         }
-        let delta_ticks = change_tick - last_tick;
-        result_ms += delta_ticks as f64 / ticks_per_quarter * current_tempo / 1000.0;
-        last_tick = change_tick;
-        current_tempo = new_tempo;
     }
 
-    let delta_ticks = max_ticks - last_tick;
-    result_ms += delta_ticks as f64 / ticks_per_quarter * current_tempo / 1000.0;
+    /// Same tempo map with time starting `ms` later, so bars stay aligned after trimming
+    fn shifted(&self, ms: f64) -> TempoMap {
+        TempoMap {
+            ticks_per_quarter: self.ticks_per_quarter,
+            segments: self.segments
+                .iter()
+                .map(|&(tick, start_ms, tempo)| (tick, start_ms - ms, tempo))
+                .collect(),
+            time_signatures: self.time_signatures.clone(),
+        }
+    }
+
+    /// Bar, beat and tempo at a given time (defaults to 4/4 without a time signature)
+    pub fn musical_position(&self, ms: f64) -> MusicalPosition {
+        let ticks = self.ms_to_ticks(ms);
+
+        let idx = self.segments.partition_point(|&(_, start_ms, _)| start_ms <= ms);
+        let tempo = self.segments[idx.saturating_sub(1)].2;
+
+        // Count whole bars in each time signature section up to `ticks`
+        let mut bars = 0.0;
+        let mut section_start = 0.0;
+        let (mut numerator, mut denominator) = (4u8, 4u8);
+        for &(change_tick, new_numerator, new_denominator) in &self.time_signatures {
+            let change_tick = change_tick as f64;
+            if change_tick > ticks {
+                break;
+            }
+            let bar_ticks = self.ticks_per_quarter * 4.0 / denominator as f64 * numerator as f64;
+            bars += ((change_tick - section_start) / bar_ticks).round();
+            section_start = change_tick;
+            numerator = new_numerator;
+            denominator = new_denominator;
+        }
+
+        let beat_ticks = self.ticks_per_quarter * 4.0 / denominator as f64;
+        let bar_ticks = beat_ticks * numerator as f64;
+        let elapsed = ticks - section_start;
+        bars += (elapsed / bar_ticks).floor();
+        let beat = ((elapsed % bar_ticks) / beat_ticks).floor();
 
-    Ok(result_ms / 1000.0) // Convert to seconds
+        MusicalPosition {
+            bar: bars as u32 + 1,
+            beat: beat as u32 + 1,
+            bpm: 60_000_000.0 / tempo,
+        }
+    }
+
+    /// Time of a beat counted from the song start (0 = start, fractions allowed). Beat length
+    /// follows the time signature and its duration follows the tempo, so beats stay musical
+    /// through tempo changes.
+    pub fn beat_to_ms(&self, beat: f64) -> f64 {
+        let mut remaining = beat.max(0.0);
+        let mut section_start = 0.0;
+        let mut denominator = 4u8;
+        for &(change_tick, _, new_denominator) in &self.time_signatures {
+            let change_tick = change_tick as f64;
+            let section_beats = (change_tick - section_start) / (self.ticks_per_quarter * 4.0 / denominator as f64);
+            if remaining < section_beats {
+                break;
+            }
+            remaining -= section_beats;
+            section_start = change_tick;
+            denominator = new_denominator;
+        }
+        let beat_ticks = self.ticks_per_quarter * 4.0 / denominator as f64;
+        self.fractional_ticks_to_ms(section_start + remaining * beat_ticks)
+    }
+
+    /// Beats from the song start at a given time (inverse of beat_to_ms)
+    pub fn ms_to_beat(&self, ms: f64) -> f64 {
+        let ticks = self.ms_to_ticks(ms);
+        let mut beats = 0.0;
+        let mut section_start = 0.0;
+        let mut denominator = 4u8;
+        for &(change_tick, _, new_denominator) in &self.time_signatures {
+            let change_tick = change_tick as f64;
+            if change_tick > ticks {
+                break;
+            }
+            beats += (change_tick - section_start) / (self.ticks_per_quarter * 4.0 / denominator as f64);
+            section_start = change_tick;
+            denominator = new_denominator;
+        }
+        beats + (ticks - section_start) / (self.ticks_per_quarter * 4.0 / denominator as f64)
+    }
+
+    /// Convert an absolute tick position to milliseconds
+    fn ticks_to_ms(&self, ticks: u64) -> f64 {
+        // Last segment that starts strictly before `ticks` (a change at `ticks` has no effect yet)
+        let idx = self.segments.partition_point(|&(tick, _, _)| tick < ticks);
+        let (start_tick, start_ms, tempo) = self.segments[idx.saturating_sub(1)];
+        start_ms + (ticks - start_tick) as f64 / self.ticks_per_quarter * tempo / 1000.0
+    }
 }
 
-pub fn load_midi(path: &str) -> Result<MidiData, String> {
+/// Tracks that make up the timeline to play. Format 0/1 files merge every track,
+/// but format 2 tracks are independent patterns, so only the selected one is used.
+fn timeline_tracks<'a, 'b>(smf: &'b Smf<'a>, sequence: usize) -> Result<&'b [midly::Track<'a>], String> {
+    if matches!(smf.header.format, midly::Format::Sequential) {
+        if sequence >= smf.tracks.len() {
+            return Err(format!("Sequence {} not found ({} available)", sequence + 1, smf.tracks.len()));
+        }
+        Ok(&smf.tracks[sequence..=sequence])
+    } else {
+        Ok(&smf.tracks)
+    }
+}
+
+/// Header details of a file that parsed as MIDI, shown before importing
+#[derive(Debug, Clone, Serialize)]
+pub struct MidiValidation {
+    pub format: u8,
+    pub track_count: usize,
+    pub timing: String,
+    pub event_count: usize,
+    pub duration: f64,
+}
+
+/// Check that a file parses as MIDI and summarize it
+pub fn validate_midi(path: &str) -> Result<MidiValidation, String> {
     let data = std::fs::read(path).map_err(|e| e.to_string())?;
-    let smf = Smf::parse(&data).map_err(|e| e.to_string())?;
+    let smf = Smf::parse(&data).map_err(|e| format!("Not a valid MIDI file: {}", e))?;
 
-    let mut events = Vec::new();
-    let _current_time_ms: u64 = 0;
-    let ticks_per_quarter = match smf.header.timing {
-        midly::Timing::Metrical(tpq) => tpq.as_int() as f64,
-        _ => 480.0, // Default
+    let format = match smf.header.format {
+        midly::Format::SingleTrack => 0,
+        midly::Format::Parallel => 1,
+        midly::Format::Sequential => 2,
+    };
+    let timing = match smf.header.timing {
+        midly::Timing::Metrical(tpq) => format!("{} ticks per beat", tpq.as_int()),
+        midly::Timing::Timecode(fps, subframe) => format!("{} fps, {} ticks per frame", fps.as_f32(), subframe),
     };
+    let event_count = smf.tracks.iter().map(|track| track.len()).sum();
+    let (events, _, _, _) = read_events(path, 0)?;
+
+    Ok(MidiValidation {
+        format,
+        track_count: smf.tracks.len(),
+        timing,
+        event_count,
+        duration: events_duration(&events),
+    })
+}
 
-    let _tempo = 500_000.0; // Default tempo (120 BPM)
-    let mut tempo_changes: Vec<(u64, f64)> = Vec::new();
+/// Summary of one independent sequence in a format 2 file
+#[derive(Debug, Clone, Serialize)]
+pub struct SequenceInfo {
+    pub index: usize,
+    pub note_count: usize,
+    pub duration: f64,
+}
 
-    // First pass: collect all tempo changes from all tracks
-    for track in &smf.tracks {
-        let mut track_time_ticks: u64 = 0;
-        for event in track {
-            track_time_ticks += event.delta.as_int() as u64;
-            if let TrackEventKind::Meta(midly::MetaMessage::Tempo(t)) = event.kind {
-                tempo_changes.push((track_time_ticks, t.as_int() as f64));
-            }
+/// List the independent sequences of a format 2 file (a single entry for format 0/1)
+pub fn list_sequences(path: &str) -> Result<Vec<SequenceInfo>, String> {
+    let count = if crate::keysheet::is_key_sheet(path) {
+        1
+    } else {
+        let data = std::fs::read(path).map_err(|e| e.to_string())?;
+        let smf = Smf::parse(&data).map_err(|e| e.to_string())?;
+        if matches!(smf.header.format, midly::Format::Sequential) {
+            smf.tracks.len()
+        } else {
+            1
         }
+    };
+
+    let mut sequences = Vec::with_capacity(count);
+    for index in 0..count {
+        let midi_data = load_midi_sequence(path, index)?;
+        let note_count = midi_data.events.iter()
+            .filter(|e| matches!(e.event_type, EventType::NoteOn | EventType::KeyDown(_)))
+            .count();
+        sequences.push(SequenceInfo {
+            index,
+            note_count,
+            duration: midi_data.duration,
+        });
     }
-    tempo_changes.sort_by_key(|(time, _)| *time);
+    Ok(sequences)
+}
 
-    // Function to convert ticks to milliseconds with tempo changes
-    let ticks_to_ms = |ticks: u64| -> u64 {
-        let mut result_ms = 0.0;
-        let mut last_tick = 0u64;
-        let mut current_tempo = 500_000.0;
+// General MIDI instrument names, by program number
+const GM_PROGRAM_NAMES: [&str; 128] = [
+    "Acoustic Grand Piano", "Bright Acoustic Piano", "Electric Grand Piano", "Honky-tonk Piano", "Electric Piano 1", "Electric Piano 2", "Harpsichord", "Clavinet",
+    "Celesta", "Glockenspiel", "Music Box", "Vibraphone", "Marimba", "Xylophone", "Tubular Bells", "Dulcimer",
+    "Drawbar Organ", "Percussive Organ", "Rock Organ", "Church Organ", "Reed Organ", "Accordion", "Harmonica", "Tango Accordion",
+    "Acoustic Guitar (nylon)", "Acoustic Guitar (steel)", "Electric Guitar (jazz)", "Electric Guitar (clean)", "Electric Guitar (muted)", "Overdriven Guitar", "Distortion Guitar", "Guitar Harmonics",
+    "Acoustic Bass", "Electric Bass (finger)", "Electric Bass (pick)", "Fretless Bass", "Slap Bass 1", "Slap Bass 2", "Synth Bass 1", "Synth Bass 2",
+    "Violin", "Viola", "Cello", "Contrabass", "Tremolo Strings", "Pizzicato Strings", "Orchestral Harp", "Timpani",
+    "String Ensemble 1", "String Ensemble 2", "Synth Strings 1", "Synth Strings 2", "Choir Aahs", "Voice Oohs", "Synth Voice", "Orchestra Hit",
+    "Trumpet", "Trombone", "Tuba", "Muted Trumpet", "French Horn", "Brass Section", "Synth Brass 1", "Synth Brass 2",
+    "Soprano Sax", "Alto Sax", "Tenor Sax", "Baritone Sax", "Oboe", "English Horn", "Bassoon", "Clarinet",
+    "Piccolo", "Flute", "Recorder", "Pan Flute", "Blown Bottle", "Shakuhachi", "Whistle", "Ocarina",
+    "Lead 1 (square)", "Lead 2 (sawtooth)", "Lead 3 (calliope)", "Lead 4 (chiff)", "Lead 5 (charang)", "Lead 6 (voice)", "Lead 7 (fifths)", "Lead 8 (bass + lead)",
+    "Pad 1 (new age)", "Pad 2 (warm)", "Pad 3 (polysynth)", "Pad 4 (choir)", "Pad 5 (bowed)", "Pad 6 (metallic)", "Pad 7 (halo)", "Pad 8 (sweep)",
+    "FX 1 (rain)", "FX 2 (soundtrack)", "FX 3 (crystal)", "FX 4 (atmosphere)", "FX 5 (brightness)", "FX 6 (goblins)", "FX 7 (echoes)", "FX 8 (sci-fi)",
+    "Sitar", "Banjo", "Shamisen", "Koto", "Kalimba", "Bagpipe", "Fiddle", "Shanai",
+    "Tinkle Bell", "Agogo", "Steel Drums", "Woodblock", "Taiko Drum", "Melodic Tom", "Synth Drum", "Reverse Cymbal",
+    "Guitar Fret Noise", "Breath Noise", "Seashore", "Bird Tweet", "Telephone Ring", "Helicopter", "Applause", "Gunshot",
+];
+
+// MIDI channel 10 plays the drum kit whatever its program
+const PERCUSSION_CHANNEL: u8 = 9;
+
+/// General MIDI instrument name of a program number
+pub fn gm_program_name(program: u8) -> &'static str {
+    GM_PROGRAM_NAMES[(program & 0x7F) as usize]
+}
 
-        for &(change_tick, new_tempo) in &tempo_changes {
-            if change_tick >= ticks {
-                break;
+// How well a track suits being the melody: lead instruments (solo strings, brass, reeds,
+// pipes, synth leads) first, pads and drums last
+fn melody_preference(track: &TrackInfo) -> u8 {
+    if track.percussion {
+        return 0;
+    }
+    match track.program {
+        Some(40 | 41 | 56..=87 | 104..=111) => 2,
+        Some(88..=95) => 0,
+        _ => 1,
+    }
+}
+
+/// Summary of one track of a MIDI file, for choosing which parts to play
+#[derive(Debug, Clone, Serialize)]
+pub struct TrackInfo {
+    pub index: usize,
+    pub name: Option<String>,
+    pub note_count: usize,
+    pub lowest_note: Option<u8>,
+    pub highest_note: Option<u8>,
+    // First program change of the track and its General MIDI name
+    pub program: Option<u8>,
+    pub instrument: Option<String>,
+    // All notes on the drum channel
+    pub percussion: bool,
+    // Best guess for the melody: a lead instrument if there is one, then the most notes
+    pub melody: bool,
+}
+
+/// List the tracks of a MIDI file with their names, note counts and pitch ranges
+pub fn list_tracks(path: &str) -> Result<Vec<TrackInfo>, String> {
+    if crate::keysheet::is_key_sheet(path) {
+        return Err("Key sheets have no tracks".to_string());
+    }
+    let data = std::fs::read(path).map_err(|e| e.to_string())?;
+    let smf = Smf::parse(&data).map_err(|e| e.to_string())?;
+
+    let mut tracks: Vec<TrackInfo> = smf.tracks.iter().enumerate().map(|(index, track)| {
+        let mut info = TrackInfo {
+            index,
+            name: None,
+            note_count: 0,
+            lowest_note: None,
+            highest_note: None,
+            program: None,
+            instrument: None,
+            percussion: false,
+            melody: false,
+        };
+        let mut drum_notes = 0;
+        for event in track {
+            match event.kind {
+                TrackEventKind::Meta(MetaMessage::TrackName(text)) if info.name.is_none() => {
+                    let name = String::from_utf8_lossy(text).trim().to_string();
+                    if !name.is_empty() {
+                        info.name = Some(name);
+                    }
+                }
+                TrackEventKind::Midi { channel, message: MidiMessage::ProgramChange { program } }
+                    if info.program.is_none() && channel.as_int() != PERCUSSION_CHANNEL =>
+                {
+                    info.program = Some(program.as_int());
+                }
+                TrackEventKind::Midi { channel, message: MidiMessage::NoteOn { key, vel } } if vel > 0 => {
+                    let key = key.as_int();
+                    info.note_count += 1;
+                    if channel.as_int() == PERCUSSION_CHANNEL {
+                        drum_notes += 1;
+                    }
+                    info.lowest_note = Some(info.lowest_note.map_or(key, |low| low.min(key)));
+                    info.highest_note = Some(info.highest_note.map_or(key, |high| high.max(key)));
+                }
+                _ => {}
             }
-            // Add time up to this tempo change
-            let delta_ticks = change_tick - last_tick;
-            result_ms += delta_ticks as f64 / ticks_per_quarter * current_tempo / 1000.0;
-            last_tick = change_tick;
-            current_tempo = new_tempo;
         }
+        info.percussion = info.note_count > 0 && drum_notes == info.note_count;
+        info.instrument = if info.percussion {
+            Some("Percussion".to_string())
+        } else {
+            info.program.map(|program| gm_program_name(program).to_string())
+        };
+        info
+    }).collect();
+
+    // First track wins a tie
+    if let Some(melody) = tracks
+        .iter()
+        .filter(|t| t.note_count > 0)
+        .max_by_key(|t| (melody_preference(t), t.note_count, std::cmp::Reverse(t.index)))
+    {
+        let index = melody.index;
+        tracks[index].melody = true;
+    }
+    Ok(tracks)
+}
+
+/// Human-readable note about how the file will be played, if it needs one
+pub fn get_format_note(path: &str) -> Option<String> {
+    let data = std::fs::read(path).ok()?;
+    let smf = Smf::parse(&data).ok()?;
+
+    if matches!(smf.header.format, midly::Format::Sequential) && smf.tracks.len() > 1 {
+        Some(format!(
+            "Format 2 file with {} independent sequences; only one sequence plays at a time",
+            smf.tracks.len()
+        ))
+    } else {
+        None
+    }
+}
+
+/// Quick function to get MIDI duration without full processing
+pub fn get_midi_duration(path: &str) -> Result<f64, String> {
+    if crate::keysheet::is_key_sheet(path) {
+        return crate::keysheet::load_key_sheet(path).map(|sheet| sheet.duration);
+    }
+
+    // Same events and duration definition as load_midi, so the list matches the seek bar
+    let (events, _, _, _) = read_events(path, 0)?;
+    Ok(events_duration(&events))
+}
+
+pub fn load_midi(path: &str) -> Result<MidiData, String> {
+    load_midi_sequence(path, 0)
+}
+
+/// Parse a sequence into time-sorted events, timed through the file's tempo map
+fn read_events(
+    path: &str,
+    sequence: usize,
+) -> Result<(Vec<TimedEvent>, Vec<Marker>, TempoMap, VolumeTimeline), String> {
+    let data = std::fs::read(path).map_err(|e| e.to_string())?;
+    let smf = Smf::parse(&data).map_err(|e| e.to_string())?;
 
-        // Add remaining time
-        let delta_ticks = ticks - last_tick;
-        result_ms += delta_ticks as f64 / ticks_per_quarter * current_tempo / 1000.0;
-        result_ms as u64
-    };
+    let mut events = Vec::new();
+    let mut markers = Vec::new();
+    let mut volumes = VolumeTimeline::default();
+    let tracks = timeline_tracks(&smf, sequence)?;
+    let tempo_map = TempoMap::from_tracks(smf.header.timing, tracks);
+    // Format 2 plays a single track, so number it as in the file
+    let first_track = if tracks.len() == smf.tracks.len() { 0 } else { sequence };
 
     // Second pass: process all tracks with proper timing
-    for track in &smf.tracks {
+    for (index, track) in tracks.iter().enumerate() {
+        let track_index = first_track + index;
         let mut track_time_ticks: u64 = 0;
 
         for event in track {
             track_time_ticks += event.delta.as_int() as u64;
-            let time_ms = ticks_to_ms(track_time_ticks);
+            let time_ms = tempo_map.ticks_to_ms(track_time_ticks) as u64;
 
-            if let TrackEventKind::Midi { message, .. } = event.kind {
+            if let TrackEventKind::Meta(MetaMessage::Marker(text) | MetaMessage::CuePoint(text)) = event.kind {
+                markers.push(Marker {
+                    time_ms,
+                    name: String::from_utf8_lossy(text).trim().to_string(),
+                });
+            }
+
+            if let TrackEventKind::Midi { channel, message } = event.kind {
+                let channel = channel.as_int();
                 match message {
                     MidiMessage::NoteOn { key, vel } => {
                         if vel > 0 {
@@ -174,6 +1190,8 @@ pub fn load_midi(path: &str) -> Result<MidiData, String> {
                                 time_ms,
                                 event_type: EventType::NoteOn,
                                 note: key.as_int(),
+                                channel,
+                                track: track_index,
                             });
                         } else {
                             // Note on with velocity 0 is treated as note off
@@ -181,6 +1199,8 @@ pub fn load_midi(path: &str) -> Result<MidiData, String> {
                                 time_ms,
                                 event_type: EventType::NoteOff,
                                 note: key.as_int(),
+                                channel,
+                                track: track_index,
                             });
                         }
                     }
@@ -189,23 +1209,143 @@ pub fn load_midi(path: &str) -> Result<MidiData, String> {
                             time_ms,
                             event_type: EventType::NoteOff,
                             note: key.as_int(),
+                            channel,
+                            track: track_index,
+                        });
+                    }
+                    MidiMessage::PitchBend { bend } => {
+                        events.push(TimedEvent {
+                            time_ms,
+                            event_type: EventType::PitchBend(bend.as_int()),
+                            note: 0,
+                            channel,
+                            track: track_index,
                         });
                     }
+                    // Channel volume
+                    MidiMessage::Controller { controller, value } if controller.as_int() == 7 => {
+                        volumes.push(channel, time_ms, value.as_int());
+                    }
                     _ => {}
                 }
             }
         }
     }
 
+    // Markers become events too, so playback reaches them in order with the notes
+    markers.sort_by_key(|m| m.time_ms);
+    for (index, marker) in markers.iter().enumerate() {
+        events.push(TimedEvent {
+            time_ms: marker.time_ms,
+            event_type: EventType::Marker(index),
+            note: 0,
+            channel: 0,
+            track: 0,
+        });
+    }
+
     // Sort events by time
-    events.sort_by_key(|e| e.time_ms);
+    sort_events(&mut events);
+    volumes.sort();
 
-    // Calculate duration
-    let duration = if !events.is_empty() {
-        events.last().unwrap().time_ms as f64 / 1000.0
-    } else {
-        0.0
-    };
+    Ok((events, markers, tempo_map, volumes))
+}
+
+/// Song length in seconds: the time of the last note release (or last event if nothing is released)
+pub fn events_duration(events: &[TimedEvent]) -> f64 {
+    let end_ms = events
+        .iter()
+        .rev()
+        .find(|e| matches!(e.event_type, EventType::NoteOff | EventType::KeyUp(_)))
+        .or(events.last())
+        .map(|e| e.time_ms)
+        .unwrap_or(0);
+    end_ms as f64 / 1000.0
+}
+
+/// Merge two parts of a duet into one song, with `part_b` starting `offset_ms` later.
+/// Tempo comes from the first part; the second part's tracks are numbered after the first's.
+pub fn merge_duet(part_a: MidiData, part_b: MidiData, offset_ms: u64) -> MidiData {
+    let track_offset = part_a.events.iter().map(|e| e.track + 1).max().unwrap_or(0);
+
+    // Markers are re-indexed after merging, so drop their events for now
+    let not_marker = |e: &TimedEvent| !matches!(e.event_type, EventType::Marker(_));
+    let mut events: Vec<TimedEvent> = part_a.events.into_iter().filter(not_marker).collect();
+    events.extend(part_b.events.into_iter().filter(not_marker).map(|event| TimedEvent {
+        time_ms: event.time_ms + offset_ms,
+        track: event.track + track_offset,
+        ..event
+    }));
+
+    let volumes = part_a.volumes.merged(&part_b.volumes, offset_ms);
+    let mut markers = part_a.markers;
+    markers.extend(part_b.markers.into_iter().map(|marker| Marker {
+        time_ms: marker.time_ms + offset_ms,
+        name: marker.name,
+    }));
+    markers.sort_by_key(|m| m.time_ms);
+    for (index, marker) in markers.iter().enumerate() {
+        events.push(TimedEvent {
+            time_ms: marker.time_ms,
+            event_type: EventType::Marker(index),
+            note: 0,
+            channel: 0,
+            track: 0,
+        });
+    }
+    sort_events(&mut events);
+
+    MidiData {
+        duration: events_duration(&events),
+        transpose: detect_best_transpose(&events),
+        tempo_map: part_a.tempo_map,
+        markers,
+        volumes,
+        events,
+    }
+}
+
+/// Most notes sounding at once
+pub fn max_polyphony(events: &[TimedEvent]) -> usize {
+    let mut sounding: std::collections::HashSet<NoteKey> = std::collections::HashSet::new();
+    let mut max_polyphony = 0;
+    for event in events {
+        match event.event_type {
+            EventType::NoteOn => {
+                sounding.insert(event.note_key());
+                max_polyphony = max_polyphony.max(sounding.len());
+            }
+            EventType::NoteOff => {
+                sounding.remove(&event.note_key());
+            }
+            _ => {}
+        }
+    }
+    max_polyphony
+}
+
+/// Warning for a merged duet with more notes at once than can be pressed cleanly
+pub fn duet_warning(midi_data: &MidiData) -> Option<String> {
+    let polyphony = max_polyphony(&midi_data.events);
+    (polyphony > PLAYABLE_POLYPHONY).then(|| {
+        format!(
+            "Up to {} notes sound at once in the combined parts; try chord reduction or a polyphony-friendly note mode",
+            polyphony
+        )
+    })
+}
+
+/// Load a file, picking `sequence` when it is a format 2 (independent sequences) file
+pub fn load_midi_sequence(path: &str, sequence: usize) -> Result<MidiData, String> {
+    if crate::keysheet::is_key_sheet(path) {
+        return crate::keysheet::load_key_sheet(path);
+    }
+
+    let (events, markers, tempo_map, volumes) = read_events(path, sequence)?;
+    if !events.iter().any(|e| matches!(e.event_type, EventType::NoteOn)) {
+        return Err("MIDI file contains no notes".to_string());
+    }
+    let duration = events_duration(&events);
 
     // Detect best transpose (port of Python heuristic)
     let transpose = detect_best_transpose(&events);
@@ -215,14 +1355,367 @@ pub fn load_midi(path: &str) -> Result<MidiData, String> {
         events,
         duration,
         transpose,
+        tempo_map,
+        markers,
+        volumes,
     })
 }
 
+/// Snap event times to the nearest multiple of `grid_ms` (timing quantize).
+/// Keeps each NoteOff after its NoteOn so no note collapses to zero length.
+pub fn quantize_events(events: &[TimedEvent], grid_ms: u64) -> Vec<TimedEvent> {
+    if grid_ms == 0 {
+        return events.to_vec();
+    }
+
+    let snap = |time_ms: u64| (time_ms + grid_ms / 2) / grid_ms * grid_ms;
+
+    // Snapped NoteOn times per (track, channel, note), oldest first, to pair with their NoteOffs
+    let mut pending_on: std::collections::HashMap<NoteKey, std::collections::VecDeque<u64>> =
+        std::collections::HashMap::new();
+    let mut quantized = Vec::with_capacity(events.len());
+
+    for event in events {
+        let mut time_ms = snap(event.time_ms);
+        match event.event_type {
+            EventType::NoteOn => {
+                pending_on.entry(event.note_key()).or_default().push_back(time_ms);
+            }
+            EventType::NoteOff => {
+                if let Some(on_time) = pending_on.get_mut(&event.note_key()).and_then(|q| q.pop_front()) {
+                    if time_ms <= on_time {
+                        time_ms = on_time + grid_ms;
+                    }
+                }
+            }
+            EventType::PitchBend(_) | EventType::KeyDown(_) | EventType::KeyUp(_) | EventType::Marker(_) => {}
+        }
+        quantized.push(TimedEvent {
+            time_ms,
+            ..event.clone()
+        });
+    }
+
+    // Stable sort keeps the original order for events that land on the same grid line
+    sort_events(&mut quantized);
+    quantized
+}
+
+/// Remove notes shorter than `min_ms` (grace notes, trill spam) along with their releases.
+/// Other events keep their times, so the rest of the song is unaffected.
+pub fn drop_short_notes(events: &[TimedEvent], min_ms: u64) -> Vec<TimedEvent> {
+    // Open presses per (track, channel, note), oldest first
+    let mut open: std::collections::HashMap<NoteKey, std::collections::VecDeque<usize>> =
+        std::collections::HashMap::new();
+    let mut dropped = vec![false; events.len()];
+
+    let mut start = 0;
+    while start < events.len() {
+        let time_ms = events[start].time_ms;
+        let end = start + events[start..].iter().take_while(|e| e.time_ms == time_ms).count();
+
+        // Releases pair with presses from earlier timestamps first, wherever they sit at this
+        // one. Any left over end a press at this same time: a zero-length note.
+        let mut unpaired: std::collections::HashMap<NoteKey, std::collections::VecDeque<usize>> =
+            std::collections::HashMap::new();
+        for (index, event) in events.iter().enumerate().take(end).skip(start) {
+            if !matches!(event.event_type, EventType::NoteOff) {
+                continue;
+            }
+            match open.get_mut(&event.note_key()).and_then(|presses| presses.pop_front()) {
+                Some(on_index) => {
+                    if time_ms - events[on_index].time_ms < min_ms {
+                        dropped[on_index] = true;
+                        dropped[index] = true;
+                    }
+                }
+                None => unpaired.entry(event.note_key()).or_default().push_back(index),
+            }
+        }
+        for (index, event) in events.iter().enumerate().take(end).skip(start) {
+            if !matches!(event.event_type, EventType::NoteOn) {
+                continue;
+            }
+            match unpaired.get_mut(&event.note_key()).and_then(|releases| releases.pop_front()) {
+                Some(off_index) => {
+                    if min_ms > 0 {
+                        dropped[index] = true;
+                        dropped[off_index] = true;
+                    }
+                }
+                None => open.entry(event.note_key()).or_default().push_back(index),
+            }
+        }
+        start = end;
+    }
+
+    events
+        .iter()
+        .zip(dropped)
+        .filter(|(_, dropped)| !dropped)
+        .map(|(event, _)| event.clone())
+        .collect()
+}
+
+/// Remove notes played while their channel's volume (CC7) is below `min_volume`, along
+/// with their releases. Channels without CC7 count as the General MIDI default of 100.
+pub fn gate_by_volume(events: &[TimedEvent], volumes: &VolumeTimeline, min_volume: u8) -> Vec<TimedEvent> {
+    // Releases still owed to a dropped press, per (track, channel, note)
+    let mut owed: std::collections::HashMap<NoteKey, u32> = std::collections::HashMap::new();
+    events
+        .iter()
+        .filter(|event| {
+            let note_key = event.note_key();
+            match event.event_type {
+                EventType::NoteOn if volumes.volume_at(event.channel, event.time_ms) < min_volume => {
+                    *owed.entry(note_key).or_insert(0) += 1;
+                    false
+                }
+                EventType::NoteOff => match owed.get_mut(&note_key) {
+                    Some(count) if *count > 0 => {
+                        *count -= 1;
+                        false
+                    }
+                    _ => true,
+                },
+                _ => true,
+            }
+        })
+        .cloned()
+        .collect()
+}
+
+/// Collapse notes that start at the same time into one voice. Notes of tracks outside
+/// `tracks` are left alone (they aren't played), and dropped notes lose their releases too.
+pub fn reduce_chords(
+    events: &[TimedEvent],
+    mode: ChordMode,
+    tracks: Option<&std::collections::HashSet<usize>>,
+) -> Vec<TimedEvent> {
+    if mode == ChordMode::Off {
+        return events.to_vec();
+    }
+
+    let in_chord = |event: &TimedEvent| {
+        matches!(event.event_type, EventType::NoteOn) && tracks.is_none_or(|tracks| tracks.contains(&event.track))
+    };
+    // Releases still owed by dropped presses, per (track, channel, note)
+    let mut dropped: std::collections::HashMap<NoteKey, u32> = std::collections::HashMap::new();
+    let mut reduced = Vec::with_capacity(events.len());
+
+    let mut start = 0;
+    while start < events.len() {
+        let time_ms = events[start].time_ms;
+        let end = start + events[start..].iter().take_while(|e| e.time_ms == time_ms).count();
+        let group = &events[start..end];
+        start = end;
+
+        let chord: Vec<u8> = group.iter().filter(|e| in_chord(e)).map(|e| e.note).collect();
+        let mut keep = if chord.len() > 1 { Some(chord_voice(&chord, mode)) } else { None };
+
+        for event in group {
+            match event.event_type {
+                EventType::NoteOn if chord.len() > 1 && in_chord(event) => {
+                    if keep == Some(event.note) {
+                        // Only the first press of the kept pitch
+                        keep = None;
+                        reduced.push(event.clone());
+                    } else {
+                        *dropped.entry(event.note_key()).or_insert(0) += 1;
+                    }
+                }
+                EventType::NoteOff => {
+                    match dropped.get_mut(&event.note_key()) {
+                        Some(owed) if *owed > 0 => *owed -= 1,
+                        _ => reduced.push(event.clone()),
+                    }
+                }
+                _ => reduced.push(event.clone()),
+            }
+        }
+    }
+    reduced
+}
+
+// The note of a chord to keep for a reduction mode
+fn chord_voice(notes: &[u8], mode: ChordMode) -> u8 {
+    let lowest = notes.iter().copied().min().unwrap_or(0);
+    match mode {
+        ChordMode::Top => notes.iter().copied().max().unwrap_or(0),
+        ChordMode::Off | ChordMode::Bottom => lowest,
+        ChordMode::Root => chord_root(notes).unwrap_or(lowest),
+    }
+}
+
+// Thirds, fifth and sevenths above a root
+const CHORD_TONE_INTERVALS: [u8; 5] = [3, 4, 7, 10, 11];
+
+// Lowest note of the pitch class the other notes stack best on as thirds/fifths/sevenths
+// (the bass wins a tie), or None if no note has any chord tone above it
+fn chord_root(notes: &[u8]) -> Option<u8> {
+    let mut sorted = notes.to_vec();
+    sorted.sort_unstable();
+    let mut best: Option<(usize, u8)> = None;
+    for &candidate in &sorted {
+        let tones = sorted
+            .iter()
+            .filter(|&&note| CHORD_TONE_INTERVALS.contains(&((note + 12 - candidate % 12) % 12)))
+            .count();
+        if tones > 0 && best.is_none_or(|(best_tones, _)| tones > best_tones) {
+            best = Some((tones, candidate));
+        }
+    }
+    best.map(|(_, root)| root)
+}
+
+/// Swing ratio for straight timing (no swing)
+pub const STRAIGHT_SWING: f64 = 0.5;
+
+/// Loop practice: play the first pass at `start` speed and add `step` each pass up to `target`
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct PracticeRamp {
+    pub start: f64,
+    pub step: f64,
+    pub target: f64,
+}
+
+impl PracticeRamp {
+    /// Speed for the pass after one played at `speed`
+    pub fn next_speed(&self, speed: f64) -> f64 {
+        (speed + self.step).min(self.target)
+    }
+}
+
+/// Delay off-beat subdivisions by the swing ratio (0.5 = straight, up to ~0.75)
+pub fn swing_events(events: &[TimedEvent], tempo_map: &TempoMap, ratio: f64) -> Vec<TimedEvent> {
+    events
+        .iter()
+        .map(|event| TimedEvent {
+            time_ms: tempo_map.swing_time_ms(event.time_ms, ratio),
+            ..event.clone()
+        })
+        .collect()
+}
+
+// SplitMix64: small, seedable and good enough for timing jitter
+fn next_random(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Nudge each note press by a random amount within +-`amount_ms` (same seed = same result).
+/// A press never moves past its own release or before the previous release on the same game
+/// key, as given by `key_of`, so two notes sharing a key can't swap or overlap.
+pub fn humanize_events(
+    events: &[TimedEvent],
+    amount_ms: u64,
+    seed: u64,
+    key_of: impl Fn(&TimedEvent) -> String,
+) -> Vec<TimedEvent> {
+    // Release time of each press, found walking backwards
+    let mut release_after = vec![u64::MAX; events.len()];
+    let mut next_release: std::collections::HashMap<NoteKey, u64> = std::collections::HashMap::new();
+    for (index, event) in events.iter().enumerate().rev() {
+        match event.event_type {
+            EventType::NoteOff => {
+                next_release.insert(event.note_key(), event.time_ms);
+            }
+            EventType::NoteOn => {
+                if let Some(&time_ms) = next_release.get(&event.note_key()) {
+                    release_after[index] = time_ms;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut rng = seed;
+    let mut last_release: std::collections::HashMap<String, u64> = std::collections::HashMap::new();
+    let mut humanized: Vec<TimedEvent> = events
+        .iter()
+        .enumerate()
+        .map(|(index, event)| {
+            match event.event_type {
+                EventType::NoteOn => {
+                    let jitter = (next_random(&mut rng) % (2 * amount_ms + 1)) as i64 - amount_ms as i64;
+                    let earliest = last_release.get(&key_of(event)).copied().unwrap_or(0);
+                    let latest = release_after[index].saturating_sub(1).max(event.time_ms);
+                    let time_ms = (event.time_ms as i64 + jitter).max(0) as u64;
+                    TimedEvent {
+                        time_ms: time_ms.clamp(earliest.min(event.time_ms), latest),
+                        ..event.clone()
+                    }
+                }
+                EventType::NoteOff => {
+                    last_release.insert(key_of(event), event.time_ms);
+                    event.clone()
+                }
+                _ => event.clone(),
+            }
+        })
+        .collect();
+    sort_events(&mut humanized);
+    humanized
+}
+
+/// Fit score of one transpose (total distance to the nearest instrument note plus the
+/// register penalty, lower is better)
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct TransposeCandidate {
+    pub transpose: i32,
+    pub score: i32,
+}
+
+/// Auto-transpose of the loaded song with the runner-ups, so the UI can confirm it
+#[derive(Debug, Clone, Serialize)]
+pub struct TransposeDetection {
+    pub detected: i32,
+    // Transpose that will be used (the detected one unless overridden)
+    pub applied: i32,
+    pub overridden: bool,
+    // Best candidates first, including the detected one
+    pub candidates: Vec<TransposeCandidate>,
+}
+
+// Candidates returned with a detection
+const TRANSPOSE_CANDIDATES: usize = 5;
+
+// Score penalty per note for each row it lands away from the instrument's center row
+// (0 = only the distance to the scale counts)
+static REGISTER_WEIGHT: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(0);
+
+pub const MAX_REGISTER_WEIGHT: u32 = 12;
+
+pub fn set_register_weight(weight: u32) {
+    REGISTER_WEIGHT.store(weight.min(MAX_REGISTER_WEIGHT), Ordering::SeqCst);
+}
+
+pub fn get_register_weight() -> u32 {
+    REGISTER_WEIGHT.load(Ordering::SeqCst)
+}
+
+// Rows between a (range-folded) note and the center row; the two middle rows of an
+// even row count are both center
+fn rows_from_center(note: i32) -> i32 {
+    let profile = active_profile();
+    let last_row = profile.rows.len() as i32 - 1;
+    let row = (note - profile.root_note).div_euclid(12).clamp(0, last_row);
+    (2 * row - last_row).abs() / 2
+}
+
 fn detect_best_transpose(events: &[TimedEvent]) -> i32 {
-    let instrument_notes = get_instrument_notes();
+    transpose_candidates(events).first().map(|c| c.transpose).unwrap_or(0)
+}
 
-    let mut best_transpose = 0;
-    let mut best_score = i32::MAX;
+/// Score every transpose from -12 to +12, best first (ties keep the lower transpose).
+/// With a register weight, notes in the outer rows also cost, favoring the middle octave.
+pub fn transpose_candidates(events: &[TimedEvent]) -> Vec<TransposeCandidate> {
+    let instrument_notes = get_instrument_notes();
+    let register_weight = get_register_weight() as i32;
+    let mut candidates = Vec::new();
 
     // Test transpose values from -12 to +12
     for transpose in -12..=12 {
@@ -241,44 +1734,520 @@ fn detect_best_transpose(events: &[TimedEvent]) -> i32 {
                         min_distance = distance;
                     }
                 }
-                score += min_distance;
+                score += min_distance + register_weight * rows_from_center(normalized);
+            }
+        }
+
+        candidates.push(TransposeCandidate { transpose, score });
+    }
+
+    // Stable sort, so equal scores stay in ascending transpose order
+    candidates.sort_by_key(|c| c.score);
+    candidates
+}
+
+/// Detected transpose for a song plus its closest alternatives
+pub fn transpose_detection(midi_data: &MidiData, applied: i32) -> TransposeDetection {
+    let mut candidates = transpose_candidates(&midi_data.events);
+    candidates.truncate(TRANSPOSE_CANDIDATES);
+    TransposeDetection {
+        detected: midi_data.transpose,
+        applied,
+        overridden: applied != midi_data.transpose,
+        candidates,
+    }
+}
+
+/// How much of a song falls outside the playable range and gets octave-folded
+#[derive(Debug, Clone, Serialize)]
+pub struct RangeReport {
+    pub min_note: u8,
+    pub max_note: u8,
+    pub total_notes: usize,
+    pub folded_notes: usize,
+    pub folded_fraction: f64,
+}
+
+/// Count NoteOn events that need folding by normalize_into_range at the given transpose
+pub fn analyze_range(events: &[TimedEvent], transpose: i32) -> Option<RangeReport> {
+    let instrument_notes = get_instrument_notes();
+    let lo = instrument_notes[0];
+    let hi = instrument_notes[instrument_notes.len() - 1];
+
+    let mut min_note = u8::MAX;
+    let mut max_note = u8::MIN;
+    let mut total_notes = 0;
+    let mut folded_notes = 0;
+
+    for event in events {
+        if matches!(event.event_type, EventType::NoteOn) {
+            min_note = min_note.min(event.note);
+            max_note = max_note.max(event.note);
+            total_notes += 1;
+
+            let transposed = event.note as i32 + transpose;
+            if transposed < lo || transposed > hi {
+                folded_notes += 1;
+            }
+        }
+    }
+
+    if total_notes == 0 {
+        return None;
+    }
+
+    Some(RangeReport {
+        min_note,
+        max_note,
+        total_notes,
+        folded_notes,
+        folded_fraction: folded_notes as f64 / total_notes as f64,
+    })
+}
+
+/// Notes folded at one candidate octave shift
+#[derive(Debug, Clone, Serialize)]
+pub struct OctaveCandidate {
+    pub octave_shift: i8,
+    pub folded_notes: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OctaveFit {
+    pub octave_shift: i8,
+    pub candidates: Vec<OctaveCandidate>,
+}
+
+/// Try each octave shift with the detected transpose and pick the one folding the fewest notes.
+/// Ties go to the smaller shift so songs that already fit stay put.
+pub fn auto_fit_octave(path: &str) -> Result<OctaveFit, String> {
+    let midi_data = load_midi_sequence(path, 0)?;
+
+    let mut candidates = Vec::new();
+    for octave_shift in -2..=2i8 {
+        let report = analyze_range(&midi_data.events, midi_data.transpose + octave_shift as i32 * 12)
+            .ok_or("No notes found in file")?;
+        candidates.push(OctaveCandidate {
+            octave_shift,
+            folded_notes: report.folded_notes,
+        });
+    }
+
+    let best = candidates
+        .iter()
+        .min_by_key(|c| (c.folded_notes, c.octave_shift.abs()))
+        .map(|c| c.octave_shift)
+        .unwrap_or(0);
+
+    Ok(OctaveFit {
+        octave_shift: best,
+        candidates,
+    })
+}
+
+/// Note statistics for a file, with a suggested mode and transpose
+#[derive(Debug, Clone, Serialize)]
+pub struct MidiAnalysis {
+    pub min_note: u8,
+    pub max_note: u8,
+    pub total_notes: usize,
+    // Most notes sounding at the same time
+    pub max_polyphony: usize,
+    // Share of notes outside the instrument's scale at the recommended transpose
+    pub accidental_fraction: f64,
+    pub recommended_mode: NoteMode,
+    pub recommended_transpose: i32,
+}
+
+// Above this share of accidentals, Chromatic keeps melodies more recognizable
+const CHROMATIC_ACCIDENTAL_FRACTION: f64 = 0.15;
+// Chord density thresholds for suggesting Pentatonic / Mono
+const PENTATONIC_POLYPHONY: usize = 4;
+const MONO_POLYPHONY: usize = 7;
+
+/// Analyze a file's range, chord density and accidentals to suggest a mapping mode
+pub fn analyze_midi(path: &str) -> Result<MidiAnalysis, String> {
+    let midi_data = load_midi_sequence(path, 0)?;
+    let profile = active_profile();
+
+    let mut min_note = u8::MAX;
+    let mut max_note = u8::MIN;
+    let mut total_notes = 0;
+    let mut accidentals = 0;
+    let mut sounding: std::collections::HashSet<NoteKey> = std::collections::HashSet::new();
+    let mut max_polyphony = 0;
+
+    for event in &midi_data.events {
+        match event.event_type {
+            EventType::NoteOn => {
+                min_note = min_note.min(event.note);
+                max_note = max_note.max(event.note);
+                total_notes += 1;
+
+                let degree = ((event.note as i32 + midi_data.transpose - profile.root_note) % 12 + 12) % 12;
+                if !profile.scale_intervals.contains(&degree) {
+                    accidentals += 1;
+                }
+
+                sounding.insert(event.note_key());
+                max_polyphony = max_polyphony.max(sounding.len());
+            }
+            EventType::NoteOff => {
+                sounding.remove(&event.note_key());
+            }
+            _ => {}
+        }
+    }
+
+    if total_notes == 0 {
+        return Err("No notes found in file".to_string());
+    }
+
+    let accidental_fraction = accidentals as f64 / total_notes as f64;
+    let recommended_mode = if max_polyphony >= MONO_POLYPHONY {
+        NoteMode::Mono
+    } else if accidental_fraction > CHROMATIC_ACCIDENTAL_FRACTION {
+        NoteMode::Chromatic
+    } else if max_polyphony >= PENTATONIC_POLYPHONY {
+        NoteMode::Pentatonic
+    } else {
+        NoteMode::Closest
+    };
+
+    Ok(MidiAnalysis {
+        min_note,
+        max_note,
+        total_notes,
+        max_polyphony,
+        accidental_fraction,
+        recommended_mode,
+        recommended_transpose: midi_data.transpose,
+    })
+}
+
+/// Kind of passage that may not come across well in game
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum ProblemKind {
+    // More notes at once than can be pressed cleanly
+    DenseChord,
+    // More notes per second than the game keeps up with
+    FastPassage,
+    // A run of different notes all landing on the same key
+    KeyCollapse,
+}
+
+/// A stretch of the song flagged by playability_report
+#[derive(Debug, Clone, Serialize)]
+pub struct ProblemSegment {
+    pub kind: ProblemKind,
+    pub start: f64,
+    pub end: f64,
+    pub bar: u32,
+    // Worst value in the stretch: notes at once, notes per second, or notes in the run
+    pub severity: usize,
+}
+
+const PLAYABLE_POLYPHONY: usize = 5;
+const PLAYABLE_NOTES_PER_SECOND: usize = 12;
+// Different notes in a row on one key before it counts as a collapse
+const COLLAPSE_RUN: usize = 4;
+// Flags of the same kind closer than this are reported as one segment
+const PROBLEM_MERGE_MS: u64 = 1000;
+
+/// Find passages likely to sound wrong in game: dense chords, very fast runs, and runs of
+/// notes that the current mapping collapses onto a single key
+pub fn playability_report(
+    path: &str,
+    mode: NoteMode,
+    transpose: Option<i32>,
+    shift_semitones: i32,
+    band_shifts: [i8; 3],
+) -> Result<Vec<ProblemSegment>, String> {
+    let midi_data = load_midi_sequence(path, 0)?;
+    let transpose = transpose.unwrap_or(midi_data.transpose);
+
+    // (kind, time, severity) for every note that trips a check
+    let mut flags: Vec<(ProblemKind, u64, usize)> = Vec::new();
+    let mut sounding: std::collections::HashSet<NoteKey> = std::collections::HashSet::new();
+    let mut recent: std::collections::VecDeque<u64> = std::collections::VecDeque::new();
+    let mut last_press: Option<(String, u8)> = None;
+    let mut run = 0;
+
+    for event in &midi_data.events {
+        match event.event_type {
+            EventType::NoteOn | EventType::KeyDown(_) => {
+                recent.push_back(event.time_ms);
+                while recent.front().is_some_and(|&t| t + 1000 <= event.time_ms) {
+                    recent.pop_front();
+                }
+                if recent.len() > PLAYABLE_NOTES_PER_SECOND {
+                    flags.push((ProblemKind::FastPassage, event.time_ms, recent.len()));
+                }
+            }
+            EventType::NoteOff => {
+                sounding.remove(&event.note_key());
+            }
+            _ => {}
+        }
+
+        if !matches!(event.event_type, EventType::NoteOn) {
+            continue;
+        }
+
+        sounding.insert(event.note_key());
+        if sounding.len() > PLAYABLE_POLYPHONY {
+            flags.push((ProblemKind::DenseChord, event.time_ms, sounding.len()));
+        }
+
+        let key = map_note_to_key(mode, event.note as i32, transpose, shift_semitones, band_shifts);
+        run = match &last_press {
+            Some((last_key, last_note)) if *last_key == key && *last_note != event.note => run + 1,
+            _ => 1,
+        };
+        if run >= COLLAPSE_RUN {
+            flags.push((ProblemKind::KeyCollapse, event.time_ms, run));
+        }
+        last_press = Some((key, event.note));
+    }
+
+    // Merge nearby flags of each kind into segments
+    flags.sort_by_key(|&(kind, time_ms, _)| (kind as u8, time_ms));
+    let mut merged: Vec<(ProblemKind, u64, u64, usize)> = Vec::new();
+    for (kind, time_ms, severity) in flags {
+        match merged.last_mut() {
+            Some(last) if last.0 == kind && time_ms <= last.2 + PROBLEM_MERGE_MS => {
+                last.2 = time_ms;
+                last.3 = last.3.max(severity);
+            }
+            _ => merged.push((kind, time_ms, time_ms, severity)),
+        }
+    }
+
+    let mut segments: Vec<ProblemSegment> = merged
+        .into_iter()
+        .map(|(kind, start_ms, end_ms, severity)| ProblemSegment {
+            kind,
+            start: start_ms as f64 / 1000.0,
+            end: end_ms as f64 / 1000.0,
+            bar: midi_data.tempo_map.musical_position(start_ms as f64).bar,
+            severity,
+        })
+        .collect();
+    segments.sort_by(|a, b| a.start.total_cmp(&b.start));
+    Ok(segments)
+}
+
+/// A stretch of the song between long rests, found by detect_sections
+#[derive(Debug, Clone, Serialize)]
+pub struct Section {
+    pub name: String,
+    pub start: f64,
+    pub end: f64,
+}
+
+/// Split a song at rests of at least `min_gap_ms` where nothing is sounding
+pub fn detect_sections(path: &str, min_gap_ms: u64) -> Result<Vec<Section>, String> {
+    let midi_data = load_midi_sequence(path, 0)?;
+
+    // (start, end) in ms of each stretch of sound
+    let mut ranges: Vec<(u64, u64)> = Vec::new();
+    let mut sounding_notes: std::collections::HashSet<NoteKey> = std::collections::HashSet::new();
+    let mut sounding_keys: std::collections::HashSet<&'static str> = std::collections::HashSet::new();
+
+    for event in &midi_data.events {
+        match event.event_type {
+            EventType::NoteOn | EventType::KeyDown(_) => {
+                let silent = sounding_notes.is_empty() && sounding_keys.is_empty();
+                match ranges.last_mut() {
+                    // Silence long enough since the last note let go: start a new section
+                    Some(last) if !silent || event.time_ms < last.1 + min_gap_ms => {
+                        last.1 = last.1.max(event.time_ms);
+                    }
+                    _ => ranges.push((event.time_ms, event.time_ms)),
+                }
+                match event.event_type {
+                    EventType::KeyDown(key) => {
+                        sounding_keys.insert(key);
+                    }
+                    _ => {
+                        sounding_notes.insert(event.note_key());
+                    }
+                }
+            }
+            EventType::NoteOff | EventType::KeyUp(_) => {
+                match event.event_type {
+                    EventType::KeyUp(key) => sounding_keys.remove(key),
+                    _ => sounding_notes.remove(&event.note_key()),
+                };
+                if let Some(last) = ranges.last_mut() {
+                    last.1 = last.1.max(event.time_ms);
+                }
             }
+            _ => {}
+        }
+    }
+
+    Ok(ranges
+        .into_iter()
+        .enumerate()
+        .map(|(index, (start_ms, end_ms))| Section {
+            name: format!("Section {}", index + 1),
+            start: start_ms as f64 / 1000.0,
+            end: end_ms as f64 / 1000.0,
+        })
+        .collect())
+}
+
+/// Which way seek_to_note looks from the current position
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NoteDirection {
+    Next,
+    Previous,
+}
+
+// Going back from just after a note skips that note, like a "previous track" button
+const PREVIOUS_NOTE_TOLERANCE_MS: u64 = 50;
+
+/// Start time of the nearest note press after (Next) or before (Previous) `position_ms`
+pub fn find_note_time(events: &[TimedEvent], position_ms: u64, direction: NoteDirection) -> Option<u64> {
+    let mut presses = events
+        .iter()
+        .filter(|e| matches!(e.event_type, EventType::NoteOn | EventType::KeyDown(_)))
+        .map(|e| e.time_ms);
+    match direction {
+        NoteDirection::Next => presses.find(|&time_ms| time_ms > position_ms),
+        NoteDirection::Previous => presses
+            .take_while(|&time_ms| time_ms + PREVIOUS_NOTE_TOLERANCE_MS < position_ms)
+            .last(),
+    }
+}
+
+/// The next group of notes starting together at or after `index`, for manual stepping.
+/// Returns the group and the index just past it, or None once the song is exhausted.
+pub fn next_note_group(events: &[TimedEvent], index: usize) -> Option<(Vec<&TimedEvent>, usize)> {
+    let is_press = |e: &TimedEvent| matches!(e.event_type, EventType::NoteOn | EventType::KeyDown(_));
+    let start = index + events.get(index..)?.iter().position(is_press)?;
+    let time_ms = events[start].time_ms;
+
+    let mut end = start;
+    let mut group = Vec::new();
+    while end < events.len() && events[end].time_ms == time_ms {
+        if is_press(&events[end]) {
+            group.push(&events[end]);
+        }
+        end += 1;
+    }
+    Some((group, end))
+}
+
+/// Move a note by the shift of the band (low/mid/high row) it lands in after folding into
+/// the instrument's range. The lowest row is "low", the highest "high" and anything between
+/// "mid". A shifted note is clamped to the instrument's range rather than folded back, which
+/// would undo a shift of a whole octave.
+fn apply_band_shift(note: i32, transpose: i32, band_shifts: [i8; 3]) -> i32 {
+    let profile = active_profile();
+    let folded = normalize_into_range(note + transpose);
+    let row = ((folded - profile.root_note) / 12).max(0) as usize;
+    let band = if row == 0 {
+        0
+    } else if row + 1 >= profile.rows.len() {
+        2
+    } else {
+        1
+    };
+    let shift = band_shifts[band] as i32;
+    if shift == 0 {
+        return note;
+    }
+    let (lo, hi) = profile.range();
+    (folded + shift).clamp(lo, hi) - transpose
+}
+
+/// Last note mapped in Contour mode: its pitch and the instrument note it was played on
+#[derive(Debug, Clone, Copy)]
+pub struct ContourNote {
+    pitch: i32,
+    index: usize,
+}
+
+/// Contour mode mapping: the closest key, except that a note whose pitch moved away from
+/// the previous note but would land on the same key goes one key further in that direction,
+/// so the melody keeps its up/down shape. Returns the key and the note to pass in next time.
+pub fn map_note_to_key_contour(
+    note: i32,
+    transpose: i32,
+    shift_semitones: i32,
+    band_shifts: [i8; 3],
+    previous: Option<ContourNote>,
+) -> (String, ContourNote) {
+    let total_transpose = transpose + shift_semitones;
+    let note = apply_band_shift(note, total_transpose, band_shifts);
+    let pitch = note + total_transpose;
+    let key_count = active_profile().keys().len();
+
+    let mut index = closest_note_index(note, total_transpose);
+    if let Some(previous) = previous.filter(|previous| previous.index == index) {
+        if pitch > previous.pitch {
+            index = (index + 1).min(key_count - 1);
+        } else if pitch < previous.pitch {
+            index = index.saturating_sub(1);
         }
+    }
+
+    let key = active_profile().keys()[index].to_string();
+    (apply_hand_split(key, pitch), ContourNote { pitch, index })
+}
+
+/// Game key for a MIDI note under the given mode. `shift_semitones` is the manual
+/// octave/live shift; Raw mode ignores the song transpose and only applies the shift.
+/// `band_shifts` are per-band offsets (low, mid, high); Raw mode doesn't use them.
+/// Contour mode maps like Closest here, as it needs the previous note (see map_note_to_key_contour).
+pub fn map_note_to_key(mode: NoteMode, note: i32, transpose: i32, shift_semitones: i32, band_shifts: [i8; 3]) -> String {
+    let total_transpose = transpose + shift_semitones;
+    let note = if mode == NoteMode::Raw {
+        note
+    } else {
+        apply_band_shift(note, total_transpose, band_shifts)
+    };
+    let key = match mode {
+        NoteMode::Closest | NoteMode::Mono | NoteMode::Contour => note_to_key(note, total_transpose),
+        NoteMode::Quantize => note_to_key_quantize(note, total_transpose),
+        NoteMode::TransposeOnly => note_to_key_transpose(note, total_transpose),
+        NoteMode::Pentatonic => note_to_key_pentatonic(note, total_transpose),
+        NoteMode::Chromatic => note_to_key_chromatic(note, total_transpose),
+        NoteMode::Raw => note_to_key_raw(note + shift_semitones),
+    };
+    let sounding = if mode == NoteMode::Raw { note + shift_semitones } else { note + total_transpose };
+    apply_hand_split(key, sounding)
+}
 
-        if score < best_score {
-            best_score = score;
-            best_transpose = transpose;
-        }
-    }
+// MIDI notes covered by the key map (88-key piano range, where nearly all songs live)
+const KEY_MAP_RANGE: std::ops::RangeInclusive<u8> = 21..=108;
 
-    best_transpose
+/// Full note -> key table for the given settings, e.g. for drawing a legend
+pub fn note_key_map(mode: NoteMode, transpose: i32, octave_shift: i8) -> Vec<(u8, String)> {
+    let shift_semitones = octave_shift as i32 * 12;
+    KEY_MAP_RANGE
+        .map(|note| (note, map_note_to_key(mode, note as i32, transpose, shift_semitones, [0; 3])))
+        .collect()
 }
 
 fn get_instrument_notes() -> Vec<i32> {
+    let profile = active_profile();
     let mut notes = Vec::new();
 
-    // Low octave
-    for interval in SCALE_INTERVALS {
-        notes.push(ROOT_NOTE - 12 + interval);
-    }
-
-    // Mid octave
-    for interval in SCALE_INTERVALS {
-        notes.push(ROOT_NOTE + interval);
-    }
-
-    // High octave
-    for interval in SCALE_INTERVALS {
-        notes.push(ROOT_NOTE + 12 + interval);
+    // One octave per key row, lowest first
+    for octave in 0..profile.rows.len() as i32 {
+        for interval in profile.scale_intervals {
+            notes.push(profile.root_note + 12 * octave + interval);
+        }
     }
 
     notes
 }
 
 fn normalize_into_range(note: i32) -> i32 {
-    let instrument_notes = get_instrument_notes();
-    let lo = instrument_notes[0];
-    let hi = instrument_notes[instrument_notes.len() - 1];
+    let (lo, hi) = active_profile().range();
 
     let mut normalized = note;
     while normalized < lo {
@@ -291,8 +2260,46 @@ fn normalize_into_range(note: i32) -> i32 {
     normalized
 }
 
+// When set, play_midi reports every note -> key mapping as a `debug-mapping` event
+static DEBUG_LOGGING: AtomicBool = AtomicBool::new(false);
+
+pub fn set_debug_logging(enabled: bool) {
+    DEBUG_LOGGING.store(enabled, Ordering::SeqCst);
+}
+
+/// How one note was mapped to a key, for debugging mappings from the UI
+#[derive(Debug, Clone, Serialize)]
+pub struct MappingDebug {
+    pub note: i32,
+    pub transpose: i32,
+    // Pitch asked for (note + transpose), before folding into the instrument's range
+    pub target: i32,
+    // Instrument note of the pressed key (None for keys outside the profile)
+    pub matched: Option<i32>,
+    pub key: String,
+}
+
+fn mapping_debug(note: i32, transpose: i32, key: &str) -> MappingDebug {
+    let index = active_profile()
+        .keys()
+        .iter()
+        .position(|primary| *primary == key)
+        .or_else(|| hand_split_keys().iter().position(|second| *second == key));
+    MappingDebug {
+        note,
+        transpose,
+        target: note + transpose,
+        matched: index.and_then(|index| get_instrument_notes().get(index).copied()),
+        key: key.to_string(),
+    }
+}
 
 fn note_to_key(note: i32, transpose: i32) -> String {
+    active_profile().keys()[closest_note_index(note, transpose)].to_string()
+}
+
+// Index of the instrument note closest to a note, after folding it into range
+fn closest_note_index(note: i32, transpose: i32) -> usize {
     let target = normalize_into_range(note + transpose);
     let instrument_notes = get_instrument_notes();
 
@@ -306,22 +2313,7 @@ fn note_to_key(note: i32, transpose: i32) -> String {
             best_dist = dist;
         }
     }
-
-    // Map index to key
-    let all_keys = [LOW_KEYS.as_slice(), MID_KEYS.as_slice(), HIGH_KEYS.as_slice()].concat();
-    let key = all_keys[best_idx].to_string();
-
-    // Debug first few mappings
-    static mut DEBUG_COUNT: i32 = 0;
-    unsafe {
-        if DEBUG_COUNT < 5 {
-            println!("Note {} + transpose {} = target {}, matched {}, key: {}",
-                note, transpose, target, instrument_notes[best_idx], key);
-            DEBUG_COUNT += 1;
-        }
-    }
-
-    key
+    best_idx
 }
 
 /// Quantize mode - snap to exact scale notes only (no in-between approximation)
@@ -358,30 +2350,31 @@ fn note_to_key_quantize(note: i32, transpose: i32) -> String {
         best_idx = best_idx;
     }
 
-    let all_keys = [LOW_KEYS.as_slice(), MID_KEYS.as_slice(), HIGH_KEYS.as_slice()].concat();
+    let all_keys = active_profile().keys();
     all_keys[best_idx].to_string()
 }
 
 /// Transpose Only mode - direct semitone to key mapping within octave
 fn note_to_key_transpose(note: i32, transpose: i32) -> String {
     let target = note + transpose;
+    let profile = active_profile();
+
+    // Offsets are measured from the middle row's root
+    let middle = profile.rows.len() as i32 / 2;
+    let middle_root = profile.root_note + 12 * middle;
 
     // Get semitone within octave (0-11)
-    let semitone = ((target - ROOT_NOTE) % 12 + 12) % 12;
+    let semitone = ((target - middle_root) % 12 + 12) % 12;
 
     // Determine octave
-    let octave_offset = (target - ROOT_NOTE) / 12;
-    let octave = (1 + octave_offset).clamp(0, 2) as usize;
+    let octave_offset = (target - middle_root) / 12;
+    let octave = (middle + octave_offset).clamp(0, profile.rows.len() as i32 - 1) as usize;
 
-    // Direct mapping: semitone 0-11 to key 0-6 (wrap around)
+    // Direct mapping: semitone 0-11 to a key in the row (wrap around)
     // This gives a more "raw" feel
-    let key_idx = (semitone * 7 / 12) as usize;
+    let key_idx = (semitone as usize * profile.row(octave).len()) / 12;
 
-    match octave {
-        0 => LOW_KEYS[key_idx].to_string(),
-        1 => MID_KEYS[key_idx].to_string(),
-        _ => HIGH_KEYS[key_idx].to_string(),
-    }
+    profile.key(octave, key_idx)
 }
 
 /// Pentatonic mode - map to pentatonic scale (5 notes per octave)
@@ -394,29 +2387,14 @@ fn note_to_key_pentatonic(note: i32, transpose: i32) -> String {
     const PENTA_KEY_IDX: [usize; 5] = [0, 1, 2, 4, 5]; // Map to do, re, mi, so, la
 
     // Normalize to range
-    let instrument_notes = get_instrument_notes();
-    let lo = instrument_notes[0];
-    let hi = instrument_notes[instrument_notes.len() - 1];
-
-    let mut normalized = target;
-    while normalized < lo {
-        normalized += 12;
-    }
-    while normalized > hi {
-        normalized -= 12;
-    }
+    let profile = active_profile();
+    let normalized = normalize_into_range(target);
 
     // Get semitone within octave
-    let semitone = ((normalized - ROOT_NOTE) % 12 + 12) % 12;
+    let semitone = ((normalized - profile.root_note) % 12 + 12) % 12;
 
     // Determine octave
-    let octave = if normalized < ROOT_NOTE {
-        0
-    } else if normalized < ROOT_NOTE + 12 {
-        1
-    } else {
-        2
-    };
+    let octave = ((normalized - profile.root_note) / 12).max(0) as usize;
 
     // Find closest pentatonic note
     let mut best_penta_idx = 0;
@@ -431,41 +2409,22 @@ fn note_to_key_pentatonic(note: i32, transpose: i32) -> String {
 
     let key_idx = PENTA_KEY_IDX[best_penta_idx];
 
-    match octave {
-        0 => LOW_KEYS[key_idx].to_string(),
-        1 => MID_KEYS[key_idx].to_string(),
-        _ => HIGH_KEYS[key_idx].to_string(),
-    }
+    profile.key(octave, key_idx)
 }
 
 /// Chromatic mode - detailed mapping of all 12 semitones to closest natural key
 fn note_to_key_chromatic(note: i32, transpose: i32) -> String {
     let target = note + transpose;
 
-    // Normalize into our 3-octave range
-    let instrument_notes = get_instrument_notes();
-    let lo = instrument_notes[0];
-    let hi = instrument_notes[instrument_notes.len() - 1];
-
-    let mut normalized = target;
-    while normalized < lo {
-        normalized += 12;
-    }
-    while normalized > hi {
-        normalized -= 12;
-    }
+    // Normalize into the instrument's range
+    let profile = active_profile();
+    let normalized = normalize_into_range(target);
 
     // Get semitone within octave (0-11)
-    let semitone_in_octave = ((normalized - ROOT_NOTE) % 12 + 12) % 12;
+    let semitone_in_octave = ((normalized - profile.root_note) % 12 + 12) % 12;
 
-    // Determine which octave we're in
-    let octave = if normalized < ROOT_NOTE {
-        0 // Low
-    } else if normalized < ROOT_NOTE + 12 {
-        1 // Mid
-    } else {
-        2 // High
-    };
+    // Determine which octave row we're in (0 = lowest)
+    let octave = ((normalized - profile.root_note) / 12).max(0) as usize;
 
     // Map each chromatic semitone to closest natural key (0-6)
     // Semitone: 0=C, 1=C#, 2=D, 3=Eb, 4=E, 5=F, 6=F#, 7=G, 8=G#, 9=A, 10=Bb, 11=B
@@ -485,60 +2444,232 @@ fn note_to_key_chromatic(note: i32, transpose: i32) -> String {
         _ => 0,
     };
 
-    match octave {
-        0 => LOW_KEYS[key_idx].to_string(),
-        1 => MID_KEYS[key_idx].to_string(),
-        _ => HIGH_KEYS[key_idx].to_string(),
-    }
+    profile.key(octave, key_idx)
 }
 
 /// Raw mode - direct 1:1 mapping, no transpose, no processing
-/// MIDI note modulo 21 maps directly to one of 21 keys
+/// MIDI note modulo the key count maps directly to one of the instrument's keys
 fn note_to_key_raw(note: i32) -> String {
-    // Direct mapping: note % 21 gives key index 0-20
-    let key_idx = ((note % 21) + 21) % 21; // Handle negative notes
-    let all_keys = [LOW_KEYS.as_slice(), MID_KEYS.as_slice(), HIGH_KEYS.as_slice()].concat();
+    let all_keys = active_profile().keys();
+    let key_count = all_keys.len() as i32;
+    // Direct mapping: note % key count gives the key index
+    let key_idx = ((note % key_count) + key_count) % key_count; // Handle negative notes
     all_keys[key_idx as usize].to_string()
 }
 
 
-pub fn play_midi(
-    midi_data: MidiData,
-    is_playing: Arc<AtomicBool>,
-    is_paused: Arc<AtomicBool>,
-    loop_mode: Arc<AtomicBool>,
-    note_mode: Arc<AtomicU8>,
-    octave_shift: Arc<std::sync::atomic::AtomicI8>,
-    current_position: Arc<std::sync::Mutex<f64>>,
-    seek_offset: Arc<std::sync::Mutex<f64>>,
-    window: Window,
-) {
-    let offset_ms = (*seek_offset.lock().unwrap() * 1000.0) as u64;
+/// Per-run playback state handed to play_midi, as opposed to the live settings it also reads
+pub struct PlaybackRun {
+    pub is_playing: Arc<AtomicBool>,
+    pub is_paused: Arc<AtomicBool>,
+    pub loop_mode: Arc<AtomicBool>,
+    pub loop_count: Arc<std::sync::atomic::AtomicU32>,
+    pub loops_remaining: Arc<std::sync::atomic::AtomicU32>,
+    pub current_position: Arc<Mutex<f64>>,
+    // Where this run starts in the song, in seconds
+    pub start_position: f64,
+    pub seek_request: Arc<std::sync::atomic::AtomicU64>,
+    pub playback_stats: Arc<Mutex<PlaybackStats>>,
+    pub signal: Arc<PlaybackSignal>,
+    pub note_overrides: Arc<Mutex<std::collections::HashMap<u8, String>>>,
+    pub active_tracks: Arc<Mutex<Option<std::collections::HashSet<usize>>>>,
+    pub practice_ramp: Arc<Mutex<Option<PracticeRamp>>>,
+    pub practice_speed: Arc<Mutex<f64>>,
+    // Count-in before the first note, so a start can be lined up with something else
+    pub start_delay_ms: u64,
+}
+
+/// Settings play_midi reads as it plays, shared with AppState so a change applies mid-song
+pub struct LiveSettings {
+    pub loop_gap_ms: Arc<std::sync::atomic::AtomicU64>,
+    pub seamless_loop: Arc<AtomicBool>,
+    pub note_mode: Arc<AtomicU8>,
+    pub output_mode: Arc<AtomicU8>,
+    pub octave_shift: Arc<std::sync::atomic::AtomicI8>,
+    pub octave_shifts: Arc<Mutex<[i8; 3]>>,
+    pub transpose: Arc<std::sync::atomic::AtomicI32>,
+    pub live_transpose: Arc<std::sync::atomic::AtomicI32>,
+    pub chord_mode: Arc<AtomicU8>,
+    pub min_note_duration_ms: Arc<std::sync::atomic::AtomicU64>,
+    pub min_channel_volume: Arc<AtomicU8>,
+    pub time_quantize_ms: Arc<std::sync::atomic::AtomicU64>,
+    pub swing_ratio: Arc<Mutex<f64>>,
+    pub humanize_ms: Arc<std::sync::atomic::AtomicU64>,
+    pub humanize_seed: Arc<std::sync::atomic::AtomicU64>,
+    pub latency_offset_ms: Arc<std::sync::atomic::AtomicI64>,
+    pub pitch_bend: Arc<AtomicBool>,
+    pub bass_tremolo: Arc<AtomicBool>,
+    pub bass_tremolo_rate_ms: Arc<std::sync::atomic::AtomicU64>,
+    pub detache: Arc<AtomicBool>,
+    pub note_off_delay_ms: Arc<std::sync::atomic::AtomicU64>,
+    pub max_nps: Arc<std::sync::atomic::AtomicU32>,
+    pub pause_at_markers: Arc<AtomicBool>,
+    pub call_and_response: Arc<AtomicBool>,
+    pub progress_interval_ms: Arc<std::sync::atomic::AtomicU64>,
+    pub pause_on_focus_loss: Arc<AtomicBool>,
+    pub resume_on_focus: Arc<AtomicBool>,
+}
+
+pub fn play_midi(midi_data: MidiData, run: PlaybackRun, settings: LiveSettings, sink: impl PlaybackSink) {
+    let PlaybackRun {
+        is_playing,
+        is_paused,
+        loop_mode,
+        loop_count,
+        loops_remaining,
+        current_position,
+        start_position,
+        seek_request,
+        playback_stats,
+        signal,
+        note_overrides,
+        active_tracks,
+        practice_ramp,
+        practice_speed,
+        start_delay_ms: _,
+    } = run;
+    let LiveSettings {
+        loop_gap_ms,
+        seamless_loop,
+        note_mode,
+        output_mode,
+        octave_shift,
+        octave_shifts,
+        transpose,
+        live_transpose,
+        chord_mode,
+        min_note_duration_ms,
+        min_channel_volume,
+        time_quantize_ms,
+        swing_ratio,
+        humanize_ms,
+        humanize_seed,
+        latency_offset_ms,
+        pitch_bend,
+        bass_tremolo,
+        bass_tremolo_rate_ms,
+        detache,
+        note_off_delay_ms,
+        max_nps,
+        pause_at_markers,
+        call_and_response,
+        progress_interval_ms,
+        pause_on_focus_loss,
+        resume_on_focus,
+    } = settings;
+
+    let mut stats = PlaybackStats::default();
+    *playback_stats.lock().unwrap() = stats;
+
+    // Nothing to press: finish right away instead of spinning through empty loop passes
+    if !midi_data.events.iter().any(|e| matches!(e.event_type, EventType::NoteOn | EventType::KeyDown(_))) {
+        crate::report_error("playback-error", "Nothing to play: the song has no notes");
+        is_playing.store(false, Ordering::SeqCst);
+        signal.notify();
+        sink.ended();
+        return;
+    }
+
+    let _timer_resolution = TimerResolutionGuard::new();
+    let mut offset_ms = (start_position * 1000.0) as u64;
 
     // Spawn a separate thread for progress updates
     let is_playing_progress = Arc::clone(&is_playing);
     let is_paused_progress = Arc::clone(&is_paused);
     let current_position_progress = Arc::clone(&current_position);
-    let window_progress = window.clone();
+    let sink_progress = sink.clone();
+    let signal_progress = Arc::clone(&signal);
+    let duration = midi_data.duration;
+    // Ticked by the playback loop while it's alive; the progress thread forwards new ticks as
+    // `heartbeat`, so the UI can tell a hung playback thread (no heartbeat) from a quiet passage
+    let heartbeat = Arc::new(std::sync::atomic::AtomicU64::new(0));
+    let heartbeat_progress = Arc::clone(&heartbeat);
 
     std::thread::spawn(move || {
+        let mut next_focus_check = Instant::now();
+        // Whether the current pause was caused by focus loss (vs. by the user)
+        let mut paused_by_focus = false;
+        let mut last_beat = 0;
+
         while is_playing_progress.load(Ordering::SeqCst) {
+            let beat = heartbeat_progress.load(Ordering::SeqCst);
+            if beat != last_beat {
+                last_beat = beat;
+                sink_progress.heartbeat(beat);
+            }
+
             if !is_paused_progress.load(Ordering::SeqCst) {
-                let position = *current_position_progress.lock().unwrap();
-                let _ = window_progress.emit("playback-progress", position);
+                paused_by_focus = false;
+                let position = reported_position(*current_position_progress.lock().unwrap(), duration);
+                sink_progress.progress(position);
+            }
+
+            // Check game focus every 500ms so keystrokes don't leak into other windows
+            if Instant::now() >= next_focus_check && pause_on_focus_loss.load(Ordering::SeqCst) {
+                next_focus_check = Instant::now() + Duration::from_millis(FOCUS_CHECK_INTERVAL_MS);
+                let focused = crate::keyboard::is_black_desert_focused().unwrap_or(true);
+                let paused = is_paused_progress.load(Ordering::SeqCst);
+
+                if !focused && !paused {
+                    is_paused_progress.store(true, Ordering::SeqCst);
+                    paused_by_focus = true;
+                    sink_progress.focus_changed(false);
+                } else if focused && paused && paused_by_focus && resume_on_focus.load(Ordering::SeqCst) {
+                    is_paused_progress.store(false, Ordering::SeqCst);
+                    paused_by_focus = false;
+                    sink_progress.focus_changed(true);
+                }
             }
-            std::thread::sleep(Duration::from_millis(100));
+
+            // Stop/pause notify the signal, so the thread exits as soon as playback ends
+            signal_progress.wait(Duration::from_millis(progress_interval_ms.load(Ordering::SeqCst)));
         }
     });
 
+    // Target of a pending seek in ms, taken once by the playback loop
+    let take_seek = || {
+        let target_ms = seek_request.swap(NO_SEEK, Ordering::SeqCst);
+        (target_ms != NO_SEEK).then_some(target_ms)
+    };
+
+    // Pass speed from the practice ramp (1.0 without one). Event times stay in song time,
+    // only the waits between them are stretched.
+    let mut pass_speed = practice_ramp.lock().unwrap().map(|ramp| ramp.start).unwrap_or(1.0);
+
+    let mut limiter = PressLimiter {
+        max_nps,
+        presses: std::collections::VecDeque::new(),
+    };
+
+    let mut pass_start = Instant::now();
+    let mut pass = 0u32;
     loop {
-        let start_time = Instant::now();
-        // Track which key is pressed for each MIDI note (note -> key that was pressed)
-        let mut note_to_pressed_key: std::collections::HashMap<u8, String> = std::collections::HashMap::new();
+        pass += 1;
+        let mut start_time = pass_start;
+        *practice_speed.lock().unwrap() = pass_speed;
+        // Track which key is pressed for each MIDI note ((track, channel, note) -> key that was pressed)
+        let mut note_to_pressed_key: std::collections::HashMap<NoteKey, String> = std::collections::HashMap::new();
+        // Current pitch bend per channel, in whole semitones
+        let mut channel_bend = [0i32; 16];
         // Track reference count for each key (multiple notes might map to same key)
         let mut key_active_count: std::collections::HashMap<String, i32> = std::collections::HashMap::new();
+        // Pitch sent to the MIDI output for each sounding note, when routing to a port
+        let mut port_notes: std::collections::HashMap<NoteKey, u8> = std::collections::HashMap::new();
+        // Notes below the instrument that are being played as a tremolo on the lowest key
+        let mut tremolo_notes: std::collections::HashSet<NoteKey> = std::collections::HashSet::new();
+        // Key sheet presses dropped by the rate cap, so their KeyUp doesn't release another press
+        let mut throttled_keys: std::collections::HashMap<&'static str, u32> = std::collections::HashMap::new();
+        let tremolo_key = active_profile().keys()[0].to_string();
+        let mut next_tremolo = Instant::now();
         let mut total_paused_duration = Duration::ZERO;
 
+        // Keys whose notes ended but are held a little longer by the note-off delay, with
+        // when to let go. A new press of the key releases it right away to re-articulate.
+        let release_due: std::cell::RefCell<std::collections::HashMap<String, Instant>> =
+            std::cell::RefCell::new(std::collections::HashMap::new());
+        // Previous note in Contour mode, which the next note's mapping depends on
+        let contour_previous: std::cell::Cell<Option<ContourNote>> = std::cell::Cell::new(None);
+
         // Helper to release all keys
         let release_all_keys = |key_active_count: &std::collections::HashMap<String, i32>| {
             for (key, count) in key_active_count {
@@ -546,99 +2677,951 @@ pub fn play_midi(
                     crate::keyboard::key_up(key);
                 }
             }
+            for (key, _) in release_due.borrow_mut().drain() {
+                crate::keyboard::key_up(&key);
+            }
+            crate::midi_out::all_notes_off();
         };
-
-        for event in &midi_data.events {
-            if event.time_ms < offset_ms {
-                continue;
+        // Press a key that no note is holding, cutting short a delayed release first
+        let press_key = |key: &str| {
+            if release_due.borrow_mut().remove(key).is_some() {
+                crate::keyboard::key_up(key);
             }
-
-            if !is_playing.load(Ordering::SeqCst) {
-                release_all_keys(&key_active_count);
-                return;
+            crate::keyboard::key_down(key);
+        };
+        // Let go of a key no note is holding anymore, after the note-off delay if one is set
+        let release_key = |key: &str| {
+            let release_delay = Duration::from_millis(note_off_delay_ms.load(Ordering::SeqCst));
+            if release_delay.is_zero() {
+                crate::keyboard::key_up(key);
+            } else {
+                release_due.borrow_mut().insert(key.to_string(), Instant::now() + release_delay);
             }
+        };
+        // Report the keys currently held down, for the on-screen keyboard
+        let report_active_keys = |key_active_count: &std::collections::HashMap<String, i32>| {
+            let mut keys: Vec<String> = key_active_count
+                .iter()
+                .filter(|(_, count)| **count > 0)
+                .map(|(key, _)| key.clone())
+                .collect();
+            keys.sort();
+            sink.active_keys(&keys);
+        };
+
+        // Timing adjustments are applied per pass, so a change takes effect on the next play/loop
+        let grid_ms = time_quantize_ms.load(Ordering::SeqCst);
+        let swing = *swing_ratio.lock().unwrap();
+        let mut events: std::borrow::Cow<[TimedEvent]> = std::borrow::Cow::Borrowed(&midi_data.events);
+        let min_volume = min_channel_volume.load(Ordering::SeqCst);
+        if min_volume > 0 {
+            events = std::borrow::Cow::Owned(gate_by_volume(&events, &midi_data.volumes, min_volume));
+        }
+        let min_duration_ms = min_note_duration_ms.load(Ordering::SeqCst);
+        if min_duration_ms > 0 {
+            // Before quantizing, which can stretch a note's length
+            events = std::borrow::Cow::Owned(drop_short_notes(&events, min_duration_ms));
+        }
+        if grid_ms > 0 {
+            events = std::borrow::Cow::Owned(quantize_events(&events, grid_ms));
+        }
+        // After quantizing, so notes snapped together count as one chord
+        let chords = ChordMode::from(chord_mode.load(Ordering::SeqCst));
+        if chords != ChordMode::Off {
+            events = std::borrow::Cow::Owned(reduce_chords(&events, chords, active_tracks.lock().unwrap().as_ref()));
+        }
+        if swing != STRAIGHT_SWING {
+            events = std::borrow::Cow::Owned(swing_events(&events, &midi_data.tempo_map, swing));
+        }
+        let humanize = humanize_ms.load(Ordering::SeqCst);
+        if humanize > 0 {
+            // Advance the seed so every pass sounds a little different, yet replays from a set seed
+            let seed = humanize_seed.fetch_add(1, Ordering::SeqCst);
+            // Guarded by the keys as mapped at the start of the pass
+            let mode = NoteMode::from(note_mode.load(Ordering::SeqCst));
+            let shift_semitones = octave_shift.load(Ordering::SeqCst) as i32 * 12
+                + live_transpose.load(Ordering::SeqCst);
+            let song_transpose = transpose.load(Ordering::SeqCst);
+            let band_shifts = *octave_shifts.lock().unwrap();
+            let key_of = |event: &TimedEvent| {
+                map_note_to_key(mode, event.note as i32, song_transpose, shift_semitones, band_shifts)
+            };
+            events = std::borrow::Cow::Owned(humanize_events(&events, humanize, seed, key_of));
+        }
 
-            let target_time = Duration::from_millis(event.time_ms - offset_ms);
+        'restart: loop {
+            for event in events.iter() {
+                if event.time_ms < offset_ms {
+                    // Keep bend state from before the seek point
+                    if let EventType::PitchBend(bend) = event.event_type {
+                        channel_bend[event.channel as usize & 0x0F] = pitch_bend_semitones(bend);
+                    }
+                    continue;
+                }
+
+                // Notes of deselected tracks are left out; their releases still go through,
+                // so a track turned off mid-note doesn't leave its key held
+                if matches!(event.event_type, EventType::NoteOn)
+                    && active_tracks.lock().unwrap().as_ref().is_some_and(|tracks| !tracks.contains(&event.track))
+                {
+                    continue;
+                }
 
-            // Wait until we reach the event time
-            loop {
                 if !is_playing.load(Ordering::SeqCst) {
                     release_all_keys(&key_active_count);
                     return;
                 }
 
-                if is_paused.load(Ordering::SeqCst) {
-                    let pause_start = Instant::now();
-                    while is_paused.load(Ordering::SeqCst) && is_playing.load(Ordering::SeqCst) {
-                        std::thread::sleep(Duration::from_millis(50));
+                // Press early (or late) by the latency offset, but never before the start
+                let due_ms = ((event.time_ms - offset_ms) as f64 / pass_speed) as i64 - latency_offset_ms.load(Ordering::SeqCst);
+                let target_time = Duration::from_millis(due_ms.max(0) as u64);
+
+                // Wait until we reach the event time
+                loop {
+                    heartbeat.fetch_add(1, Ordering::SeqCst);
+                    if !is_playing.load(Ordering::SeqCst) {
+                        release_all_keys(&key_active_count);
+                        return;
+                    }
+
+                    if let Some(target_ms) = take_seek() {
+                        // Jump in place: let go of held notes and replay the events from the target
+                        release_all_keys(&key_active_count);
+                        key_active_count.clear();
+                        note_to_pressed_key.clear();
+                        port_notes.clear();
+                        tremolo_notes.clear();
+                        channel_bend = [0; 16];
+                        contour_previous.set(None);
+                        sink.active_keys(&[]);
+                        offset_ms = target_ms;
+                        start_time = Instant::now();
+                        total_paused_duration = Duration::ZERO;
+                        *current_position.lock().unwrap() = target_ms as f64 / 1000.0;
+                        continue 'restart;
+                    }
+
+                    if is_paused.load(Ordering::SeqCst) {
+                        // Let go of held keys so the instrument doesn't drone while paused.
+                        // The counts are kept, so resume knows which notes should still sound.
+                        release_all_keys(&key_active_count);
+                        sink.active_keys(&[]);
+
+                        let pause_start = Instant::now();
+                        while is_paused.load(Ordering::SeqCst)
+                            && is_playing.load(Ordering::SeqCst)
+                            && seek_request.load(Ordering::SeqCst) == NO_SEEK
+                        {
+                            heartbeat.fetch_add(1, Ordering::SeqCst);
+                            signal.wait(Duration::from_millis(MAX_WAIT_SLICE_MS));
+                        }
                         if !is_playing.load(Ordering::SeqCst) {
-                            release_all_keys(&key_active_count);
                             return;
                         }
+                        total_paused_duration += pause_start.elapsed();
+                        if seek_request.load(Ordering::SeqCst) != NO_SEEK {
+                            // Seeked while paused: jump first, staying paused
+                            continue;
+                        }
+
+                        for (key, count) in &key_active_count {
+                            if *count > 0 {
+                                crate::keyboard::key_down(key);
+                            }
+                        }
+                        report_active_keys(&key_active_count);
+                        next_tremolo = Instant::now() + Duration::from_millis(bass_tremolo_rate_ms.load(Ordering::SeqCst));
                     }
-                    total_paused_duration += pause_start.elapsed();
-                }
 
-                let effective_elapsed = start_time.elapsed().saturating_sub(total_paused_duration);
-                *current_position.lock().unwrap() = effective_elapsed.as_secs_f64() + (offset_ms as f64 / 1000.0);
+                    let effective_elapsed = start_time.elapsed().saturating_sub(total_paused_duration);
+                    *current_position.lock().unwrap() = effective_elapsed.as_secs_f64() * pass_speed + (offset_ms as f64 / 1000.0);
 
-                if effective_elapsed >= target_time {
-                    break;
+                    if effective_elapsed >= target_time {
+                        break;
+                    }
+
+                    // Sleep until the event is due; pause/stop notify the signal to wake us early
+                    let mut remaining = (target_time - effective_elapsed).min(Duration::from_millis(MAX_WAIT_SLICE_MS));
+                    if !tremolo_notes.is_empty() {
+                        // Re-strike the lowest key while a bass tremolo is held
+                        let now = Instant::now();
+                        if now >= next_tremolo {
+                            if key_active_count.get(&tremolo_key).copied().unwrap_or(0) > 0 {
+                                crate::keyboard::key_up(&tremolo_key);
+                                crate::keyboard::key_down(&tremolo_key);
+                            }
+                            next_tremolo = now + Duration::from_millis(bass_tremolo_rate_ms.load(Ordering::SeqCst));
+                        }
+                        remaining = remaining.min(next_tremolo.saturating_duration_since(now));
+                    }
+                    {
+                        // Finish delayed releases that are due, and wake for the next one
+                        let now = Instant::now();
+                        let mut due = release_due.borrow_mut();
+                        due.retain(|key, release_at| {
+                            if *release_at <= now {
+                                crate::keyboard::key_up(key);
+                                return false;
+                            }
+                            remaining = remaining.min(release_at.saturating_duration_since(now));
+                            true
+                        });
+                    }
+                    signal.wait(remaining);
                 }
 
-                std::thread::sleep(Duration::from_millis(1));
-            }
-
-            // Get key based on note calculation mode (read in realtime for live switching)
-            let current_mode = NoteMode::from(note_mode.load(Ordering::SeqCst));
-            // Get octave shift in semitones (1 octave = 12 semitones)
-            let shift_semitones = octave_shift.load(Ordering::SeqCst) as i32 * 12;
-            let total_transpose = midi_data.transpose + shift_semitones;
-            let key = match current_mode {
-                NoteMode::Closest => note_to_key(event.note as i32, total_transpose),
-                NoteMode::Quantize => note_to_key_quantize(event.note as i32, total_transpose),
-                NoteMode::TransposeOnly => note_to_key_transpose(event.note as i32, total_transpose),
-                NoteMode::Pentatonic => note_to_key_pentatonic(event.note as i32, total_transpose),
-                NoteMode::Chromatic => note_to_key_chromatic(event.note as i32, total_transpose),
-                NoteMode::Raw => note_to_key_raw(event.note as i32 + shift_semitones), // Raw ignores auto-transpose, only uses manual shift
-            };
+                // Get key based on note calculation mode (read in realtime for live switching)
+                let current_mode = NoteMode::from(note_mode.load(Ordering::SeqCst));
+                // Get octave shift in semitones (1 octave = 12 semitones)
+                // Live transpose is a manual A/B offset, so it counts as part of the shift
+                let shift_semitones = octave_shift.load(Ordering::SeqCst) as i32 * 12
+                    + live_transpose.load(Ordering::SeqCst);
+                // Transpose is shared so a manual override applies on the next note
+                let song_transpose = transpose.load(Ordering::SeqCst);
+                let band_shifts = *octave_shifts.lock().unwrap();
+                let debug_logging = DEBUG_LOGGING.load(Ordering::SeqCst);
+                let key_for = |note: i32| {
+                    // Contour mode maps like Closest here; see contour_key_for
+                    let key = map_note_to_key(current_mode, note, song_transpose, shift_semitones, band_shifts);
+                    if debug_logging {
+                        sink.debug_mapping(&mapping_debug(note, song_transpose + shift_semitones, &key));
+                    }
+                    key
+                };
+                // Contour mode key for a new press, relative to the last pressed note. The
+                // returned note only becomes the previous one once the press really happens.
+                let contour_key_for = |note: i32| {
+                    let (key, mapped) = map_note_to_key_contour(
+                        note,
+                        song_transpose,
+                        shift_semitones,
+                        band_shifts,
+                        contour_previous.get(),
+                    );
+                    if debug_logging {
+                        sink.debug_mapping(&mapping_debug(note, song_transpose + shift_semitones, &key));
+                    }
+                    (key, mapped)
+                };
+                let channel = event.channel as usize & 0x0F;
+                let bend_enabled = pitch_bend.load(Ordering::SeqCst);
+                let bend = if bend_enabled { channel_bend[channel] } else { 0 };
+                let note_key = event.note_key();
+                // Per-song key overrides win over the mode mapping (and bends) for their pitch
+                let overrides = note_overrides.lock().unwrap();
+                // Bass notes under the instrument's range tremolo on the lowest key instead of folding up
+                let tremolo_enabled = bass_tremolo.load(Ordering::SeqCst);
+                let is_bass_tremolo = |note: i32| {
+                    tremolo_enabled && note + song_transpose + shift_semitones < active_profile().range().0
+                };
+
+                // MIDI port output sends the transposed pitch instead of pressing game keys.
+                // Key sheets have no pitch, so their keys are skipped.
+                if crate::midi_out::OutputMode::from(output_mode.load(Ordering::SeqCst)) == crate::midi_out::OutputMode::MidiPort {
+                    match event.event_type {
+                        EventType::NoteOn => {
+                            let pitch = (event.note as i32 + bend + song_transpose + shift_semitones).clamp(0, 127) as u8;
+                            if let Some(previous) = port_notes.insert(note_key, pitch) {
+                                crate::midi_out::note_off(event.channel, previous);
+                            }
+                            crate::midi_out::note_on(event.channel, pitch);
+                            stats.note_ons += 1;
+                            *playback_stats.lock().unwrap() = stats;
+                            continue;
+                        }
+                        EventType::NoteOff => {
+                            if let Some(pitch) = port_notes.remove(&note_key) {
+                                crate::midi_out::note_off(event.channel, pitch);
+                            }
+                            continue;
+                        }
+                        EventType::KeyDown(_) | EventType::KeyUp(_) => continue,
+                        _ => {}
+                    }
+                }
 
-            match event.event_type {
-                EventType::NoteOn => {
-                    // Store which key we're pressing for this MIDI note
-                    note_to_pressed_key.insert(event.note, key.clone());
-                    let count = key_active_count.entry(key.clone()).or_insert(0);
-                    if *count == 0 {
+                match event.event_type {
+                    EventType::NoteOn if current_mode == NoteMode::Mono && !limiter.allow() => {
+                        // Over the rate cap: keep the current note sounding instead
+                        stats.note_ons += 1;
+                        stats.throttled += 1;
+                    }
+                    EventType::NoteOn if current_mode == NoteMode::Mono => {
+                        // Single voice: release whatever is held before pressing the new note
+                        release_all_keys(&key_active_count);
+                        key_active_count.clear();
+                        note_to_pressed_key.clear();
+                        tremolo_notes.clear();
+
+                        let pitch = event.note as i32 + bend;
+                        let key = if let Some(key) = overrides.get(&event.note) {
+                            key.clone()
+                        } else if is_bass_tremolo(pitch) {
+                            tremolo_notes.insert(note_key);
+                            next_tremolo = Instant::now() + Duration::from_millis(bass_tremolo_rate_ms.load(Ordering::SeqCst));
+                            tremolo_key.clone()
+                        } else {
+                            key_for(pitch)
+                        };
                         crate::keyboard::key_down(&key);
+                        note_to_pressed_key.insert(note_key, key.clone());
+                        key_active_count.insert(key, 1);
+                        stats.note_ons += 1;
+                        stats.keys_pressed += 1;
                     }
-                    *count += 1;
-                }
-                EventType::NoteOff => {
-                    // Use the key that was actually pressed for this note, not current mode mapping
-                    if let Some(pressed_key) = note_to_pressed_key.remove(&event.note) {
-                        if let Some(count) = key_active_count.get_mut(&pressed_key) {
+                    EventType::NoteOn => {
+                        let pitch = event.note as i32 + bend;
+                        let mut contour_note = None;
+                        let key = if let Some(key) = overrides.get(&event.note) {
+                            key.clone()
+                        } else if is_bass_tremolo(pitch) {
+                            if tremolo_notes.is_empty() {
+                                next_tremolo = Instant::now() + Duration::from_millis(bass_tremolo_rate_ms.load(Ordering::SeqCst));
+                            }
+                            tremolo_notes.insert(note_key);
+                            tremolo_key.clone()
+                        } else if current_mode == NoteMode::Contour {
+                            let (key, mapped) = contour_key_for(pitch);
+                            contour_note = Some(mapped);
+                            key
+                        } else {
+                            key_for(pitch)
+                        };
+                        let held = key_active_count.get(&key).copied().unwrap_or(0) > 0;
+                        stats.note_ons += 1;
+                        if !held && !limiter.allow() {
+                            // Over the rate cap: drop the note (its NoteOff finds nothing to release)
+                            tremolo_notes.remove(&note_key);
+                            stats.throttled += 1;
+                        } else {
+                            if contour_note.is_some() {
+                                contour_previous.set(contour_note);
+                            }
+                            // Store which key we're pressing for this MIDI note
+                            note_to_pressed_key.insert(note_key, key.clone());
+                            let count = key_active_count.entry(key.clone()).or_insert(0);
+                            if *count == 0 {
+                                press_key(&key);
+                                stats.keys_pressed += 1;
+                            } else if detache.load(Ordering::SeqCst) && limiter.allow() {
+                                // Detache: re-strike the held key so repeated notes stay distinct.
+                                // Over the rate cap it merges into the held press instead.
+                                crate::keyboard::key_up(&key);
+                                crate::keyboard::key_down(&key);
+                                stats.keys_pressed += 1;
+                            } else {
+                                stats.keys_skipped += 1;
+                            }
+                            *count += 1;
+                        }
+                    }
+                    EventType::NoteOff => {
+                        tremolo_notes.remove(&note_key);
+                        // Use the key that was actually pressed for this note, not current mode mapping
+                        if let Some(pressed_key) = note_to_pressed_key.remove(&note_key) {
+                            if let Some(count) = key_active_count.get_mut(&pressed_key) {
+                                if *count > 0 {
+                                    *count -= 1;
+                                    if *count == 0 {
+                                        release_key(&pressed_key);
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    EventType::Marker(index) => {
+                        if let Some(marker) = midi_data.markers.get(index) {
+                            let pause = pause_at_markers.load(Ordering::SeqCst);
+                            if pause {
+                                is_paused.store(true, Ordering::SeqCst);
+                            }
+                            sink.marker_reached(marker, pause);
+                        }
+                    }
+                    EventType::KeyDown(key) => {
+                        // Key sheets name the key directly
+                        let held = key_active_count.get(key).copied().unwrap_or(0) > 0;
+                        stats.note_ons += 1;
+                        if !held && !limiter.allow() {
+                            *throttled_keys.entry(key).or_insert(0) += 1;
+                            stats.throttled += 1;
+                        } else {
+                            let count = key_active_count.entry(key.to_string()).or_insert(0);
+                            if *count == 0 {
+                                press_key(key);
+                                stats.keys_pressed += 1;
+                            } else if detache.load(Ordering::SeqCst) && limiter.allow() {
+                                crate::keyboard::key_up(key);
+                                crate::keyboard::key_down(key);
+                                stats.keys_pressed += 1;
+                            } else {
+                                stats.keys_skipped += 1;
+                            }
+                            *count += 1;
+                        }
+                    }
+                    EventType::KeyUp(key) if throttled_keys.get(key).is_some_and(|&dropped| dropped > 0) => {
+                        if let Some(dropped) = throttled_keys.get_mut(key) {
+                            *dropped -= 1;
+                        }
+                    }
+                    EventType::KeyUp(key) => {
+                        if let Some(count) = key_active_count.get_mut(key) {
                             if *count > 0 {
                                 *count -= 1;
                                 if *count == 0 {
-                                    crate::keyboard::key_up(&pressed_key);
+                                    release_key(key);
+                                }
+                            }
+                        }
+                    }
+                    EventType::PitchBend(value) => {
+                        channel_bend[channel] = pitch_bend_semitones(value);
+                        if !bend_enabled {
+                            continue;
+                        }
+
+                        // Slide held notes on this channel onto the key for their bent pitch
+                        for (&held_note, pressed_key) in note_to_pressed_key.iter_mut() {
+                            let (_, note_channel, note) = held_note;
+                            if note_channel as usize & 0x0F != channel
+                                || tremolo_notes.contains(&held_note)
+                                || overrides.contains_key(&note)
+                            {
+                                continue;
+                            }
+                            // Plain mapping, also in Contour mode: a bend isn't a new melody note
+                            let bent_key = key_for(note as i32 + channel_bend[channel]);
+                            if bent_key == *pressed_key {
+                                continue;
+                            }
+
+                            if let Some(count) = key_active_count.get_mut(pressed_key) {
+                                if *count > 0 {
+                                    *count -= 1;
+                                    if *count == 0 {
+                                        crate::keyboard::key_up(pressed_key);
+                                    }
                                 }
                             }
+                            let count = key_active_count.entry(bent_key.clone()).or_insert(0);
+                            if *count == 0 {
+                                press_key(&bent_key);
+                            }
+                            *count += 1;
+                            *pressed_key = bent_key;
                         }
                     }
                 }
+                drop(overrides);
+                report_active_keys(&key_active_count);
+                *playback_stats.lock().unwrap() = stats;
             }
+            break;
         }
 
         // Release all remaining keys
         release_all_keys(&key_active_count);
+        sink.active_keys(&[]);
 
-        if !loop_mode.load(Ordering::SeqCst) {
-            break;
+        // Loop mode or a loop count of 0 repeats forever, otherwise count down the repeats
+        let repeat_forever = loop_mode.load(Ordering::SeqCst) || loop_count.load(Ordering::SeqCst) == 0;
+        if !repeat_forever {
+            let remaining = loops_remaining.load(Ordering::SeqCst);
+            if remaining == 0 {
+                break;
+            }
+            loops_remaining.store(remaining - 1, Ordering::SeqCst);
         }
 
-        std::thread::sleep(Duration::from_millis(500));
+        if call_and_response.load(Ordering::SeqCst) {
+            // Your turn: the next pass waits at its first event until resumed (or stopped)
+            is_paused.store(true, Ordering::SeqCst);
+            sink.section_complete(pass);
+            pass_start = Instant::now();
+        } else if seamless_loop.load(Ordering::SeqCst) {
+            // Schedule the next pass from where this one was due to end rather than from now,
+            // so the loop boundary keeps the song's rhythm
+            let song_length_ms = ((midi_data.duration * 1000.0) as u64).saturating_sub(offset_ms);
+            let pass_length_ms = (song_length_ms as f64 / pass_speed) as u64;
+            pass_start = start_time + total_paused_duration + Duration::from_millis(pass_length_ms);
+        } else {
+            // Wait out the gap in slices so the heartbeat keeps going
+            let gap_end = Instant::now() + Duration::from_millis(loop_gap_ms.load(Ordering::SeqCst));
+            while is_playing.load(Ordering::SeqCst) && Instant::now() < gap_end {
+                heartbeat.fetch_add(1, Ordering::SeqCst);
+                signal.wait(gap_end.saturating_duration_since(Instant::now()).min(Duration::from_millis(MAX_WAIT_SLICE_MS)));
+            }
+            pass_start = Instant::now();
+        }
+        // Later passes play the whole song, not from the seek point
+        offset_ms = 0;
+        // Speed up for the next pass; clearing the ramp mid-loop goes back to full speed
+        pass_speed = match *practice_ramp.lock().unwrap() {
+            Some(ramp) => ramp.next_speed(pass_speed),
+            None => 1.0,
+        };
     }
 
     is_playing.store(false, Ordering::SeqCst);
-    let _ = window.emit("playback-ended", ());
-}
\ No newline at end of file
+    signal.notify();
+    sink.stats(&stats);
+    sink.ended();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(time_ms: u64, event_type: EventType, note: u8) -> TimedEvent {
+        TimedEvent { time_ms, event_type, note, channel: 0, track: 0 }
+    }
+
+    fn on(time_ms: u64, note: u8) -> TimedEvent {
+        event(time_ms, EventType::NoteOn, note)
+    }
+
+    fn off(time_ms: u64, note: u8) -> TimedEvent {
+        event(time_ms, EventType::NoteOff, note)
+    }
+
+    // (time, kind, note) of each event, for comparing event lists
+    fn outline(events: &[TimedEvent]) -> Vec<(u64, &'static str, u8)> {
+        events
+            .iter()
+            .map(|e| {
+                let kind = match e.event_type {
+                    EventType::NoteOn => "on",
+                    EventType::NoteOff => "off",
+                    EventType::PitchBend(_) => "bend",
+                    EventType::KeyDown(_) => "down",
+                    EventType::KeyUp(_) => "up",
+                    EventType::Marker(_) => "marker",
+                };
+                (e.time_ms, kind, e.note)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sort_keeps_zero_length_note_pressed_then_released() {
+        // A drum hit pressed and released at 100
+        let mut events = vec![on(100, 36), off(100, 36), on(0, 60), off(50, 60)];
+        sort_events(&mut events);
+        assert_eq!(
+            outline(&events),
+            vec![(0, "on", 60), (50, "off", 60), (100, "on", 36), (100, "off", 36)]
+        );
+    }
+
+    #[test]
+    fn sort_releases_a_repeated_note_before_pressing_it_again() {
+        let mut events = vec![
+            on(0, 60),
+            on(100, 60),
+            event(100, EventType::PitchBend(0), 0),
+            off(100, 60),
+            off(200, 60),
+        ];
+        sort_events(&mut events);
+        assert_eq!(
+            outline(&events),
+            vec![(0, "on", 60), (100, "off", 60), (100, "bend", 0), (100, "on", 60), (200, "off", 60)]
+        );
+    }
+
+    #[test]
+    fn sort_handles_repeat_and_zero_length_note_at_one_time() {
+        // A note ends at 100 while a grace note on the same pitch starts and ends at 100
+        let mut events = vec![on(0, 60), on(100, 60), off(100, 60), off(100, 60)];
+        sort_events(&mut events);
+        assert_eq!(
+            outline(&events),
+            vec![(0, "on", 60), (100, "off", 60), (100, "on", 60), (100, "off", 60)]
+        );
+    }
+
+    #[test]
+    fn drop_short_notes_removes_a_grace_note_burst() {
+        let events = vec![
+            on(0, 60),
+            off(500, 60),
+            // 10ms grace notes leading into the next note
+            on(500, 62),
+            off(510, 62),
+            on(510, 64),
+            off(520, 64),
+            // Zero length, with the release listed before the press
+            off(520, 65),
+            on(520, 65),
+            event(525, EventType::PitchBend(0), 0),
+            // A real note on the grace note's pitch must keep its own release
+            on(530, 65),
+            off(1000, 65),
+        ];
+        let kept = drop_short_notes(&events, 30);
+        assert_eq!(
+            outline(&kept),
+            vec![(0, "on", 60), (500, "off", 60), (525, "bend", 0), (530, "on", 65), (1000, "off", 65)]
+        );
+    }
+
+    #[test]
+    fn drop_short_notes_keeps_notes_at_the_limit() {
+        let events = vec![on(0, 60), off(30, 60), on(30, 62), off(40, 62)];
+        let kept = drop_short_notes(&events, 30);
+        assert_eq!(outline(&kept), vec![(0, "on", 60), (30, "off", 60)]);
+    }
+
+    #[test]
+    fn quantize_snaps_to_the_nearest_grid_line() {
+        let events = vec![on(130, 60), off(160, 60), on(260, 62), off(380, 62)];
+        let quantized = quantize_events(&events, 100);
+        assert_eq!(
+            outline(&quantized),
+            vec![(100, "on", 60), (200, "off", 60), (300, "on", 62), (400, "off", 62)]
+        );
+    }
+
+    #[test]
+    fn quantize_keeps_a_short_note_pressed_before_its_release() {
+        // Both ends snap to 100, so the release moves a grid line later
+        let events = vec![on(140, 60), off(145, 60)];
+        let quantized = quantize_events(&events, 100);
+        assert_eq!(outline(&quantized), vec![(100, "on", 60), (200, "off", 60)]);
+    }
+
+    #[test]
+    fn reported_position_never_passes_the_end() {
+        assert_eq!(reported_position(12.5, 10.0), 10.0);
+        assert_eq!(reported_position(-0.2, 10.0), 0.0);
+        assert_eq!(reported_position(4.0, 10.0), 4.0);
+    }
+
+    #[test]
+    fn without_leading_silence_starts_at_the_first_note() {
+        let marker = |time_ms: u64, name: &str| Marker { time_ms, name: name.to_string() };
+        // 3 seconds of rest with a setup bend and a marker before the first note
+        let midi_data = MidiData {
+            events: vec![
+                event(500, EventType::PitchBend(0), 0),
+                event(1000, EventType::Marker(0), 0),
+                on(3000, 60),
+                off(3500, 60),
+                event(4000, EventType::Marker(1), 0),
+                on(4000, 62),
+                off(5000, 62),
+            ],
+            duration: 5.0,
+            transpose: 0,
+            tempo_map: TempoMap::constant(500_000.0),
+            markers: vec![marker(1000, "Intro"), marker(4000, "Verse")],
+            volumes: VolumeTimeline::default(),
+        };
+
+        let trimmed = midi_data.without_leading_silence();
+        assert_eq!(
+            outline(&trimmed.events),
+            vec![
+                (0, "bend", 0),
+                (0, "marker", 0),
+                (0, "on", 60),
+                (500, "off", 60),
+                (1000, "marker", 0),
+                (1000, "on", 62),
+                (2000, "off", 62),
+            ]
+        );
+        let marker_times: Vec<u64> = trimmed.markers.iter().map(|m| m.time_ms).collect();
+        assert_eq!(marker_times, vec![0, 1000]);
+        assert_eq!(trimmed.duration, 2.0);
+    }
+
+    #[test]
+    fn band_shift_applies_after_folding() {
+        let (lo, hi) = active_profile().range();
+        let map = |note: i32, band_shifts: [i8; 3]| map_note_to_key(NoteMode::Closest, note, 0, 0, band_shifts);
+
+        // Two octaves below range folds onto the low row, then moves up to the mid row
+        assert_eq!(map(lo - 24, [12, 0, 0]), map(lo + 12, [0; 3]));
+        // Shifting past the top stays on the top key
+        assert_eq!(map(hi, [0, 0, 12]), map(hi, [0; 3]));
+        assert_eq!(map(hi, [0, 0, -12]), map(hi - 12, [0; 3]));
+    }
+
+    #[test]
+    fn humanize_keeps_notes_on_one_key_apart() {
+        // Two pitches that land on the same game key, one after the other
+        let events = vec![on(0, 60), off(100, 60), on(100, 61), off(200, 61)];
+        for seed in 0..50 {
+            let humanized = humanize_events(&events, 40, seed, |_| "a".to_string());
+            let second_press = humanized.iter().find(|e| e.note == 61 && matches!(e.event_type, EventType::NoteOn));
+            assert!(second_press.unwrap().time_ms >= 100);
+        }
+    }
+
+    // C major in first inversion (E, G, C), all released at 500
+    fn inverted_chord() -> Vec<TimedEvent> {
+        vec![on(0, 64), on(0, 67), on(0, 72), off(500, 64), off(500, 67), off(500, 72)]
+    }
+
+    #[test]
+    fn reduce_chords_keeps_one_voice_per_mode() {
+        let events = inverted_chord();
+        let kept = |mode| outline(&reduce_chords(&events, mode, None));
+        assert_eq!(kept(ChordMode::Top), vec![(0, "on", 72), (500, "off", 72)]);
+        assert_eq!(kept(ChordMode::Bottom), vec![(0, "on", 64), (500, "off", 64)]);
+        // The root of the inversion, not its bass note
+        assert_eq!(kept(ChordMode::Root), vec![(0, "on", 72), (500, "off", 72)]);
+        assert_eq!(kept(ChordMode::Off), outline(&events));
+    }
+
+    #[test]
+    fn reduce_chords_drops_only_the_releases_of_dropped_notes() {
+        let events = vec![
+            on(0, 60),
+            on(0, 64),
+            off(100, 60),
+            // The dropped pitch again, on its own: it plays with its own release
+            on(200, 60),
+            off(300, 60),
+            off(400, 64),
+        ];
+        let reduced = reduce_chords(&events, ChordMode::Top, None);
+        assert_eq!(
+            outline(&reduced),
+            vec![(0, "on", 64), (200, "on", 60), (300, "off", 60), (400, "off", 64)]
+        );
+    }
+
+    #[test]
+    fn quantize_pairs_releases_within_their_own_track() {
+        let events = vec![
+            on_track(0, EventType::NoteOn, 60, 0),
+            // Track 1's short note on the same pitch snaps to a single grid line
+            on_track(90, EventType::NoteOn, 60, 1),
+            on_track(110, EventType::NoteOff, 60, 1),
+            on_track(400, EventType::NoteOff, 60, 0),
+        ];
+        let quantized = quantize_events(&events, 100);
+        let times = |track: usize| -> Vec<u64> {
+            quantized.iter().filter(|e| e.track == track).map(|e| e.time_ms).collect()
+        };
+        assert_eq!(times(0), vec![0, 400]);
+        assert_eq!(times(1), vec![100, 200]);
+    }
+
+    // Records what play_midi reports, in place of the UI
+    #[derive(Clone, Default)]
+    struct RecordingSink {
+        progress: Arc<Mutex<Vec<f64>>>,
+        pressed: Arc<Mutex<std::collections::HashSet<String>>>,
+        ended: Arc<AtomicUsize>,
+    }
+
+    impl PlaybackSink for RecordingSink {
+        fn progress(&self, position: f64) {
+            self.progress.lock().unwrap().push(position);
+        }
+
+        fn ended(&self) {
+            self.ended.fetch_add(1, Ordering::SeqCst);
+        }
+
+        fn active_keys(&self, keys: &[String]) {
+            self.pressed.lock().unwrap().extend(keys.iter().cloned());
+        }
+    }
+
+    fn live_settings() -> LiveSettings {
+        LiveSettings {
+            loop_gap_ms: Arc::new(std::sync::atomic::AtomicU64::new(0)),
+            seamless_loop: Arc::new(AtomicBool::new(false)),
+            note_mode: Arc::new(AtomicU8::new(NoteMode::Closest as u8)),
+            output_mode: Arc::new(AtomicU8::new(crate::midi_out::OutputMode::GameKeyboard as u8)),
+            octave_shift: Arc::new(std::sync::atomic::AtomicI8::new(0)),
+            octave_shifts: Arc::new(Mutex::new([0; 3])),
+            transpose: Arc::new(std::sync::atomic::AtomicI32::new(0)),
+            live_transpose: Arc::new(std::sync::atomic::AtomicI32::new(0)),
+            chord_mode: Arc::new(AtomicU8::new(ChordMode::Off as u8)),
+            min_note_duration_ms: Arc::new(std::sync::atomic::AtomicU64::new(0)),
+            min_channel_volume: Arc::new(AtomicU8::new(0)),
+            time_quantize_ms: Arc::new(std::sync::atomic::AtomicU64::new(0)),
+            swing_ratio: Arc::new(Mutex::new(STRAIGHT_SWING)),
+            humanize_ms: Arc::new(std::sync::atomic::AtomicU64::new(0)),
+            humanize_seed: Arc::new(std::sync::atomic::AtomicU64::new(0)),
+            latency_offset_ms: Arc::new(std::sync::atomic::AtomicI64::new(0)),
+            pitch_bend: Arc::new(AtomicBool::new(false)),
+            bass_tremolo: Arc::new(AtomicBool::new(false)),
+            bass_tremolo_rate_ms: Arc::new(std::sync::atomic::AtomicU64::new(60)),
+            detache: Arc::new(AtomicBool::new(false)),
+            note_off_delay_ms: Arc::new(std::sync::atomic::AtomicU64::new(0)),
+            max_nps: Arc::new(std::sync::atomic::AtomicU32::new(0)),
+            pause_at_markers: Arc::new(AtomicBool::new(false)),
+            call_and_response: Arc::new(AtomicBool::new(false)),
+            progress_interval_ms: Arc::new(std::sync::atomic::AtomicU64::new(16)),
+            pause_on_focus_loss: Arc::new(AtomicBool::new(false)),
+            resume_on_focus: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    #[test]
+    fn play_midi_reports_progress_and_end_to_its_sink() {
+        crate::keyboard::set_test_mode(true);
+        let midi_data = MidiData {
+            events: vec![on(0, 60), off(100, 60), on(100, 64), off(200, 64)],
+            duration: 0.2,
+            transpose: 0,
+            tempo_map: TempoMap::constant(500_000.0),
+            markers: Vec::new(),
+            volumes: VolumeTimeline::default(),
+        };
+        let is_playing = Arc::new(AtomicBool::new(true));
+        let run = PlaybackRun {
+            is_playing: Arc::clone(&is_playing),
+            is_paused: Arc::new(AtomicBool::new(false)),
+            loop_mode: Arc::new(AtomicBool::new(false)),
+            loop_count: Arc::new(std::sync::atomic::AtomicU32::new(1)),
+            loops_remaining: Arc::new(std::sync::atomic::AtomicU32::new(0)),
+            current_position: Arc::new(Mutex::new(0.0)),
+            start_position: 0.0,
+            seek_request: Arc::new(std::sync::atomic::AtomicU64::new(NO_SEEK)),
+            playback_stats: Arc::new(Mutex::new(PlaybackStats::default())),
+            signal: Arc::new(PlaybackSignal::default()),
+            note_overrides: Arc::new(Mutex::new(std::collections::HashMap::new())),
+            active_tracks: Arc::new(Mutex::new(None)),
+            practice_ramp: Arc::new(Mutex::new(None)),
+            practice_speed: Arc::new(Mutex::new(1.0)),
+            start_delay_ms: 0,
+        };
+        let sink = RecordingSink::default();
+
+        // Plays on this thread and returns once the song is over
+        play_midi(midi_data, run, live_settings(), sink.clone());
+
+        assert!(!is_playing.load(Ordering::SeqCst));
+        assert_eq!(sink.ended.load(Ordering::SeqCst), 1);
+        let progress = sink.progress.lock().unwrap();
+        assert!(!progress.is_empty());
+        assert!(progress.iter().all(|&position| (0.0..=0.2).contains(&position)));
+        let pressed = sink.pressed.lock().unwrap();
+        for note in [60, 64] {
+            assert!(pressed.contains(&map_note_to_key(NoteMode::Closest, note, 0, 0, [0; 3])));
+        }
+    }
+
+    // Write a one-track file of (start, length, key) notes in ticks to the temp dir.
+    // 500 ticks per beat at the default 120 BPM makes one tick one millisecond.
+    fn write_midi(name: &str, notes: &[(u32, u32, u8)]) -> String {
+        use midly::num::{u15, u28, u4, u7};
+
+        let mut timed: Vec<(u32, MidiMessage)> = Vec::with_capacity(notes.len() * 2);
+        for &(start, length, key) in notes {
+            timed.push((start, MidiMessage::NoteOn { key: u7::new(key), vel: u7::new(100) }));
+            timed.push((start + length, MidiMessage::NoteOff { key: u7::new(key), vel: u7::new(0) }));
+        }
+        timed.sort_by_key(|(time, _)| *time);
+
+        let mut track = Vec::with_capacity(timed.len() + 1);
+        let mut last_time = 0;
+        for (time, message) in timed {
+            track.push(midly::TrackEvent {
+                delta: u28::new(time - last_time),
+                kind: TrackEventKind::Midi { channel: u4::new(0), message },
+            });
+            last_time = time;
+        }
+        track.push(midly::TrackEvent {
+            delta: u28::new(0),
+            kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+        });
+
+        let mut smf = Smf::new(midly::Header::new(
+            midly::Format::SingleTrack,
+            midly::Timing::Metrical(u15::new(500)),
+        ));
+        smf.tracks.push(track);
+        let path = std::env::temp_dir().join(format!("wwm-test-{}-{}.mid", std::process::id(), name));
+        smf.save(&path).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn load_midi_parses_a_large_file() {
+        // 50k back-to-back 10ms notes: 100k events
+        let notes: Vec<(u32, u32, u8)> = (0..50_000).map(|i| (i * 20, 10, 48 + (i % 24) as u8)).collect();
+        let path = write_midi("large", &notes);
+        let midi_data = load_midi(&path);
+        std::fs::remove_file(&path).ok();
+
+        let midi_data = midi_data.unwrap();
+        assert_eq!(midi_data.events.len(), 100_000);
+        assert!((midi_data.duration - 999.99).abs() < 0.01);
+    }
+
+    #[test]
+    fn duration_matches_between_file_list_and_loaded_song() {
+        let path = write_midi("duration", &[(0, 400, 60), (200, 1300, 64), (1000, 200, 67)]);
+        let listed = get_midi_duration(&path);
+        let loaded = load_midi(&path);
+        std::fs::remove_file(&path).ok();
+
+        let listed = listed.unwrap();
+        assert_eq!(listed, loaded.unwrap().duration);
+        assert!((listed - 1.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn load_rejects_a_file_without_notes() {
+        let path = write_midi("no-notes", &[]);
+        let result = load_midi_sequence(&path, 0);
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(result.err().as_deref(), Some("MIDI file contains no notes"));
+    }
+
+    // Melody on track 0 and an accompaniment on track 1 sounding the same pitches
+    fn on_track(time_ms: u64, event_type: EventType, note: u8, track: usize) -> TimedEvent {
+        TimedEvent { track, ..event(time_ms, event_type, note) }
+    }
+
+    #[test]
+    fn tracks_on_the_same_pitch_stay_independent() {
+        let events = vec![
+            on_track(0, EventType::NoteOn, 60, 0),
+            // Track 1 plays a short note on the same pitch inside track 0's long one
+            on_track(100, EventType::NoteOn, 60, 1),
+            on_track(110, EventType::NoteOff, 60, 1),
+            on_track(500, EventType::NoteOff, 60, 0),
+        ];
+        assert_eq!(max_polyphony(&events), 2);
+
+        // Dropping track 1's short note must leave track 0's whole note alone
+        let kept = drop_short_notes(&events, 50);
+        assert_eq!(outline(&kept), vec![(0, "on", 60), (500, "off", 60)]);
+        assert!(kept.iter().all(|e| e.track == 0));
+    }
+
+    #[test]
+    fn humanize_keeps_each_track_pressed_before_its_own_release() {
+        let events = vec![
+            on_track(0, EventType::NoteOn, 60, 0),
+            on_track(100, EventType::NoteOn, 60, 1),
+            on_track(200, EventType::NoteOff, 60, 0),
+            on_track(300, EventType::NoteOff, 60, 1),
+        ];
+        for seed in 0..50 {
+            let humanized = humanize_events(&events, 40, seed, |e| e.note.to_string());
+            for track in 0..2 {
+                let time_of = |press: bool| {
+                    humanized
+                        .iter()
+                        .find(|e| e.track == track && matches!(e.event_type, EventType::NoteOn) == press)
+                        .map(|e| e.time_ms)
+                        .unwrap()
+                };
+                assert!(time_of(true) < time_of(false));
+            }
+        }
+    }
+}
@@ -0,0 +1,128 @@
+#[cfg(target_os = "windows")]
+use std::sync::Mutex;
+use serde::{Serialize, Deserialize};
+#[cfg(target_os = "windows")]
+use windows::Win32::Media::Audio::{
+    midiOutClose, midiOutGetDevCapsW, midiOutGetNumDevs, midiOutOpen, midiOutReset, midiOutShortMsg,
+    CALLBACK_NULL, HMIDIOUT, MIDIOUTCAPSW,
+};
+
+/// Where playback goes: simulated game keystrokes or a MIDI output device
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[repr(u8)]
+pub enum OutputMode {
+    GameKeyboard = 0,
+    MidiPort = 1,
+}
+
+impl From<u8> for OutputMode {
+    fn from(value: u8) -> Self {
+        match value {
+            1 => OutputMode::MidiPort,
+            _ => OutputMode::GameKeyboard,
+        }
+    }
+}
+
+// Fixed velocity for notes sent to the port (events don't keep the original one)
+const NOTE_VELOCITY: u32 = 100;
+
+#[cfg(target_os = "windows")]
+struct MidiOutPort(HMIDIOUT);
+
+// The handle is only used behind the OUTPUT mutex
+#[cfg(target_os = "windows")]
+unsafe impl Send for MidiOutPort {}
+
+#[cfg(target_os = "windows")]
+impl Drop for MidiOutPort {
+    fn drop(&mut self) {
+        unsafe {
+            midiOutReset(self.0);
+            midiOutClose(self.0);
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+lazy_static::lazy_static! {
+    static ref OUTPUT: Mutex<Option<MidiOutPort>> = Mutex::new(None);
+}
+
+/// Names of the MIDI output devices, indexed as set_midi_output expects
+#[cfg(target_os = "windows")]
+pub fn list_midi_outputs() -> Vec<String> {
+    let count = unsafe { midiOutGetNumDevs() };
+    (0..count)
+        .map(|index| {
+            let mut caps = MIDIOUTCAPSW::default();
+            let result = unsafe {
+                midiOutGetDevCapsW(index as usize, &mut caps, std::mem::size_of::<MIDIOUTCAPSW>() as u32)
+            };
+            if result != 0 {
+                return format!("MIDI output {}", index + 1);
+            }
+            let len = caps.szPname.iter().position(|&c| c == 0).unwrap_or(caps.szPname.len());
+            String::from_utf16_lossy(&caps.szPname[..len])
+        })
+        .collect()
+}
+
+#[cfg(not(target_os = "windows"))]
+pub fn list_midi_outputs() -> Vec<String> {
+    Vec::new()
+}
+
+/// Open the output device at `index`, closing any previously opened one
+#[cfg(target_os = "windows")]
+pub fn set_midi_output(index: u32) -> Result<String, String> {
+    let names = list_midi_outputs();
+    let name = names
+        .get(index as usize)
+        .cloned()
+        .ok_or_else(|| format!("MIDI output {} not found ({} available)", index, names.len()))?;
+
+    let mut output = OUTPUT.lock().unwrap();
+    // Close first: some drivers only allow one open handle per device
+    *output = None;
+
+    let mut handle = HMIDIOUT::default();
+    let result = unsafe { midiOutOpen(&mut handle, index, 0, 0, CALLBACK_NULL) };
+    if result != 0 {
+        return Err(format!("Failed to open MIDI output \"{}\" (error {})", name, result));
+    }
+    *output = Some(MidiOutPort(handle));
+    Ok(name)
+}
+
+#[cfg(not(target_os = "windows"))]
+pub fn set_midi_output(_index: u32) -> Result<String, String> {
+    Err("MIDI output is only supported on Windows".to_string())
+}
+
+#[cfg(target_os = "windows")]
+fn send(message: u32) {
+    if let Some(port) = OUTPUT.lock().unwrap().as_ref() {
+        unsafe {
+            midiOutShortMsg(port.0, message);
+        }
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+fn send(_message: u32) {}
+
+pub fn note_on(channel: u8, pitch: u8) {
+    send(0x90 | (channel as u32 & 0x0F) | ((pitch as u32) << 8) | (NOTE_VELOCITY << 16));
+}
+
+pub fn note_off(channel: u8, pitch: u8) {
+    send(0x80 | (channel as u32 & 0x0F) | ((pitch as u32) << 8));
+}
+
+/// Silence every channel (CC 123, All Notes Off)
+pub fn all_notes_off() {
+    for channel in 0..16u32 {
+        send(0xB0 | channel | (123 << 8));
+    }
+}
@@ -0,0 +1,106 @@
+use std::io::{Read, Seek};
+
+/// One file inside a song pack, or why it couldn't be extracted
+pub struct PackEntry {
+    pub name: String,
+    pub data: Result<Vec<u8>, String>,
+}
+
+// Largest file taken from a pack. No song comes close, so anything bigger is skipped
+// rather than inflated without bound (zip bombs).
+const MAX_ENTRY_BYTES: u64 = 8 * 1024 * 1024;
+
+/// Read every file entry (directories skipped) from a zip archive
+pub fn read_zip_entries(path: &str) -> Result<Vec<PackEntry>, String> {
+    let file = std::fs::File::open(path).map_err(|e| e.to_string())?;
+    read_zip(file)
+}
+
+fn read_zip(reader: impl Read + Seek) -> Result<Vec<PackEntry>, String> {
+    let mut archive = zip::ZipArchive::new(reader).map_err(|e| format!("Not a zip file: {}", e))?;
+
+    let mut entries = Vec::new();
+    for index in 0..archive.len() {
+        let entry = match archive.by_index(index) {
+            Ok(entry) => entry,
+            Err(e) => {
+                entries.push(PackEntry {
+                    name: format!("Entry {}", index + 1),
+                    data: Err(e.to_string()),
+                });
+                continue;
+            }
+        };
+        if entry.is_dir() {
+            continue;
+        }
+        let name = entry.name().to_string();
+        entries.push(PackEntry {
+            data: read_entry(entry),
+            name,
+        });
+    }
+    Ok(entries)
+}
+
+// Contents of one entry, refusing to read past MAX_ENTRY_BYTES
+fn read_entry(entry: impl Read) -> Result<Vec<u8>, String> {
+    let mut contents = Vec::new();
+    entry
+        .take(MAX_ENTRY_BYTES + 1)
+        .read_to_end(&mut contents)
+        .map_err(|e| format!("Failed to extract: {}", e))?;
+    if contents.len() as u64 > MAX_ENTRY_BYTES {
+        return Err(format!("Larger than {}MB, not a song", MAX_ENTRY_BYTES / (1024 * 1024)));
+    }
+    Ok(contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+
+    fn build_zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        writer.add_directory("songs/", zip::write::SimpleFileOptions::default()).unwrap();
+        for (name, data) in files {
+            writer.start_file(*name, zip::write::SimpleFileOptions::default()).unwrap();
+            writer.write_all(data).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn reads_every_file_and_skips_directories() {
+        let zip = build_zip(&[("songs/a.mid", b"MThd first"), ("b.mid", b"MThd second")]);
+        let entries = read_zip(Cursor::new(zip)).unwrap();
+
+        let names: Vec<&str> = entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["songs/a.mid", "b.mid"]);
+        assert_eq!(entries[0].data.as_deref(), Ok(&b"MThd first"[..]));
+        assert_eq!(entries[1].data.as_deref(), Ok(&b"MThd second"[..]));
+    }
+
+    #[test]
+    fn rejects_data_that_is_not_a_zip() {
+        assert!(read_zip(Cursor::new(b"MThd not a zip at all".to_vec())).is_err());
+    }
+
+    #[test]
+    fn rejects_a_truncated_zip() {
+        let mut zip = build_zip(&[("a.mid", b"MThd song")]);
+        zip.truncate(zip.len() / 2);
+        assert!(read_zip(Cursor::new(zip)).is_err());
+    }
+
+    #[test]
+    fn skips_entries_over_the_size_limit() {
+        let huge = vec![0u8; MAX_ENTRY_BYTES as usize + 1];
+        let zip = build_zip(&[("bomb.mid", &huge), ("ok.mid", b"MThd song")]);
+        let entries = read_zip(Cursor::new(zip)).unwrap();
+
+        assert!(entries[0].data.is_err());
+        assert_eq!(entries[1].data.as_deref(), Ok(&b"MThd song"[..]));
+    }
+}
@@ -1,6 +1,8 @@
 use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
 use xcap::Monitor;
 use image::{RgbaImage, Rgba, ImageBuffer};
+use serde::{Serialize, Deserialize};
 
 /// Cached button positions for 36-key mode
 /// Each position is (x, y) screen coordinates for clicking
@@ -10,28 +12,222 @@ pub struct ButtonPositions {
     pub sharps: Vec<(i32, i32)>,
     // Flat keys (6 keys) - click positions: Eb, Bb for each octave (low, mid, high)
     pub flats: Vec<(i32, i32)>,
+    // Natural keys (21 keys) - click positions: C, D, E, F, G, A, B for each octave (low, mid, high)
+    pub naturals: Vec<(i32, i32)>,
     pub is_cached: bool,
 }
 
+/// Summary of a scan, so the UI can tell how trustworthy the detected positions are
+#[derive(Debug, Clone, Serialize)]
+pub struct ScanResult {
+    pub width: u32,
+    pub height: u32,
+    // Number of buttons in every row found, top to bottom
+    pub buttons_per_row: Vec<usize>,
+    // Rows with 10-14 buttons, i.e. rows that look like instrument rows
+    pub instrument_rows: usize,
+    pub used_fallback: bool,
+    pub sharps_found: usize,
+    pub flats_found: usize,
+    pub naturals_found: usize,
+}
+
+/// Detection thresholds used by is_game_button, tunable for other UI themes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScanParams {
+    // Brightness range for a button center
+    pub min_brightness: f32,
+    pub max_brightness: f32,
+    // Upper brightness bound for samples inside the button
+    pub inner_max_brightness: f32,
+    // Fraction of inner samples that must be within the brightness range
+    pub dark_ratio: f32,
+    // Minimum brightness difference between the edge and the center
+    pub edge_contrast: f32,
+    // Fraction of edge samples that must show that contrast
+    pub edge_ratio: f32,
+}
+
+impl Default for ScanParams {
+    fn default() -> Self {
+        ScanParams {
+            min_brightness: 25.0,
+            max_brightness: 130.0,
+            inner_max_brightness: 140.0,
+            dark_ratio: 0.75,
+            edge_contrast: 20.0,
+            edge_ratio: 0.25,
+        }
+    }
+}
+
+/// Screen area (in full-screen pixels) that contains the instrument UI
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ScanRegion {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
 lazy_static::lazy_static! {
     pub static ref BUTTON_CACHE: Mutex<ButtonPositions> = Mutex::new(ButtonPositions::default());
+    pub static ref SCAN_PARAMS: Mutex<ScanParams> = Mutex::new(ScanParams::default());
+    // None = scan the lower half of the screen
+    pub static ref SCAN_REGION: Mutex<Option<ScanRegion>> = Mutex::new(None);
+}
+
+pub fn set_scan_params(params: ScanParams) {
+    *SCAN_PARAMS.lock().unwrap() = params;
+}
+
+pub fn get_scan_params() -> ScanParams {
+    SCAN_PARAMS.lock().unwrap().clone()
+}
+
+pub fn set_scan_region(region: Option<ScanRegion>) -> Result<(), String> {
+    if let Some(region) = region {
+        if region.width <= 0 || region.height <= 0 {
+            return Err(format!("Invalid scan region size: {}x{}", region.width, region.height));
+        }
+    }
+    *SCAN_REGION.lock().unwrap() = region;
+    Ok(())
+}
+
+pub fn get_scan_region() -> Option<ScanRegion> {
+    *SCAN_REGION.lock().unwrap()
+}
+
+/// Set by cancel_scan() to abort a running scan between rows
+static SCAN_CANCELLED: AtomicBool = AtomicBool::new(false);
+
+/// Error returned when a scan is aborted via cancel_scan()
+pub const SCAN_CANCELLED_ERROR: &str = "Cancelled";
+
+/// Request that the running scan stops at the next row
+pub fn cancel_scan() {
+    SCAN_CANCELLED.store(true, Ordering::SeqCst);
+}
+
+// Fails with SCAN_CANCELLED_ERROR if cancel_scan() was called since the scan started
+fn check_cancelled() -> Result<(), String> {
+    if SCAN_CANCELLED.load(Ordering::SeqCst) {
+        println!("Scan cancelled");
+        return Err(SCAN_CANCELLED_ERROR.to_string());
+    }
+    Ok(())
+}
+
+// Default wait after focusing the game, so the instrument UI has finished drawing
+pub const AUTO_SCAN_SETTLE_MS: u64 = 500;
+
+/// Bring the game to the front, let it settle, then scan. Fails instead of
+/// scanning whatever else is on screen if the game window can't be found.
+pub fn auto_scan(settle_ms: u64) -> Result<ScanResult, String> {
+    // Cleared before the wait, so a cancel during the settle time isn't lost
+    SCAN_CANCELLED.store(false, Ordering::SeqCst);
+    crate::keyboard::focus_black_desert_window()
+        .map_err(|e| format!("Cannot auto-scan: {}", e))?;
+    std::thread::sleep(std::time::Duration::from_millis(settle_ms));
+    check_cancelled()?;
+    capture_and_scan()
 }
 
 /// Scan the screen to detect button positions
-/// Returns true if detection was successful
-pub fn scan_button_positions() -> Result<bool, String> {
-    // Capture the primary monitor
-    let monitors = Monitor::all().map_err(|e| e.to_string())?;
-    let monitor = monitors.first().ok_or("No monitor found")?;
+/// Returns a summary of what was detected if successful
+pub fn scan_button_positions() -> Result<ScanResult, String> {
+    SCAN_CANCELLED.store(false, Ordering::SeqCst);
+    capture_and_scan()
+}
+
+fn capture_and_scan() -> Result<ScanResult, String> {
+    let screenshot = capture_primary_monitor()?;
+    println!("Screenshot captured: {}x{}", screenshot.width(), screenshot.height());
+    check_cancelled()?;
+    scan_image(&screenshot)
+}
+
+/// Detect button positions from a screenshot saved to disk, for sessions where
+/// screen capture doesn't work. The image should be a full-screen capture so the
+/// detected positions match screen coordinates.
+pub fn scan_from_image(path: &str) -> Result<ScanResult, String> {
+    SCAN_CANCELLED.store(false, Ordering::SeqCst);
+    let screenshot = image::open(path)
+        .map_err(|e| format!("Failed to open screenshot {}: {}", path, e))?
+        .to_rgba8();
+    println!("Screenshot loaded from {}: {}x{}", path, screenshot.width(), screenshot.height());
+    check_cancelled()?;
+    scan_image(&screenshot)
+}
+
+/// A screen capture for the UI, as base64 PNG so it can go straight into an <img> data URL
+#[derive(Debug, Clone, Serialize)]
+pub struct Screenshot {
+    pub width: u32,
+    pub height: u32,
+    pub png_base64: String,
+}
 
-    let screenshot = monitor.capture_image().map_err(|e| e.to_string())?;
-    let width = screenshot.width();
-    let height = screenshot.height();
+/// Capture the monitor the scan uses, without scanning or writing anything to disk
+pub fn capture_screenshot() -> Result<Screenshot, String> {
+    let screenshot = capture_primary_monitor()?;
+    let mut png = Vec::new();
+    screenshot
+        .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+        .map_err(|e| format!("Failed to encode screenshot: {}", e))?;
+    Ok(Screenshot {
+        width: screenshot.width(),
+        height: screenshot.height(),
+        png_base64: base64_encode(&png),
+    })
+}
 
-    println!("Screenshot captured: {}x{}", width, height);
+// Standard base64 with padding
+fn base64_encode(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let group = ((bytes[0] as u32) << 16) | ((bytes[1] as u32) << 8) | bytes[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[((group >> (18 - 6 * i)) & 0x3F) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
 
+/// Capture the first monitor, telling apart a session where capture isn't available
+/// (remote desktop, headless) from one that really has no monitor
+fn capture_primary_monitor() -> Result<RgbaImage, String> {
+    let monitors = Monitor::all().map_err(|e| {
+        format!(
+            "Screen capture is not supported in this session ({}). Take a screenshot manually and scan it from file instead",
+            e
+        )
+    })?;
+    let monitor = monitors.first().ok_or(
+        "No monitor found. If this is a remote or headless session, take a screenshot manually and scan it from file instead",
+    )?;
+
+    monitor.capture_image().map_err(|e| {
+        format!(
+            "Failed to capture monitor {} ({}). Capture may be blocked in this session; take a screenshot manually and scan it from file instead",
+            monitor.name(),
+            e
+        )
+    })
+}
+
+fn scan_image(screenshot: &RgbaImage) -> Result<ScanResult, String> {
     // Detect buttons and save debug image
-    let detected = detect_button_grid(&screenshot)?;
+    let params = get_scan_params();
+    let (detected, result) = detect_button_grid(screenshot, &params, get_scan_region())?;
 
     if detected.is_cached {
         let mut cache = BUTTON_CACHE.lock().unwrap();
@@ -45,7 +241,11 @@ pub fn scan_button_positions() -> Result<bool, String> {
         for (i, pos) in cache.flats.iter().enumerate() {
             println!("  [{}] {:?}", i, pos);
         }
-        Ok(true)
+        println!("Naturals ({}):", cache.naturals.len());
+        for (i, pos) in cache.naturals.iter().enumerate() {
+            println!("  [{}] {:?}", i, pos);
+        }
+        Ok(result)
     } else {
         Err("Could not detect button positions".to_string())
     }
@@ -122,7 +322,7 @@ fn draw_cross(img: &mut RgbaImage, cx: i32, cy: i32, size: i32, color: [u8; 4])
 }
 
 /// Detect the button grid from the screenshot
-fn detect_button_grid(img: &RgbaImage) -> Result<ButtonPositions, String> {
+fn detect_button_grid(img: &RgbaImage, params: &ScanParams, region: Option<ScanRegion>) -> Result<(ButtonPositions, ScanResult), String> {
     let width = img.width() as i32;
     let height = img.height() as i32;
 
@@ -139,15 +339,24 @@ fn detect_button_grid(img: &RgbaImage) -> Result<ButtonPositions, String> {
 
     println!("Detection params: scale={:.2}, radius={}, step={}", scale, button_radius, step);
 
-    // Scan the lower portion of the screen where the instrument UI typically is
-    let scan_top = height / 2;  // Start from middle of screen
-    let scan_bottom = height - 50;
-    let scan_left = 50;
-    let scan_right = width - 50;
+    // Scan the user's region if set, otherwise the lower portion of the screen where the
+    // instrument UI typically is. Coordinates stay in full-screen space either way.
+    let (scan_left, scan_top, scan_right, scan_bottom) = match region {
+        Some(region) => (
+            region.x.max(0),
+            region.y.max(0),
+            (region.x + region.width).min(width),
+            (region.y + region.height).min(height),
+        ),
+        None => (50, height / 2, width - 50, height - 50),
+    };
+    println!("Scan area: ({}, {}) to ({}, {})", scan_left, scan_top, scan_right, scan_bottom);
 
     for y in (scan_top..scan_bottom).step_by(step as usize) {
+        check_cancelled()?;
+
         for x in (scan_left..scan_right).step_by(step as usize) {
-            if is_game_button(img, x, y, button_radius) {
+            if is_game_button(img, x, y, button_radius, params) {
                 // Refine position to find actual button center
                 let (cx, cy) = refine_button_center(img, x, y, button_radius);
 
@@ -211,7 +420,8 @@ fn detect_button_grid(img: &RgbaImage) -> Result<ButtonPositions, String> {
 
     println!("Found {} instrument rows (10-14 buttons each)", instrument_rows.len());
 
-    let positions = if instrument_rows.len() >= 3 {
+    let used_fallback = instrument_rows.len() < 3;
+    let positions = if !used_fallback {
         // Use the detected rows to identify sharp/flat positions
         identify_positions_from_rows(&instrument_rows)
     } else {
@@ -223,7 +433,18 @@ fn detect_button_grid(img: &RgbaImage) -> Result<ButtonPositions, String> {
     // Save debug image
     save_debug_image(img, &button_centers, &positions.sharps, &positions.flats);
 
-    Ok(positions)
+    let result = ScanResult {
+        width: img.width(),
+        height: img.height(),
+        buttons_per_row: rows.iter().map(|row| row.len()).collect(),
+        instrument_rows: instrument_rows.len(),
+        used_fallback,
+        sharps_found: positions.sharps.len(),
+        flats_found: positions.flats.len(),
+        naturals_found: positions.naturals.len(),
+    };
+
+    Ok((positions, result))
 }
 
 /// Refine button center by searching for the darkest/most consistent area nearby
@@ -300,7 +521,7 @@ fn calculate_center_score(img: &RgbaImage, cx: i32, cy: i32, radius: i32) -> f32
 }
 
 /// Check if a position looks like a game button
-fn is_game_button(img: &RgbaImage, cx: i32, cy: i32, radius: i32) -> bool {
+fn is_game_button(img: &RgbaImage, cx: i32, cy: i32, radius: i32, params: &ScanParams) -> bool {
     let width = img.width() as i32;
     let height = img.height() as i32;
 
@@ -319,7 +540,7 @@ fn is_game_button(img: &RgbaImage, cx: i32, cy: i32, radius: i32) -> bool {
     let brightness = (r + g + b) / 3.0;
 
     // Button centers should be darker (roughly 30-100 brightness)
-    if brightness < 25.0 || brightness > 130.0 {
+    if brightness < params.min_brightness || brightness > params.max_brightness {
         return false;
     }
 
@@ -338,7 +559,7 @@ fn is_game_button(img: &RgbaImage, cx: i32, cy: i32, radius: i32) -> bool {
         if x >= 0 && x < width && y >= 0 && y < height {
             let p = img.get_pixel(x as u32, y as u32);
             let b = (p[0] as f32 + p[1] as f32 + p[2] as f32) / 3.0;
-            if b >= 25.0 && b <= 140.0 {
+            if b >= params.min_brightness && b <= params.inner_max_brightness {
                 dark_count += 1;
             }
         }
@@ -354,14 +575,15 @@ fn is_game_button(img: &RgbaImage, cx: i32, cy: i32, radius: i32) -> bool {
             let p = img.get_pixel(x as u32, y as u32);
             let b = (p[0] as f32 + p[1] as f32 + p[2] as f32) / 3.0;
             // Edge should be different from center
-            if (b - brightness).abs() > 20.0 {
+            if (b - brightness).abs() > params.edge_contrast {
                 edge_dark_count += 1;
             }
         }
     }
 
     // Should have mostly dark inner pixels and some edge contrast
-    dark_count >= samples * 3 / 4 && edge_dark_count >= samples / 4
+    dark_count as f32 >= samples as f32 * params.dark_ratio
+        && edge_dark_count as f32 >= samples as f32 * params.edge_ratio
 }
 
 /// Identify sharp and flat positions from detected rows
@@ -392,9 +614,15 @@ fn identify_positions_from_rows(rows: &[&Vec<(i32, i32)>]) -> ButtonPositions {
             // Flats at indices 3, 10 (Eb, Bb)
             positions.flats.push(row[3]);
             positions.flats.push(row[10]);
+
+            // Naturals at indices 0, 2, 4, 5, 7, 9, 11 (C, D, E, F, G, A, B)
+            for &idx in &[0, 2, 4, 5, 7, 9, 11] {
+                positions.naturals.push(row[idx]);
+            }
         } else if row.len() >= 7 {
             // Only natural keys detected, estimate sharp/flat positions
             // Natural keys at indices 0-6 (C, D, E, F, G, A, B)
+            positions.naturals.extend_from_slice(&row[..7]);
             let spacing = if row.len() > 1 { row[1].0 - row[0].0 } else { 80 };
 
             // C# between C(0) and D(1)
@@ -462,6 +690,12 @@ fn estimate_positions_fallback(width: i32, height: i32, scale: f32) -> ButtonPos
             let x = ui_left + button_spacing / 2 + col * button_spacing;
             positions.flats.push((x, y));
         }
+
+        // Naturals at columns 0, 2, 4, 5, 7, 9, 11 (C, D, E, F, G, A, B)
+        for &col in &[0, 2, 4, 5, 7, 9, 11] {
+            let x = ui_left + button_spacing / 2 + col * button_spacing;
+            positions.naturals.push((x, y));
+        }
     }
 
     positions.is_cached = true;
@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use serde::{Serialize, Deserialize};
+
+/// Persisted user settings, stored as settings.json next to the executable
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    // Manual transpose per file path, overriding the auto-detected value
+    pub transpose_overrides: HashMap<String, i32>,
+    // Button scanner detection thresholds
+    pub scan_params: crate::scanner::ScanParams,
+    // Screen area containing the instrument UI (None = lower half of the screen)
+    pub scan_region: Option<crate::scanner::ScanRegion>,
+    // Custom album order (file paths), used by the Custom sort mode
+    pub album_order: Vec<String>,
+    // Name of the selected instrument profile (empty = default)
+    pub instrument_profile: String,
+    // Playback speed for .txt key sheets (None = default)
+    pub key_sheet_notes_per_second: Option<f64>,
+    // Play the next queued song when one ends (None = on)
+    pub autoplay_next: Option<bool>,
+    // Pause before auto-playing the next song
+    pub autoplay_gap_ms: u64,
+    // Input lag compensation: positive presses keys this many ms early
+    pub latency_offset_ms: i64,
+    // Switch to each song's remembered note mode on load (None = on)
+    pub apply_song_preferences: Option<bool>,
+    // What play does when the game window isn't focused
+    pub on_not_focused: NotFocusedPolicy,
+    // Folders songs are listed from (empty = the album folder next to the executable)
+    pub library_paths: Vec<String>,
+    // Library that imports go into (None or not in library_paths = the first library)
+    pub default_library: Option<String>,
+    // Two-handed split onto a second key cluster (None = one cluster)
+    pub hand_split: Option<crate::midi::HandSplit>,
+    // Whether play brings the game window to the front
+    pub auto_focus_mode: AutoFocusMode,
+    // Auto-transpose penalty for notes in the outer rows (0 = off)
+    pub register_weight: u32,
+    // Remember the last song and position so it can be resumed after a restart (None = on)
+    pub remember_last_song: Option<bool>,
+    // The song playing when last saved and how far it got, in seconds
+    pub last_file: Option<String>,
+    pub last_position: f64,
+}
+
+/// When play raises the game window
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum AutoFocusMode {
+    // On every play (original behavior)
+    #[default]
+    Always,
+    // Only when another window is in front
+    IfNotFocused,
+    // Leave focus alone
+    Never,
+}
+
+/// How play handles the game window not being in the foreground
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum NotFocusedPolicy {
+    // Bring the game to the front after starting (original behavior)
+    #[default]
+    AutoFocus,
+    // Start paused and resume once the game gets focus
+    Pause,
+    // Don't start at all
+    Refuse,
+}
+
+fn settings_path() -> Result<PathBuf, String> {
+    let exe_path = std::env::current_exe().map_err(|e| e.to_string())?;
+    let exe_dir = exe_path.parent().ok_or("Failed to get executable directory")?;
+    Ok(exe_dir.join("settings.json"))
+}
+
+/// Load settings from disk, falling back to defaults if missing or invalid
+pub fn load_settings() -> Settings {
+    let path = match settings_path() {
+        Ok(path) => path,
+        Err(_) => return Settings::default(),
+    };
+
+    match std::fs::read_to_string(&path) {
+        Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+            eprintln!("Failed to parse settings, using defaults: {}", e);
+            Settings::default()
+        }),
+        Err(_) => Settings::default(),
+    }
+}
+
+pub fn save_settings(settings: &Settings) -> Result<(), String> {
+    let path = settings_path()?;
+    let contents = serde_json::to_string_pretty(settings).map_err(|e| e.to_string())?;
+    std::fs::write(&path, contents).map_err(|e| format!("Failed to save settings: {}", e))
+}
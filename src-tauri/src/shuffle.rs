@@ -0,0 +1,81 @@
+use std::collections::HashSet;
+use std::hash::{BuildHasher, Hasher};
+
+/// Random order through a playlist that plays every track once before repeating.
+/// Tracks are identified by their index in the frontend's queue.
+#[derive(Debug, Default)]
+pub struct Shuffle {
+    enabled: bool,
+    track_count: usize,
+    // Tracks in the order they were played this round, and where we are in it
+    history: Vec<usize>,
+    position: usize,
+}
+
+// Random number below `n` without pulling in a crate: RandomState is seeded per instance
+fn random_below(n: usize) -> usize {
+    let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+    if let Ok(elapsed) = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH) {
+        hasher.write_u128(elapsed.as_nanos());
+    }
+    (hasher.finish() % n as u64) as usize
+}
+
+impl Shuffle {
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Turning shuffle on or off starts a fresh round from the current track
+    pub fn set_enabled(&mut self, enabled: bool, current: usize, track_count: usize) {
+        self.enabled = enabled;
+        self.restart(current, track_count);
+    }
+
+    fn restart(&mut self, current: usize, track_count: usize) {
+        self.track_count = track_count;
+        self.history = vec![current];
+        self.position = 0;
+    }
+
+    /// Pick a random track not yet played this round
+    pub fn next(&mut self, current: usize, track_count: usize) -> usize {
+        if track_count == 0 {
+            return 0;
+        }
+        // The queue changed, so old indices mean nothing
+        if track_count != self.track_count || self.history.get(self.position) != Some(&current) {
+            self.restart(current, track_count);
+        }
+
+        // Went back earlier: walk forward through the same order again
+        if self.position + 1 < self.history.len() {
+            self.position += 1;
+            return self.history[self.position];
+        }
+
+        let played: HashSet<usize> = self.history.iter().copied().collect();
+        let mut unplayed: Vec<usize> = (0..track_count).filter(|i| !played.contains(i)).collect();
+        if unplayed.is_empty() {
+            // Everything played: new round, avoiding an immediate repeat when possible
+            self.restart(current, track_count);
+            unplayed = (0..track_count).filter(|&i| i != current || track_count == 1).collect();
+        }
+
+        let next = unplayed[random_below(unplayed.len())];
+        self.history.push(next);
+        self.position = self.history.len() - 1;
+        next
+    }
+
+    /// Step back through the tracks played this round
+    pub fn previous(&mut self, current: usize, track_count: usize) -> usize {
+        if track_count != self.track_count || self.history.get(self.position) != Some(&current) {
+            self.restart(current, track_count);
+        }
+        if self.position > 0 {
+            self.position -= 1;
+        }
+        self.history[self.position]
+    }
+}
@@ -1,10 +1,40 @@
 use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, AtomicU8, AtomicI8, Ordering};
-use std::time::Instant;
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicI8, AtomicI32, AtomicI64, AtomicU32, AtomicU64, Ordering};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 use tauri::Window;
 use serde::{Serialize, Deserialize};
 
-use crate::midi::NoteMode;
+use crate::midi::{LiveSettings, NoteMode, PlaybackRun, PlaybackSignal, PlaybackSink};
+use crate::settings::Settings;
+
+// How long stop_playback waits for the playback thread to release keys and exit
+const STOP_JOIN_TIMEOUT_MS: u64 = 1000;
+// Largest input lag compensation either way
+const MAX_LATENCY_OFFSET_MS: i64 = 500;
+// Largest humanize jitter, beyond which timing sounds sloppy rather than human
+const MAX_HUMANIZE_MS: u64 = 50;
+// Fastest bass tremolo; quicker re-presses get dropped by the game
+const MIN_BASS_TREMOLO_RATE_MS: u64 = 30;
+// Longest note-off delay; past this it stops sounding like the same articulation
+const MAX_NOTE_OFF_DELAY_MS: u64 = 500;
+// Longest preview snippet; past this it's just playing the song
+const MAX_PREVIEW_SECONDS: f64 = 30.0;
+// Resume points closer than this to the end start the song over instead
+const RESUME_END_MARGIN_SECONDS: f64 = 2.0;
+
+/// Wait for a playback thread to detect its stop flag and release its keys
+fn join_playback_thread(handle: JoinHandle<()>) {
+    let deadline = Instant::now() + Duration::from_millis(STOP_JOIN_TIMEOUT_MS);
+    while !handle.is_finished() && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    if handle.is_finished() {
+        let _ = handle.join();
+    } else {
+        eprintln!("Playback thread did not stop within {}ms", STOP_JOIN_TIMEOUT_MS);
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PlaybackState {
@@ -12,83 +42,344 @@ pub struct PlaybackState {
     pub is_paused: bool,
     pub current_position: f64,
     pub total_duration: f64,
+    // m:ss (h:mm:ss past an hour) versions of the above, for display
+    pub current_position_formatted: String,
+    pub remaining_formatted: String,
+    pub total_formatted: String,
     pub current_file: Option<String>,
     pub loop_mode: bool,
+    pub loop_count: u32,
+    pub loops_remaining: u32,
     pub note_mode: NoteMode,
     pub octave_shift: i8,
+    pub octave_shifts: [i8; 3],
+    pub transpose: i32,
+    pub transpose_overridden: bool,
+    pub live_transpose: i32,
+    pub effective_transpose: i32,
+    pub current_bar: u32,
+    pub current_beat: u32,
+    pub current_bpm: f64,
+    // Position and length in beats from the start, for a tempo-aware seek bar (see seek_to_beat)
+    pub beat_position: f64,
+    pub total_beats: f64,
+    // Next note group for manual stepping (0 = start of the song)
+    pub manual_step: usize,
+    // Speed of the current pass under a practice ramp (1.0 otherwise)
+    pub practice_speed: f64,
+}
+
+/// Format seconds as m:ss, or h:mm:ss from an hour up. Invalid or negative times show 0:00.
+fn format_time(seconds: f64) -> String {
+    let total = if seconds.is_finite() { seconds.max(0.0) as u64 } else { 0 };
+    let (hours, minutes, secs) = (total / 3600, total / 60 % 60, total % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, secs)
+    } else {
+        format!("{}:{:02}", minutes, secs)
+    }
+}
+
+/// Where the last session left off, offered as "Resume <song> at 1:23" on launch
+#[derive(Debug, Clone, Serialize)]
+pub struct ResumeState {
+    pub path: String,
+    pub position: f64,
+    pub position_formatted: String,
+}
+
+/// Auto-advance settings for the queue, applied by the frontend on playback-ended
+#[derive(Debug, Clone, Serialize)]
+pub struct AutoplaySettings {
+    pub enabled: bool,
+    pub gap_ms: u64,
 }
 
 pub struct AppState {
     is_playing: Arc<AtomicBool>,
     is_paused: Arc<AtomicBool>,
     loop_mode: Arc<AtomicBool>,
+    loop_count: Arc<AtomicU32>,
+    loops_remaining: Arc<AtomicU32>,
+    loop_gap_ms: Arc<AtomicU64>,
+    seamless_loop: Arc<AtomicBool>,
     note_mode: Arc<AtomicU8>,
+    chord_mode: Arc<AtomicU8>,
+    // Notes shorter than this are left out (0 = keep all)
+    min_note_duration_ms: Arc<AtomicU64>,
+    // Notes on channels whose volume (CC7) is below this are left out (0 = keep all)
+    min_channel_volume: Arc<AtomicU8>,
+    output_mode: Arc<AtomicU8>,
     octave_shift: Arc<AtomicI8>,
+    // Per-band semitone offsets (low, mid, high), on top of octave_shift
+    octave_shifts: Arc<std::sync::Mutex<[i8; 3]>>,
+    transpose: Arc<AtomicI32>,
+    live_transpose: Arc<AtomicI32>,
+    time_quantize_ms: Arc<AtomicU64>,
+    swing_ratio: Arc<std::sync::Mutex<f64>>,
+    humanize_ms: Arc<AtomicU64>,
+    humanize_seed: Arc<AtomicU64>,
+    latency_offset_ms: Arc<AtomicI64>,
+    pitch_bend: Arc<AtomicBool>,
+    bass_tremolo: Arc<AtomicBool>,
+    bass_tremolo_rate_ms: Arc<AtomicU64>,
+    // Re-press an already held key for each new note on it (false = legato)
+    detache: Arc<AtomicBool>,
+    // Extra time a key is held after its note ends (0 = release on time)
+    note_off_delay_ms: Arc<AtomicU64>,
+    // Safety cap on new key presses per second (0 = off)
+    max_nps: Arc<AtomicU32>,
+    // Key overrides by MIDI pitch for the loaded song
+    note_overrides: Arc<std::sync::Mutex<std::collections::HashMap<u8, String>>>,
+    // Tracks of the loaded song to play (None = every track)
+    active_tracks: Arc<std::sync::Mutex<Option<std::collections::HashSet<usize>>>>,
+    practice_ramp: Arc<std::sync::Mutex<Option<crate::midi::PracticeRamp>>>,
+    // Speed of the pass being played (1.0 without a practice ramp)
+    practice_speed: Arc<std::sync::Mutex<f64>>,
+    pause_at_markers: Arc<AtomicBool>,
+    call_and_response: Arc<AtomicBool>,
+    trim_leading_silence: Arc<AtomicBool>,
+    progress_interval_ms: Arc<AtomicU64>,
+    pause_on_focus_loss: Arc<AtomicBool>,
+    resume_on_focus: Arc<AtomicBool>,
     current_position: Arc<std::sync::Mutex<f64>>,
     total_duration: Arc<std::sync::Mutex<f64>>,
     current_file: Arc<std::sync::Mutex<Option<String>>>,
     playback_start: Arc<std::sync::Mutex<Option<Instant>>>,
     midi_data: Arc<std::sync::Mutex<Option<crate::midi::MidiData>>>,
     seek_offset: Arc<std::sync::Mutex<f64>>,
+    // Seek target in ms for the running playback loop (NO_SEEK = none)
+    seek_request: Arc<AtomicU64>,
+    playback_stats: Arc<std::sync::Mutex<crate::midi::PlaybackStats>>,
+    // Manual step mode: event index of the next group and the keys held by the last step
+    manual_step: usize,
+    manual_event_index: usize,
+    manual_keys: Vec<String>,
+    playback_thread: Option<JoinHandle<()>>,
+    playback_signal: Arc<PlaybackSignal>,
+    // Snippet playback from the file list, separate from the loaded song
+    preview_playing: Arc<AtomicBool>,
+    preview_thread: Option<JoinHandle<()>>,
+    preview_signal: Arc<PlaybackSignal>,
+    focus_delay_ms: Arc<AtomicU64>,
+    settings: Settings,
+    metadata: crate::metadata::MetadataStore,
+    shuffle: crate::shuffle::Shuffle,
 }
 
 impl AppState {
     pub fn new() -> Self {
+        let mut settings = crate::settings::load_settings();
+        crate::scanner::set_scan_params(settings.scan_params.clone());
+        if let Err(e) = crate::scanner::set_scan_region(settings.scan_region) {
+            eprintln!("{}", e);
+        }
+        if let Some(notes_per_second) = settings.key_sheet_notes_per_second {
+            crate::keysheet::set_notes_per_second(notes_per_second);
+        }
+        crate::midi::set_register_weight(settings.register_weight);
+        if !settings.instrument_profile.is_empty() {
+            if let Err(e) = crate::midi::set_instrument_profile(&settings.instrument_profile) {
+                eprintln!("{}", e);
+            }
+        }
+        // A split that no longer fits the profile is dropped, so the saved settings match what plays
+        if let Err(e) = crate::midi::set_hand_split(settings.hand_split.as_ref()) {
+            eprintln!("Hand split turned off: {}", e);
+            settings.hand_split = None;
+            if let Err(e) = crate::settings::save_settings(&settings) {
+                eprintln!("{}", e);
+            }
+        }
+
         AppState {
             is_playing: Arc::new(AtomicBool::new(false)),
             is_paused: Arc::new(AtomicBool::new(false)),
             loop_mode: Arc::new(AtomicBool::new(false)),
+            loop_count: Arc::new(AtomicU32::new(1)),
+            loops_remaining: Arc::new(AtomicU32::new(0)),
+            loop_gap_ms: Arc::new(AtomicU64::new(0)),
+            seamless_loop: Arc::new(AtomicBool::new(false)),
             note_mode: Arc::new(AtomicU8::new(NoteMode::Closest as u8)),
+            chord_mode: Arc::new(AtomicU8::new(crate::midi::ChordMode::Off as u8)),
+            min_note_duration_ms: Arc::new(AtomicU64::new(0)),
+            min_channel_volume: Arc::new(AtomicU8::new(0)),
+            output_mode: Arc::new(AtomicU8::new(crate::midi_out::OutputMode::GameKeyboard as u8)),
             octave_shift: Arc::new(AtomicI8::new(0)),
+            octave_shifts: Arc::new(std::sync::Mutex::new([0; 3])),
+            transpose: Arc::new(AtomicI32::new(0)),
+            live_transpose: Arc::new(AtomicI32::new(0)),
+            time_quantize_ms: Arc::new(AtomicU64::new(0)),
+            swing_ratio: Arc::new(std::sync::Mutex::new(crate::midi::STRAIGHT_SWING)),
+            humanize_ms: Arc::new(AtomicU64::new(0)),
+            humanize_seed: Arc::new(AtomicU64::new(
+                std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map(|elapsed| elapsed.as_nanos() as u64)
+                    .unwrap_or(0),
+            )),
+            latency_offset_ms: Arc::new(AtomicI64::new(settings.latency_offset_ms)),
+            pitch_bend: Arc::new(AtomicBool::new(false)),
+            bass_tremolo: Arc::new(AtomicBool::new(false)),
+            bass_tremolo_rate_ms: Arc::new(AtomicU64::new(60)),
+            detache: Arc::new(AtomicBool::new(false)),
+            note_off_delay_ms: Arc::new(AtomicU64::new(0)),
+            max_nps: Arc::new(AtomicU32::new(0)),
+            note_overrides: Arc::new(std::sync::Mutex::new(std::collections::HashMap::new())),
+            active_tracks: Arc::new(std::sync::Mutex::new(None)),
+            practice_ramp: Arc::new(std::sync::Mutex::new(None)),
+            practice_speed: Arc::new(std::sync::Mutex::new(1.0)),
+            pause_at_markers: Arc::new(AtomicBool::new(false)),
+            call_and_response: Arc::new(AtomicBool::new(false)),
+            trim_leading_silence: Arc::new(AtomicBool::new(false)),
+            progress_interval_ms: Arc::new(AtomicU64::new(100)),
+            pause_on_focus_loss: Arc::new(AtomicBool::new(false)),
+            resume_on_focus: Arc::new(AtomicBool::new(false)),
             current_position: Arc::new(std::sync::Mutex::new(0.0)),
             total_duration: Arc::new(std::sync::Mutex::new(0.0)),
             current_file: Arc::new(std::sync::Mutex::new(None)),
             playback_start: Arc::new(std::sync::Mutex::new(None)),
             midi_data: Arc::new(std::sync::Mutex::new(None)),
             seek_offset: Arc::new(std::sync::Mutex::new(0.0)),
+            seek_request: Arc::new(AtomicU64::new(crate::midi::NO_SEEK)),
+            playback_stats: Arc::new(std::sync::Mutex::new(crate::midi::PlaybackStats::default())),
+            manual_step: 0,
+            manual_event_index: 0,
+            manual_keys: Vec::new(),
+            playback_thread: None,
+            playback_signal: Arc::new(PlaybackSignal::default()),
+            preview_playing: Arc::new(AtomicBool::new(false)),
+            preview_thread: None,
+            preview_signal: Arc::new(PlaybackSignal::default()),
+            focus_delay_ms: Arc::new(AtomicU64::new(100)),
+            settings,
+            metadata: crate::metadata::load_metadata(),
+            shuffle: crate::shuffle::Shuffle::default(),
         }
     }
 
-    pub fn load_midi(&mut self, path: &str) -> Result<(), String> {
-        let midi_data = crate::midi::load_midi(path)?;
+    pub fn load_midi(&mut self, path: &str, sequence: usize) -> Result<(), String> {
+        let midi_data = crate::midi::load_midi_sequence(path, sequence)?;
+        self.install_song(path, midi_data)
+    }
+
+    /// Load two files as one song (experimental duet). Per-song settings come from the
+    /// first part. Returns a warning when the combined parts are too dense to play cleanly.
+    pub fn load_duet(&mut self, path_a: &str, path_b: &str, offset_ms: u64) -> Result<Option<String>, String> {
+        let part_a = crate::midi::load_midi_sequence(path_a, 0)?;
+        let part_b = crate::midi::load_midi_sequence(path_b, 0)?;
+        let midi_data = crate::midi::merge_duet(part_a, part_b, offset_ms);
+        let warning = crate::midi::duet_warning(&midi_data);
+        self.install_song(path_a, midi_data)?;
+        Ok(warning)
+    }
 
+    // Make parsed song data the loaded song, restoring the file's per-song settings
+    fn install_song(&mut self, path: &str, mut midi_data: crate::midi::MidiData) -> Result<(), String> {
+        if self.trim_leading_silence.load(Ordering::SeqCst) {
+            midi_data = midi_data.without_leading_silence();
+        }
+
+        // Restore a pinned transpose for this file, otherwise use the detected one
+        let transpose = self.settings.transpose_overrides
+            .get(path)
+            .copied()
+            .unwrap_or(midi_data.transpose);
+        self.transpose.store(transpose, Ordering::SeqCst);
+        if self.song_preferences_enabled() {
+            if let Some(mode) = self.metadata.get(path).and_then(|metadata| metadata.note_mode) {
+                self.set_note_mode(mode);
+            }
+        }
+        *self.note_overrides.lock().unwrap() = self.metadata
+            .get(path)
+            .map(|metadata| metadata.note_overrides.clone())
+            .unwrap_or_default();
+        // Track selection is per song, a new file plays every track
+        *self.active_tracks.lock().unwrap() = None;
+        // Live A/B offset is per listen, start each file without it
+        self.live_transpose.store(0, Ordering::SeqCst);
+
+        // A new song starts from the top, not where the last one was seeked or resumed to
+        *self.seek_offset.lock().unwrap() = 0.0;
+        *self.current_position.lock().unwrap() = 0.0;
         *self.total_duration.lock().unwrap() = midi_data.duration;
         *self.current_file.lock().unwrap() = Some(path.to_string());
         *self.midi_data.lock().unwrap() = Some(midi_data);
+        self.reset_manual_step();
 
         Ok(())
     }
 
     pub fn start_playback(&mut self, window: Window) -> Result<(), String> {
+        self.start_playback_with(window)
+    }
+
+    /// Start playback reporting to any sink (e.g. `NullSink` for headless playback)
+    pub fn start_playback_with(&mut self, sink: impl PlaybackSink) -> Result<(), String> {
+        let start_position = self.start_position(0);
+        self.start_playback_from(sink, false, 0, start_position)
+    }
+
+    /// Start playback already paused, so no key is pressed until it's resumed. Waiting
+    /// takes the place of a count-in, but a negative `delay_ms` still starts that far in.
+    pub fn start_playback_paused(&mut self, window: Window, delay_ms: i64) -> Result<(), String> {
+        let start_position = self.start_position(delay_ms);
+        self.start_playback_from(window, true, 0, start_position)
+    }
+
+    /// Start after a count-in of `delay_ms` (emitting playback-starting as it counts down),
+    /// or that far into the song right away when negative. The command returns immediately.
+    pub fn start_playback_delayed(&mut self, window: Window, delay_ms: i64) -> Result<(), String> {
+        let start_position = self.start_position(delay_ms);
+        self.start_playback_from(window, false, delay_ms.max(0) as u64, start_position)
+    }
+
+    // Where a start with `delay_ms` begins: that far into the song when negative (for this
+    // run only), otherwise the loaded song's seek position
+    fn start_position(&self, delay_ms: i64) -> f64 {
+        if delay_ms < 0 {
+            let total_duration = *self.total_duration.lock().unwrap();
+            (delay_ms.unsigned_abs() as f64 / 1000.0).min(total_duration)
+        } else {
+            *self.seek_offset.lock().unwrap()
+        }
+    }
+
+    fn start_playback_from(
+        &mut self,
+        sink: impl PlaybackSink,
+        paused: bool,
+        start_delay_ms: u64,
+        start_position: f64,
+    ) -> Result<(), String> {
         if let Some(midi_data) = self.midi_data.lock().unwrap().clone() {
+            self.stop_preview();
+            self.reset_loops_remaining();
             self.is_playing.store(true, Ordering::SeqCst);
-            self.is_paused.store(false, Ordering::SeqCst);
-            let offset = *self.seek_offset.lock().unwrap();
+            self.is_paused.store(paused, Ordering::SeqCst);
+            self.seek_request.store(crate::midi::NO_SEEK, Ordering::SeqCst);
             *self.playback_start.lock().unwrap() = Some(Instant::now());
-            *self.current_position.lock().unwrap() = offset;
-
-            // Clone Arc references for the thread
-            let is_playing = Arc::clone(&self.is_playing);
-            let is_paused = Arc::clone(&self.is_paused);
-            let loop_mode = Arc::clone(&self.loop_mode);
-            let note_mode = Arc::clone(&self.note_mode);
-            let octave_shift = Arc::clone(&self.octave_shift);
-            let current_position = Arc::clone(&self.current_position);
-            let seek_offset = Arc::clone(&self.seek_offset);
-
-            std::thread::spawn(move || {
-                crate::midi::play_midi(
-                    midi_data,
-                    is_playing,
-                    is_paused,
-                    loop_mode,
-                    note_mode,
-                    octave_shift,
-                    current_position,
-                    seek_offset,
-                    window
-                );
-            });
+            *self.current_position.lock().unwrap() = start_position;
+
+            let run = PlaybackRun {
+                is_playing: Arc::clone(&self.is_playing),
+                is_paused: Arc::clone(&self.is_paused),
+                loop_mode: Arc::clone(&self.loop_mode),
+                loop_count: Arc::clone(&self.loop_count),
+                loops_remaining: Arc::clone(&self.loops_remaining),
+                current_position: Arc::clone(&self.current_position),
+                start_position,
+                seek_request: Arc::clone(&self.seek_request),
+                playback_stats: Arc::clone(&self.playback_stats),
+                signal: Arc::clone(&self.playback_signal),
+                note_overrides: Arc::clone(&self.note_overrides),
+                active_tracks: Arc::clone(&self.active_tracks),
+                practice_ramp: Arc::clone(&self.practice_ramp),
+                practice_speed: Arc::clone(&self.practice_speed),
+                start_delay_ms,
+            };
+            self.playback_thread = Some(self.spawn_playback(midi_data, run, sink));
 
             Ok(())
         } else {
@@ -96,14 +387,145 @@ impl AppState {
         }
     }
 
+    /// Run play_midi on its own thread with the live settings and the given per-run state
+    fn spawn_playback(&self, midi_data: crate::midi::MidiData, run: PlaybackRun, sink: impl PlaybackSink) -> JoinHandle<()> {
+        let settings = self.live_settings();
+        std::thread::spawn(move || {
+            if run.start_delay_ms > 0 {
+                // A stop during the count-in leaves play_midi with nothing to do
+                crate::midi::count_in(run.start_delay_ms, &run.is_playing, &run.signal, &sink);
+            }
+            crate::midi::play_midi(midi_data, run, settings, sink);
+        })
+    }
+
+    // Handles on the settings a playback thread reads live
+    fn live_settings(&self) -> LiveSettings {
+        LiveSettings {
+            loop_gap_ms: Arc::clone(&self.loop_gap_ms),
+            seamless_loop: Arc::clone(&self.seamless_loop),
+            note_mode: Arc::clone(&self.note_mode),
+            output_mode: Arc::clone(&self.output_mode),
+            octave_shift: Arc::clone(&self.octave_shift),
+            octave_shifts: Arc::clone(&self.octave_shifts),
+            transpose: Arc::clone(&self.transpose),
+            live_transpose: Arc::clone(&self.live_transpose),
+            chord_mode: Arc::clone(&self.chord_mode),
+            min_note_duration_ms: Arc::clone(&self.min_note_duration_ms),
+            min_channel_volume: Arc::clone(&self.min_channel_volume),
+            time_quantize_ms: Arc::clone(&self.time_quantize_ms),
+            swing_ratio: Arc::clone(&self.swing_ratio),
+            humanize_ms: Arc::clone(&self.humanize_ms),
+            humanize_seed: Arc::clone(&self.humanize_seed),
+            latency_offset_ms: Arc::clone(&self.latency_offset_ms),
+            pitch_bend: Arc::clone(&self.pitch_bend),
+            bass_tremolo: Arc::clone(&self.bass_tremolo),
+            bass_tremolo_rate_ms: Arc::clone(&self.bass_tremolo_rate_ms),
+            detache: Arc::clone(&self.detache),
+            note_off_delay_ms: Arc::clone(&self.note_off_delay_ms),
+            max_nps: Arc::clone(&self.max_nps),
+            pause_at_markers: Arc::clone(&self.pause_at_markers),
+            call_and_response: Arc::clone(&self.call_and_response),
+            progress_interval_ms: Arc::clone(&self.progress_interval_ms),
+            pause_on_focus_loss: Arc::clone(&self.pause_on_focus_loss),
+            resume_on_focus: Arc::clone(&self.resume_on_focus),
+        }
+    }
+
+    /// Play the first `seconds` of a file once, without touching the loaded song.
+    /// Stops any running playback first, since both would press keys.
+    pub fn preview_midi(&mut self, path: &str, seconds: f64, sink: impl PlaybackSink) -> Result<(), String> {
+        if !seconds.is_finite() || seconds <= 0.0 {
+            return Err(format!("Invalid preview length: {}", seconds));
+        }
+        // Start at the first note, not in the song's lead-in silence
+        let midi_data = crate::midi::load_midi(path)?
+            .without_leading_silence()
+            .truncated(seconds.min(MAX_PREVIEW_SECONDS));
+
+        // Pause the loaded song rather than stopping it, so it keeps its place
+        self.stop_preview();
+        if self.is_playing.load(Ordering::SeqCst) {
+            self.is_paused.store(true, Ordering::SeqCst);
+            self.playback_signal.notify();
+        }
+        self.preview_playing.store(true, Ordering::SeqCst);
+
+        // Fresh per-run state: a single pass from the start, with its own position
+        let run = PlaybackRun {
+            is_playing: Arc::clone(&self.preview_playing),
+            is_paused: Arc::new(AtomicBool::new(false)),
+            loop_mode: Arc::new(AtomicBool::new(false)),
+            loop_count: Arc::new(AtomicU32::new(1)),
+            loops_remaining: Arc::new(AtomicU32::new(0)),
+            current_position: Arc::new(std::sync::Mutex::new(0.0)),
+            start_position: 0.0,
+            seek_request: Arc::new(AtomicU64::new(crate::midi::NO_SEEK)),
+            playback_stats: Arc::new(std::sync::Mutex::new(crate::midi::PlaybackStats::default())),
+            signal: Arc::clone(&self.preview_signal),
+            // The previewed file's own overrides, not the loaded song's
+            note_overrides: Arc::new(std::sync::Mutex::new(
+                self.metadata.get(path).map(|metadata| metadata.note_overrides.clone()).unwrap_or_default(),
+            )),
+            active_tracks: Arc::new(std::sync::Mutex::new(None)),
+            practice_ramp: Arc::new(std::sync::Mutex::new(None)),
+            practice_speed: Arc::new(std::sync::Mutex::new(1.0)),
+            start_delay_ms: 0,
+        };
+        self.preview_thread = Some(self.spawn_playback(midi_data, run, sink));
+        Ok(())
+    }
+
+    pub fn stop_preview(&mut self) {
+        self.preview_playing.store(false, Ordering::SeqCst);
+        self.preview_signal.notify();
+        if let Some(handle) = self.preview_thread.take() {
+            join_playback_thread(handle);
+        }
+    }
+
     pub fn set_note_mode(&mut self, mode: NoteMode) {
         self.note_mode.store(mode as u8, Ordering::SeqCst);
     }
 
+    /// Note mode picked by the user: applies live and is remembered for the loaded song
+    pub fn choose_note_mode(&mut self, mode: NoteMode) -> Result<(), String> {
+        self.set_note_mode(mode);
+        if !self.song_preferences_enabled() {
+            return Ok(());
+        }
+        match self.current_file.lock().unwrap().clone() {
+            Some(path) => {
+                self.metadata.entry(path).or_default().note_mode = Some(mode);
+                crate::metadata::save_metadata(&self.metadata)
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Remember and restore a note mode per song, or use one global mode for everything
+    pub fn set_song_preferences(&mut self, enabled: bool) -> Result<(), String> {
+        self.settings.apply_song_preferences = Some(enabled);
+        crate::settings::save_settings(&self.settings)
+    }
+
+    pub fn song_preferences_enabled(&self) -> bool {
+        self.settings.apply_song_preferences.unwrap_or(true)
+    }
+
     pub fn get_note_mode(&self) -> NoteMode {
         NoteMode::from(self.note_mode.load(Ordering::SeqCst))
     }
 
+    /// Route playback to game keystrokes or the selected MIDI output (switches live)
+    pub fn set_output_mode(&mut self, mode: crate::midi_out::OutputMode) {
+        self.output_mode.store(mode as u8, Ordering::SeqCst);
+    }
+
+    pub fn get_output_mode(&self) -> crate::midi_out::OutputMode {
+        crate::midi_out::OutputMode::from(self.output_mode.load(Ordering::SeqCst))
+    }
+
     pub fn set_octave_shift(&mut self, shift: i8) {
         // Clamp to -2 to +2 octaves
         let clamped = shift.clamp(-2, 2);
@@ -114,61 +536,917 @@ impl AppState {
         self.octave_shift.load(Ordering::SeqCst)
     }
 
+    /// Separate semitone offsets for notes landing in the low, mid and high rows
+    pub fn set_octave_shifts(&mut self, low: i8, mid: i8, high: i8) {
+        // Clamp each band to one octave either way
+        *self.octave_shifts.lock().unwrap() = [low.clamp(-12, 12), mid.clamp(-12, 12), high.clamp(-12, 12)];
+    }
+
+    pub fn get_octave_shifts(&self) -> [i8; 3] {
+        *self.octave_shifts.lock().unwrap()
+    }
+
+    /// Transpose for this session only, without pinning it in settings
+    pub fn set_session_transpose(&mut self, semitones: i32) -> i32 {
+        let clamped = semitones.clamp(-12, 12);
+        self.transpose.store(clamped, Ordering::SeqCst);
+        clamped
+    }
+
+    /// Pin a manual transpose for the current file (applies live and persists across reloads)
+    pub fn set_transpose(&mut self, semitones: i32) -> Result<(), String> {
+        let clamped = self.set_session_transpose(semitones);
+
+        if let Some(path) = self.current_file.lock().unwrap().clone() {
+            self.settings.transpose_overrides.insert(path, clamped);
+            crate::settings::save_settings(&self.settings)?;
+        }
+        Ok(())
+    }
+
+    /// Drop the pinned transpose for the current file and go back to auto-detection
+    pub fn clear_transpose_override(&mut self) -> Result<(), String> {
+        if let Some(path) = self.current_file.lock().unwrap().clone() {
+            if self.settings.transpose_overrides.remove(&path).is_some() {
+                crate::settings::save_settings(&self.settings)?;
+            }
+        }
+        if let Some(midi_data) = self.midi_data.lock().unwrap().as_ref() {
+            self.transpose.store(midi_data.transpose, Ordering::SeqCst);
+        }
+        Ok(())
+    }
+
+    /// Range analysis of the loaded file using the transpose and octave shift currently in effect
+    pub fn get_range_report(&self) -> Option<crate::midi::RangeReport> {
+        let total_transpose = self.get_effective_transpose();
+        self.midi_data.lock().unwrap()
+            .as_ref()
+            .and_then(|midi_data| crate::midi::analyze_range(&midi_data.events, total_transpose))
+    }
+
+    /// Range analysis for a file that isn't loaded yet (preflight)
+    pub fn preview_range_report(&self, path: &str) -> Result<Option<crate::midi::RangeReport>, String> {
+        let midi_data = crate::midi::load_midi(path)?;
+        let transpose = self.settings.transpose_overrides
+            .get(path)
+            .copied()
+            .unwrap_or(midi_data.transpose);
+        let total_transpose = transpose + self.get_octave_shift() as i32 * 12;
+        Ok(crate::midi::analyze_range(&midi_data.events, total_transpose))
+    }
+
+    /// Problem spots of a file with the current mode, shifts and its pinned transpose (if any)
+    pub fn playability_report(&self, path: &str) -> Result<Vec<crate::midi::ProblemSegment>, String> {
+        crate::midi::playability_report(
+            path,
+            self.get_note_mode(),
+            self.settings.transpose_overrides.get(path).copied(),
+            self.get_octave_shift() as i32 * 12,
+            self.get_octave_shifts(),
+        )
+    }
+
+    pub fn get_transpose(&self) -> i32 {
+        self.transpose.load(Ordering::SeqCst)
+    }
+
+    /// Adjust the live (non-persisted) transpose offset, heard on the next note
+    pub fn nudge_transpose(&mut self, delta: i32) {
+        let current = self.live_transpose.load(Ordering::SeqCst);
+        let clamped = current.saturating_add(delta).clamp(-12, 12);
+        self.live_transpose.store(clamped, Ordering::SeqCst);
+    }
+
+    pub fn get_live_transpose(&self) -> i32 {
+        self.live_transpose.load(Ordering::SeqCst)
+    }
+
+    /// Auto-transpose of the loaded song and its runner-ups
+    pub fn get_transpose_detection(&self) -> Result<crate::midi::TransposeDetection, String> {
+        let midi_data = self.midi_data.lock().unwrap();
+        let midi_data = midi_data.as_ref().ok_or("No MIDI file loaded")?;
+        Ok(crate::midi::transpose_detection(midi_data, self.get_transpose()))
+    }
+
+    /// Transpose actually applied to notes: file transpose + live offset + octave shift
+    pub fn get_effective_transpose(&self) -> i32 {
+        self.get_transpose() + self.get_live_transpose() + self.get_octave_shift() as i32 * 12
+    }
+
+    /// Nudge the octave shift by a relative amount (clamped like set_octave_shift)
+    pub fn nudge_octave_shift(&mut self, delta: i8) {
+        let current = self.get_octave_shift();
+        self.set_octave_shift(current.saturating_add(delta));
+    }
+
     pub fn toggle_pause(&mut self) {
         if self.is_playing.load(Ordering::SeqCst) {
             let was_paused = self.is_paused.load(Ordering::SeqCst);
             let paused = !was_paused;
+            if !paused {
+                // Resuming the song ends a preview that paused it
+                self.stop_preview();
+            }
             self.is_paused.store(paused, Ordering::SeqCst);
+            self.playback_signal.notify();
+        }
+    }
+
+    /// Rescale the loaded song for a playback speed multiplier
+    pub fn set_playback_speed(&mut self, speed: f64) -> Result<(), String> {
+        if !speed.is_finite() || speed <= 0.0 {
+            return Err(format!("Invalid speed: {}", speed));
+        }
+        let mut midi_data = self.midi_data.lock().unwrap();
+        let scaled = midi_data.as_ref().ok_or("No MIDI file loaded")?.with_speed(speed);
+        *self.total_duration.lock().unwrap() = scaled.duration;
+        *midi_data = Some(scaled);
+        Ok(())
+    }
+
+    /// Block until the playback thread finishes on its own
+    pub fn wait_for_playback(&mut self) {
+        if let Some(handle) = self.playback_thread.take() {
+            let _ = handle.join();
         }
     }
 
     pub fn stop_playback(&mut self) {
+        self.stop_preview();
         self.is_playing.store(false, Ordering::SeqCst);
         self.is_paused.store(false, Ordering::SeqCst);
+        self.playback_signal.notify();
+
+        if let Some(handle) = self.playback_thread.take() {
+            join_playback_thread(handle);
+        }
+
         *self.current_position.lock().unwrap() = 0.0;
         *self.playback_start.lock().unwrap() = None;
+        self.reset_loops_remaining();
+    }
+
+    /// Abandon a playback thread that stopped responding instead of waiting for it.
+    /// It gets a stop flag of its own, so it can never press keys for a later song.
+    pub fn force_stop(&mut self) {
+        // Neither run is joined: a hung thread would hold the state lock with it
+        self.preview_playing.store(false, Ordering::SeqCst);
+        self.preview_signal.notify();
+        self.preview_playing = Arc::new(AtomicBool::new(false));
+        self.preview_thread = None;
+        self.is_playing.store(false, Ordering::SeqCst);
+        self.is_paused.store(false, Ordering::SeqCst);
+        self.playback_signal.notify();
+        self.is_playing = Arc::new(AtomicBool::new(false));
+        // Dropping the handle detaches the thread
+        self.playback_thread = None;
+
+        *self.current_position.lock().unwrap() = 0.0;
+        *self.playback_start.lock().unwrap() = None;
+        self.reset_loops_remaining();
+
+        // Release keys off this thread, since the hung one may be holding the input lock
+        std::thread::spawn(crate::keyboard::panic_release);
+    }
+
+    /// Manual step mode: release the previous step and hold the next group of notes.
+    /// Reaching the end wraps back to the start of the song.
+    pub fn step_next_note(&mut self) -> Result<(), String> {
+        if self.is_playing.load(Ordering::SeqCst) {
+            return Err("Stop playback before stepping through notes".to_string());
+        }
+        self.release_manual_keys();
+
+        let midi_data = self.midi_data.lock().unwrap().clone().ok_or("No MIDI file loaded")?;
+        let (group, next_index) = match crate::midi::next_note_group(&midi_data.events, self.manual_event_index) {
+            Some(found) => found,
+            None => {
+                self.manual_step = 0;
+                self.manual_event_index = 0;
+                return Ok(());
+            }
+        };
+
+        let mode = self.get_note_mode();
+        let transpose = self.get_transpose();
+        let shift_semitones = self.get_octave_shift() as i32 * 12 + self.get_live_transpose();
+        let band_shifts = self.get_octave_shifts();
+        for event in group {
+            let key = match event.event_type {
+                crate::midi::EventType::KeyDown(key) => key.to_string(),
+                _ => crate::midi::map_note_to_key(mode, event.note as i32, transpose, shift_semitones, band_shifts),
+            };
+            if !self.manual_keys.contains(&key) {
+                crate::keyboard::key_down(&key);
+                self.manual_keys.push(key);
+            }
+            if mode == NoteMode::Mono {
+                break;
+            }
+        }
+
+        self.manual_step += 1;
+        self.manual_event_index = next_index;
+        Ok(())
+    }
+
+    /// Release held step keys and go back to the first note
+    pub fn reset_manual_step(&mut self) {
+        self.release_manual_keys();
+        self.manual_step = 0;
+        self.manual_event_index = 0;
+    }
+
+    fn release_manual_keys(&mut self) {
+        for key in self.manual_keys.drain(..) {
+            crate::keyboard::key_up(&key);
+        }
+    }
+
+    /// Reduce chords to a single note during playback; applies from the next play/loop
+    pub fn set_chord_reduction(&mut self, mode: crate::midi::ChordMode) {
+        self.chord_mode.store(mode as u8, Ordering::SeqCst);
+    }
+
+    /// Skip notes shorter than `min_ms` during playback (0 = off); applies from the next play/loop
+    pub fn set_min_note_duration_ms(&mut self, min_ms: u64) {
+        self.min_note_duration_ms.store(min_ms, Ordering::SeqCst);
+    }
+
+    /// Skip notes on channels whose volume (CC7) is below `min_volume` (0 = off, max 127);
+    /// applies from the next play/loop
+    pub fn set_min_channel_volume(&mut self, min_volume: u8) {
+        self.min_channel_volume.store(min_volume.min(127), Ordering::SeqCst);
+    }
+
+    /// Snap event times to a grid of `grid_ms` during playback (0 = off)
+    pub fn set_time_quantize(&mut self, grid_ms: u64) {
+        self.time_quantize_ms.store(grid_ms, Ordering::SeqCst);
+    }
+
+    /// Swing ratio for off-beat subdivisions (0.5 = straight, clamped to 0.75)
+    pub fn set_swing(&mut self, ratio: f64) -> Result<(), String> {
+        if !ratio.is_finite() {
+            return Err(format!("Invalid swing ratio: {}", ratio));
+        }
+        *self.swing_ratio.lock().unwrap() = ratio.clamp(crate::midi::STRAIGHT_SWING, 0.75);
+        Ok(())
+    }
+
+    /// Random +-`amount_ms` timing jitter per note press (0 = off). Passing a seed makes
+    /// the jitter reproducible for debugging.
+    pub fn set_humanize(&mut self, amount_ms: u64, seed: Option<u64>) {
+        self.humanize_ms.store(amount_ms.min(MAX_HUMANIZE_MS), Ordering::SeqCst);
+        if let Some(seed) = seed {
+            self.humanize_seed.store(seed, Ordering::SeqCst);
+        }
+    }
+
+    /// Loop practice ramp: start at `start` speed and add `step` each loop until `target`.
+    /// Takes effect from the next play or loop pass.
+    pub fn set_practice_ramp(&mut self, start: f64, step: f64, target: f64) -> Result<(), String> {
+        if ![start, step, target].iter().all(|v| v.is_finite()) || start <= 0.0 || step <= 0.0 {
+            return Err("Practice ramp needs a positive start speed and step".to_string());
+        }
+        if target < start {
+            return Err(format!("Target speed {} is below the start speed {}", target, start));
+        }
+        *self.practice_ramp.lock().unwrap() = Some(crate::midi::PracticeRamp { start, step, target });
+        Ok(())
+    }
+
+    pub fn clear_practice_ramp(&mut self) {
+        *self.practice_ramp.lock().unwrap() = None;
+    }
 
-        // Wait for the playback thread to detect the stop flag and clean up
-        std::thread::sleep(std::time::Duration::from_millis(100));
+    /// Fixed compensation for input lag, applied live and remembered for this machine
+    pub fn set_latency_offset(&mut self, offset_ms: i64) -> Result<(), String> {
+        let clamped = offset_ms.clamp(-MAX_LATENCY_OFFSET_MS, MAX_LATENCY_OFFSET_MS);
+        self.latency_offset_ms.store(clamped, Ordering::SeqCst);
+        self.settings.latency_offset_ms = clamped;
+        crate::settings::save_settings(&self.settings)
+    }
+
+    pub fn get_latency_offset(&self) -> i64 {
+        self.latency_offset_ms.load(Ordering::SeqCst)
+    }
+
+    /// Approximate pitch bends by sliding held notes onto adjacent keys
+    pub fn set_pitch_bend(&mut self, enabled: bool) {
+        self.pitch_bend.store(enabled, Ordering::SeqCst);
+    }
+
+    /// Play notes below the instrument's range as a tremolo on the lowest key, re-pressed
+    /// every `rate_ms`, instead of folding them up into the melody
+    pub fn set_bass_tremolo(&mut self, enabled: bool, rate_ms: u64) {
+        self.bass_tremolo.store(enabled, Ordering::SeqCst);
+        self.bass_tremolo_rate_ms.store(rate_ms.max(MIN_BASS_TREMOLO_RATE_MS), Ordering::SeqCst);
+    }
+
+    /// Detache re-articulates repeated notes landing on a held key; legato (off) holds through them
+    pub fn set_detache(&mut self, enabled: bool) {
+        self.detache.store(enabled, Ordering::SeqCst);
+    }
+
+    /// Hold keys a little past their note's end to soften clicky staccato (0 = off).
+    /// A new note on the same key still releases it first, so repeats stay distinct.
+    pub fn set_note_off_delay_ms(&mut self, delay_ms: u64) {
+        self.note_off_delay_ms.store(delay_ms.min(MAX_NOTE_OFF_DELAY_MS), Ordering::SeqCst);
+    }
+
+    /// Cap new key presses per second (0 = off). Notes over the cap are dropped, or merged
+    /// into an already held key, so bursts don't look like a macro to anti-cheat.
+    pub fn set_max_nps(&mut self, rate: u32) {
+        self.max_nps.store(rate, Ordering::SeqCst);
+    }
+
+    /// Pause playback whenever a marker is reached (for practicing section by section)
+    pub fn set_pause_at_markers(&mut self, enabled: bool) {
+        self.pause_at_markers.store(enabled, Ordering::SeqCst);
+    }
+
+    /// Pause after every loop pass so a student can play the phrase back; resume continues
+    pub fn set_call_and_response(&mut self, enabled: bool) {
+        self.call_and_response.store(enabled, Ordering::SeqCst);
+    }
+
+    /// Start songs at their first note instead of any empty lead-in (applies from the next load)
+    pub fn set_trim_leading_silence(&mut self, enabled: bool) {
+        self.trim_leading_silence.store(enabled, Ordering::SeqCst);
+    }
+
+    /// Key event tally of the current playback, or the last one once it ended
+    pub fn get_playback_stats(&self) -> crate::midi::PlaybackStats {
+        *self.playback_stats.lock().unwrap()
+    }
+
+    /// Markers of the loaded song, for drawing on the seek bar and jumping between sections
+    pub fn get_markers(&self) -> Vec<crate::midi::Marker> {
+        self.midi_data.lock().unwrap()
+            .as_ref()
+            .map(|midi_data| midi_data.markers.clone())
+            .unwrap_or_default()
+    }
+
+    /// How often playback-progress is emitted (clamped to 16ms..1s)
+    pub fn set_progress_interval(&mut self, interval_ms: u64) {
+        self.progress_interval_ms.store(interval_ms.clamp(16, 1000), Ordering::SeqCst);
+    }
+
+    /// Switch the key layout used for note mapping and persist the choice
+    pub fn set_instrument_profile(&mut self, name: &str) -> Result<(), String> {
+        crate::midi::set_instrument_profile(name)?;
+        self.settings.instrument_profile = crate::midi::active_profile().name.to_string();
+        // The second key set has to match the new profile's key count
+        let split_error = crate::midi::set_hand_split(self.settings.hand_split.as_ref()).err();
+        if split_error.is_some() {
+            crate::midi::set_hand_split(None)?;
+            self.settings.hand_split = None;
+        }
+        crate::settings::save_settings(&self.settings)?;
+        match split_error {
+            Some(e) => Err(format!("Hand split turned off: {}", e)),
+            None => Ok(()),
+        }
+    }
+
+    /// Send notes at or above a split point to a second key cluster (None = one cluster).
+    /// Applies live and is remembered.
+    pub fn set_hand_split(&mut self, split: Option<crate::midi::HandSplit>) -> Result<(), String> {
+        crate::midi::set_hand_split(split.as_ref())?;
+        self.settings.hand_split = split;
+        crate::settings::save_settings(&self.settings)
+    }
+
+    /// How strongly auto-transpose keeps songs in the middle row; applies from the next load
+    pub fn set_register_weight(&mut self, weight: u32) -> Result<(), String> {
+        crate::midi::set_register_weight(weight);
+        self.settings.register_weight = crate::midi::get_register_weight();
+        crate::settings::save_settings(&self.settings)
+    }
+
+    /// Notes per second for .txt key sheets; applies the next time a sheet is loaded
+    pub fn set_key_sheet_speed(&mut self, notes_per_second: f64) -> Result<(), String> {
+        if !notes_per_second.is_finite() || notes_per_second <= 0.0 {
+            return Err(format!("Invalid speed: {}", notes_per_second));
+        }
+        crate::keysheet::set_notes_per_second(notes_per_second);
+        self.settings.key_sheet_notes_per_second = Some(crate::keysheet::get_notes_per_second());
+        crate::settings::save_settings(&self.settings)
+    }
+
+    /// Whether a naturally finished song advances to the next queued one, and after how long
+    pub fn set_autoplay_next(&mut self, enabled: bool, gap_ms: Option<u64>) -> Result<(), String> {
+        self.settings.autoplay_next = Some(enabled);
+        if let Some(gap_ms) = gap_ms {
+            self.settings.autoplay_gap_ms = gap_ms;
+        }
+        crate::settings::save_settings(&self.settings)
+    }
+
+    pub fn get_autoplay_next(&self) -> AutoplaySettings {
+        AutoplaySettings {
+            enabled: self.settings.autoplay_next.unwrap_or(true),
+            gap_ms: self.settings.autoplay_gap_ms,
+        }
+    }
+
+    /// Remember the last song and position across restarts; turning it off forgets them
+    pub fn set_remember_last_song(&mut self, enabled: bool) -> Result<(), String> {
+        self.settings.remember_last_song = Some(enabled);
+        if !enabled {
+            self.settings.last_file = None;
+            self.settings.last_position = 0.0;
+        }
+        crate::settings::save_settings(&self.settings)
+    }
+
+    pub fn remember_last_song_enabled(&self) -> bool {
+        self.settings.remember_last_song.unwrap_or(true)
+    }
+
+    /// Save the playing song and position as the resume point. Called periodically, so it
+    /// only writes the settings file when the position moved noticeably.
+    pub fn save_resume_point(&mut self) -> Result<(), String> {
+        if !self.remember_last_song_enabled() || !self.is_playing() {
+            return Ok(());
+        }
+        let path = match self.current_file.lock().unwrap().clone() {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let position = *self.current_position.lock().unwrap();
+        if self.settings.last_file.as_deref() == Some(path.as_str())
+            && (self.settings.last_position - position).abs() < 1.0
+        {
+            return Ok(());
+        }
+        self.settings.last_file = Some(path);
+        self.settings.last_position = position;
+        crate::settings::save_settings(&self.settings)
+    }
+
+    /// The saved resume point, if there is one and its file still exists. A resume point
+    /// for a deleted file is forgotten.
+    pub fn get_resume_state(&mut self) -> Option<ResumeState> {
+        if !self.remember_last_song_enabled() {
+            return None;
+        }
+        let path = self.settings.last_file.clone()?;
+        if !std::path::Path::new(&path).exists() {
+            self.settings.last_file = None;
+            self.settings.last_position = 0.0;
+            if let Err(e) = crate::settings::save_settings(&self.settings) {
+                eprintln!("{}", e);
+            }
+            return None;
+        }
+        let position = self.settings.last_position.max(0.0);
+        Some(ResumeState {
+            path,
+            position,
+            position_formatted: format_time(position),
+        })
+    }
+
+    /// Load the resume point's song, positioned where it left off (or at the start if it had
+    /// nearly finished). Returns the position to play from.
+    pub fn load_resume_point(&mut self) -> Result<f64, String> {
+        let resume = self
+            .get_resume_state()
+            .ok_or("Nothing to resume (the last song may have been moved or deleted)")?;
+        self.stop_playback();
+        self.load_midi(&resume.path, 0)?;
+        let total_duration = *self.total_duration.lock().unwrap();
+        let position = if resume.position < total_duration - RESUME_END_MARGIN_SECONDS {
+            resume.position
+        } else {
+            0.0
+        };
+        self.seek(position)?;
+        Ok(position)
+    }
+
+    /// Update scanner detection thresholds and persist them
+    pub fn set_scan_params(&mut self, params: crate::scanner::ScanParams) -> Result<(), String> {
+        crate::scanner::set_scan_params(params.clone());
+        self.settings.scan_params = params;
+        crate::settings::save_settings(&self.settings)
+    }
+
+    /// Restrict scanning to a screen region (None = lower half of the screen) and persist it
+    pub fn set_scan_region(&mut self, region: Option<crate::scanner::ScanRegion>) -> Result<(), String> {
+        crate::scanner::set_scan_region(region)?;
+        self.settings.scan_region = region;
+        crate::settings::save_settings(&self.settings)
+    }
+
+    /// Auto-pause when the game loses focus, optionally resuming when it regains focus
+    pub fn set_pause_on_focus_loss(&mut self, enabled: bool, resume_on_focus: bool) {
+        self.pause_on_focus_loss.store(enabled, Ordering::SeqCst);
+        self.resume_on_focus.store(resume_on_focus, Ordering::SeqCst);
+    }
+
+    pub fn get_file_metadata(&self, path: &str) -> Option<crate::metadata::FileMetadata> {
+        self.metadata.get(path).cloned()
+    }
+
+    pub fn set_file_metadata(&mut self, path: &str, metadata: crate::metadata::FileMetadata) -> Result<(), String> {
+        self.metadata.insert(path.to_string(), metadata);
+        crate::metadata::save_metadata(&self.metadata)
+    }
+
+    /// Press a chosen key for specific MIDI pitches in the loaded song. Entries naming a key
+    /// the instrument doesn't have are ignored. Applies live and is saved with the song.
+    pub fn set_note_overrides(&mut self, overrides: std::collections::HashMap<u8, String>) -> Result<std::collections::HashMap<u8, String>, String> {
+        let valid_keys = crate::midi::active_profile().keys();
+        let overrides: std::collections::HashMap<u8, String> = overrides
+            .into_iter()
+            .filter(|(note, _)| *note <= 127)
+            .map(|(note, key)| (note, key.trim().to_lowercase()))
+            .filter(|(_, key)| valid_keys.contains(&key.as_str()))
+            .collect();
+
+        *self.note_overrides.lock().unwrap() = overrides.clone();
+        if let Some(path) = self.current_file.lock().unwrap().clone() {
+            self.metadata.entry(path).or_default().note_overrides = overrides.clone();
+            crate::metadata::save_metadata(&self.metadata)?;
+        }
+        Ok(overrides)
+    }
+
+    /// Play only the given tracks of the loaded song (empty = every track).
+    /// Applies from the next note, even while playing.
+    pub fn set_active_tracks(&mut self, indices: Vec<usize>) -> Result<(), String> {
+        let path = self.current_file.lock().unwrap().clone().ok_or("No MIDI file loaded")?;
+        let track_count = crate::midi::list_tracks(&path)?.len();
+        if let Some(index) = indices.iter().find(|&&index| index >= track_count) {
+            return Err(format!("Track {} not found ({} available)", index, track_count));
+        }
+        *self.active_tracks.lock().unwrap() = if indices.is_empty() {
+            None
+        } else {
+            Some(indices.into_iter().collect())
+        };
+        Ok(())
+    }
+
+    pub fn get_note_overrides(&self) -> std::collections::HashMap<u8, String> {
+        self.note_overrides.lock().unwrap().clone()
+    }
+
+    /// Move metadata along with a renamed file, or drop it (new_path = None) when deleted
+    pub fn move_file_metadata(&mut self, path: &str, new_path: Option<&str>) -> Result<(), String> {
+        if let Some(metadata) = self.metadata.remove(path) {
+            if let Some(new_path) = new_path {
+                self.metadata.insert(new_path.to_string(), metadata);
+            }
+            crate::metadata::save_metadata(&self.metadata)?;
+        }
+        Ok(())
+    }
+
+    pub fn set_shuffle(&mut self, enabled: bool, current: usize, track_count: usize) {
+        self.shuffle.set_enabled(enabled, current, track_count);
+    }
+
+    /// Queue index to play after `current`: random when shuffling, otherwise the next one
+    pub fn next_track_index(&mut self, current: usize, track_count: usize) -> usize {
+        if self.shuffle.is_enabled() {
+            self.shuffle.next(current, track_count)
+        } else if track_count == 0 {
+            0
+        } else {
+            (current + 1) % track_count
+        }
+    }
+
+    /// Queue index to go back to: shuffle history when shuffling, otherwise the previous one
+    pub fn previous_track_index(&mut self, current: usize, track_count: usize) -> usize {
+        if self.shuffle.is_enabled() {
+            self.shuffle.previous(current, track_count)
+        } else if track_count == 0 {
+            0
+        } else {
+            (current + track_count - 1) % track_count
+        }
+    }
+
+    /// Save a custom album order (drag-to-reorder in the file list)
+    pub fn set_album_order(&mut self, paths: Vec<String>) -> Result<(), String> {
+        self.settings.album_order = paths;
+        crate::settings::save_settings(&self.settings)
+    }
+
+    pub fn get_album_order(&self) -> Vec<String> {
+        self.settings.album_order.clone()
+    }
+
+    /// Folders the song list is built from; empty goes back to the album folder
+    pub fn set_library_paths(&mut self, paths: Vec<String>) -> Result<(), String> {
+        let mut unique: Vec<String> = Vec::new();
+        for path in paths {
+            let path = path.trim().to_string();
+            if !path.is_empty() && !unique.contains(&path) {
+                unique.push(path);
+            }
+        }
+        self.settings.library_paths = unique;
+        crate::settings::save_settings(&self.settings)
+    }
+
+    pub fn get_library_paths(&self) -> Vec<String> {
+        self.settings.library_paths.clone()
+    }
+
+    pub fn set_default_library(&mut self, path: Option<String>) -> Result<(), String> {
+        self.settings.default_library = path;
+        crate::settings::save_settings(&self.settings)
+    }
+
+    pub fn get_default_library(&self) -> Option<String> {
+        self.settings.default_library.clone()
+    }
+
+    pub fn set_focus_delay(&mut self, delay_ms: u64) {
+        self.focus_delay_ms.store(delay_ms, Ordering::SeqCst);
+    }
+
+    pub fn get_focus_delay(&self) -> Duration {
+        Duration::from_millis(self.focus_delay_ms.load(Ordering::SeqCst))
+    }
+
+    pub fn set_on_not_focused(&mut self, policy: crate::settings::NotFocusedPolicy) -> Result<(), String> {
+        self.settings.on_not_focused = policy;
+        crate::settings::save_settings(&self.settings)
+    }
+
+    pub fn get_on_not_focused(&self) -> crate::settings::NotFocusedPolicy {
+        self.settings.on_not_focused
+    }
+
+    pub fn set_auto_focus_mode(&mut self, mode: crate::settings::AutoFocusMode) -> Result<(), String> {
+        self.settings.auto_focus_mode = mode;
+        crate::settings::save_settings(&self.settings)
+    }
+
+    pub fn get_auto_focus_mode(&self) -> crate::settings::AutoFocusMode {
+        self.settings.auto_focus_mode
+    }
+
+    /// Put every setting back to its default, including per-file transpose and note overrides.
+    /// Song libraries, album order, the song to resume and tags/ratings are kept. Safe while playing: live
+    /// settings apply from the next note, per-pass ones (timing, chords) from the next loop.
+    pub fn reset_settings(&mut self) -> Result<(), String> {
+        self.settings = Settings {
+            library_paths: std::mem::take(&mut self.settings.library_paths),
+            default_library: self.settings.default_library.take(),
+            album_order: std::mem::take(&mut self.settings.album_order),
+            remember_last_song: self.settings.remember_last_song.take(),
+            last_file: self.settings.last_file.take(),
+            last_position: self.settings.last_position,
+            ..Settings::default()
+        };
+        crate::scanner::set_scan_params(self.settings.scan_params.clone());
+        crate::scanner::set_scan_region(None)?;
+        crate::keysheet::set_notes_per_second(crate::keysheet::DEFAULT_NOTES_PER_SECOND);
+        crate::midi::set_register_weight(0);
+        crate::midi::set_instrument_profile(crate::midi::INSTRUMENT_PROFILES[0].name)?;
+        crate::midi::set_hand_split(None)?;
+        crate::keyboard::set_test_mode(false);
+
+        self.loop_mode.store(false, Ordering::SeqCst);
+        self.loop_count.store(1, Ordering::SeqCst);
+        self.reset_loops_remaining();
+        self.loop_gap_ms.store(0, Ordering::SeqCst);
+        self.seamless_loop.store(false, Ordering::SeqCst);
+        self.note_mode.store(NoteMode::Closest as u8, Ordering::SeqCst);
+        self.chord_mode.store(crate::midi::ChordMode::Off as u8, Ordering::SeqCst);
+        self.min_note_duration_ms.store(0, Ordering::SeqCst);
+        self.min_channel_volume.store(0, Ordering::SeqCst);
+        self.output_mode.store(crate::midi_out::OutputMode::GameKeyboard as u8, Ordering::SeqCst);
+        self.octave_shift.store(0, Ordering::SeqCst);
+        *self.octave_shifts.lock().unwrap() = [0; 3];
+        self.live_transpose.store(0, Ordering::SeqCst);
+        // Back to the detected transpose now that no override is pinned
+        if let Some(midi_data) = self.midi_data.lock().unwrap().as_ref() {
+            self.transpose.store(midi_data.transpose, Ordering::SeqCst);
+        }
+        self.time_quantize_ms.store(0, Ordering::SeqCst);
+        *self.swing_ratio.lock().unwrap() = crate::midi::STRAIGHT_SWING;
+        self.humanize_ms.store(0, Ordering::SeqCst);
+        self.latency_offset_ms.store(self.settings.latency_offset_ms, Ordering::SeqCst);
+        self.pitch_bend.store(false, Ordering::SeqCst);
+        self.bass_tremolo.store(false, Ordering::SeqCst);
+        self.bass_tremolo_rate_ms.store(60, Ordering::SeqCst);
+        self.detache.store(false, Ordering::SeqCst);
+        self.note_off_delay_ms.store(0, Ordering::SeqCst);
+        self.max_nps.store(0, Ordering::SeqCst);
+        self.note_overrides.lock().unwrap().clear();
+        *self.active_tracks.lock().unwrap() = None;
+        *self.practice_ramp.lock().unwrap() = None;
+        self.pause_at_markers.store(false, Ordering::SeqCst);
+        self.call_and_response.store(false, Ordering::SeqCst);
+        self.trim_leading_silence.store(false, Ordering::SeqCst);
+        self.progress_interval_ms.store(100, Ordering::SeqCst);
+        self.pause_on_focus_loss.store(false, Ordering::SeqCst);
+        self.resume_on_focus.store(false, Ordering::SeqCst);
+        self.focus_delay_ms.store(100, Ordering::SeqCst);
+
+        // Per-song note modes and key overrides are overrides too
+        for metadata in self.metadata.values_mut() {
+            metadata.note_mode = None;
+            metadata.note_overrides.clear();
+        }
+        crate::metadata::save_metadata(&self.metadata)?;
+        crate::settings::save_settings(&self.settings)
+    }
+
+    /// Resume if still paused from a start that was waiting for the game to be focused
+    pub fn resume_if_paused(&mut self) -> bool {
+        if self.is_playing.load(Ordering::SeqCst) && self.is_paused.load(Ordering::SeqCst) {
+            self.toggle_pause();
+            return true;
+        }
+        false
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.is_playing.load(Ordering::SeqCst)
     }
 
     pub fn set_loop_mode(&mut self, enabled: bool) {
         self.loop_mode.store(enabled, Ordering::SeqCst);
     }
 
-    pub fn seek(&mut self, position: f64, window: Window) -> Result<(), String> {
-        let was_playing = self.is_playing.load(Ordering::SeqCst);
-        let was_paused = self.is_paused.load(Ordering::SeqCst);
-        
-        if was_playing && !was_paused {
-            *self.seek_offset.lock().unwrap() = position;
-            self.stop_playback();
-            self.start_playback(window)?;
-        } else if was_playing && was_paused {
-            *self.seek_offset.lock().unwrap() = position;
-            *self.current_position.lock().unwrap() = position;
-            self.stop_playback();
-            self.start_playback(window)?;
-            self.is_paused.store(true, Ordering::SeqCst);
-        } else {
-            *self.current_position.lock().unwrap() = position;
-            *self.seek_offset.lock().unwrap() = position;
+    /// Number of times to play the song (0 = repeat forever, 1 = play once)
+    pub fn set_loop_count(&mut self, count: u32) {
+        self.loop_count.store(count, Ordering::SeqCst);
+        self.reset_loops_remaining();
+    }
+
+    /// Silence between repeats, ignored when seamless looping is on
+    pub fn set_loop_gap_ms(&mut self, gap_ms: u64) {
+        self.loop_gap_ms.store(gap_ms, Ordering::SeqCst);
+    }
+
+    /// Start each repeat exactly one song length after the previous one, with no gap
+    pub fn set_seamless_loop(&mut self, enabled: bool) {
+        self.seamless_loop.store(enabled, Ordering::SeqCst);
+    }
+
+    // Repeats left after the current pass
+    fn reset_loops_remaining(&self) {
+        let count = self.loop_count.load(Ordering::SeqCst);
+        self.loops_remaining.store(count.saturating_sub(1), Ordering::SeqCst);
+    }
+
+    /// Jump to a position. While playing, the running playback loop picks the seek up
+    /// (staying paused if it was), so the thread isn't restarted.
+    pub fn seek(&mut self, position: f64) -> Result<(), String> {
+        if !position.is_finite() {
+            return Err(format!("Invalid position: {}", position));
+        }
+        let position = position.max(0.0);
+
+        *self.seek_offset.lock().unwrap() = position;
+        *self.current_position.lock().unwrap() = position;
+        if self.is_playing.load(Ordering::SeqCst) {
+            self.seek_request.store((position * 1000.0) as u64, Ordering::SeqCst);
+            self.playback_signal.notify();
         }
         Ok(())
     }
 
+    /// Seek exactly to the next or previous note press. Errors at either end of the song
+    /// so the position never jumps somewhere unexpected.
+    pub fn seek_to_note(&mut self, direction: crate::midi::NoteDirection) -> Result<f64, String> {
+        let position_ms = (*self.current_position.lock().unwrap() * 1000.0).max(0.0) as u64;
+        let time_ms = {
+            let midi_data = self.midi_data.lock().unwrap();
+            let midi_data = midi_data.as_ref().ok_or("No MIDI file loaded")?;
+            crate::midi::find_note_time(&midi_data.events, position_ms, direction)
+        };
+        let time_ms = time_ms.ok_or(match direction {
+            crate::midi::NoteDirection::Next => "Already past the last note",
+            crate::midi::NoteDirection::Previous => "Already at the first note",
+        })?;
+
+        let position = time_ms as f64 / 1000.0;
+        self.seek(position)?;
+        Ok(position)
+    }
+
+    /// Seek to a beat counted from the start, through the tempo map, so scrubbing through
+    /// tempo changes lands on the expected music. Returns the new position in seconds.
+    pub fn seek_to_beat(&mut self, beat: f64) -> Result<f64, String> {
+        if !beat.is_finite() || beat < 0.0 {
+            return Err(format!("Invalid beat: {}", beat));
+        }
+        let position = {
+            let midi_data = self.midi_data.lock().unwrap();
+            let midi_data = midi_data.as_ref().ok_or("No MIDI file loaded")?;
+            (midi_data.tempo_map.beat_to_ms(beat) / 1000.0).clamp(0.0, midi_data.duration)
+        };
+        self.seek(position)?;
+        Ok(position)
+    }
+
     pub fn get_playback_state(&self) -> PlaybackState {
         let position = *self.current_position.lock().unwrap();
+        let current_file = self.current_file.lock().unwrap().clone();
+        let transpose_overridden = current_file
+            .as_ref()
+            .map(|path| self.settings.transpose_overrides.contains_key(path))
+            .unwrap_or(false);
+
+        // Derive bar:beat from the same position we report, so they never drift apart
+        let (musical, beats) = self.midi_data.lock().unwrap()
+            .as_ref()
+            .map(|midi_data| {
+                let tempo_map = &midi_data.tempo_map;
+                let beats = (tempo_map.ms_to_beat(position * 1000.0), tempo_map.ms_to_beat(midi_data.duration * 1000.0));
+                (Some(tempo_map.musical_position(position * 1000.0)), beats)
+            })
+            .unwrap_or((None, (0.0, 0.0)));
+        let total_duration = *self.total_duration.lock().unwrap();
 
         PlaybackState {
             is_playing: self.is_playing.load(Ordering::SeqCst),
             is_paused: self.is_paused.load(Ordering::SeqCst),
             current_position: position,
-            total_duration: *self.total_duration.lock().unwrap(),
-            current_file: self.current_file.lock().unwrap().clone(),
+            total_duration,
+            current_position_formatted: format_time(position),
+            remaining_formatted: format_time(total_duration - position),
+            total_formatted: format_time(total_duration),
+            current_file,
             loop_mode: self.loop_mode.load(Ordering::SeqCst),
+            loop_count: self.loop_count.load(Ordering::SeqCst),
+            loops_remaining: self.loops_remaining.load(Ordering::SeqCst),
             note_mode: self.get_note_mode(),
             octave_shift: self.get_octave_shift(),
+            octave_shifts: self.get_octave_shifts(),
+            transpose: self.get_transpose(),
+            transpose_overridden,
+            live_transpose: self.get_live_transpose(),
+            effective_transpose: self.get_effective_transpose(),
+            current_bar: musical.map(|m| m.bar).unwrap_or(1),
+            current_beat: musical.map(|m| m.beat).unwrap_or(1),
+            current_bpm: musical.map(|m| m.bpm).unwrap_or(120.0),
+            beat_position: beats.0,
+            total_beats: beats.1,
+            manual_step: self.manual_step,
+            practice_speed: *self.practice_speed.lock().unwrap(),
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::midi::{EventType, MidiData, NullSink, TempoMap, TimedEvent, VolumeTimeline};
+
+    // Ten seconds with one long note, so a run can start anywhere in it
+    fn song() -> MidiData {
+        let note = |time_ms, event_type| TimedEvent { time_ms, event_type, note: 60, channel: 0, track: 0 };
+        MidiData {
+            events: vec![note(0, EventType::NoteOn), note(10_000, EventType::NoteOff)],
+            duration: 10.0,
+            transpose: 0,
+            tempo_map: TempoMap::constant(500_000.0),
+            markers: Vec::new(),
+            volumes: VolumeTimeline::default(),
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn pre_roll_only_moves_its_own_run() {
+        crate::keyboard::set_test_mode(true);
+        let mut app_state = AppState::new();
+        app_state.install_song("song.mid", song()).unwrap();
+
+        // Started paused, so no key is pressed
+        let pre_roll = app_state.start_position(-5000);
+        app_state.start_playback_from(NullSink, true, 0, pre_roll).unwrap();
+        assert_eq!(*app_state.current_position.lock().unwrap(), 5.0);
+        app_state.stop_playback();
+
+        // The next normal start is back at the beginning
+        let start_position = app_state.start_position(0);
+        app_state.start_playback_from(NullSink, true, 0, start_position).unwrap();
+        assert_eq!(*app_state.current_position.lock().unwrap(), 0.0);
+        app_state.stop_playback();
+    }
+
+    #[test]
+    fn next_song_starts_at_the_beginning_after_a_resume() {
+        let mut app_state = AppState::new();
+        app_state.install_song("first.mid", song()).unwrap();
+        // What load_resume_point leaves behind
+        app_state.seek(7.5).unwrap();
+        assert_eq!(app_state.start_position(0), 7.5);
+
+        app_state.install_song("second.mid", song()).unwrap();
+        assert_eq!(app_state.start_position(0), 0.0);
+    }
+}
@@ -18,6 +18,9 @@ export const noteMode = writable("Closest");
 // Octave shift (-2 to +2)
 export const octaveShift = writable(0);
 
+// Effective transpose in semitones (file transpose + live offset + octave shift)
+export const effectiveTranspose = writable(0);
+
 // Playlist state
 export const midiFiles = writable([]);
 export const playlist = writable([]);
@@ -36,6 +39,13 @@ export const isMinimized = writable(false);
 export const miniMode = writable(false);
 export const smartPause = writable(true);
 
+// Shuffle queue navigation (order is tracked in the backend)
+export const shuffle = writable(false);
+
+// Auto-advance to the next queued song when one finishes
+export const autoplayNext = writable(true);
+export const autoplayGapMs = writable(0);
+
 // Store previous window size/position for restore
 let previousWindowState = null;
 
@@ -289,7 +299,7 @@ export const formatTime = (seconds) => {
   return `${mins}:${secs.toString().padStart(2, '0')}`;
 };
 
-// Load MIDI files from album folder
+// Load MIDI files from every library folder
 export async function loadMidiFiles() {
   try {
     const files = await invoke('load_midi_files');
@@ -299,7 +309,35 @@ export async function loadMidiFiles() {
   }
 }
 
-// Import a MIDI file to album folder
+// Folders the library is built from (the album folder unless configured)
+export async function getLibraryPaths() {
+  try {
+    return await invoke('get_library_paths');
+  } catch (error) {
+    console.error('Failed to get library paths:', error);
+    return [];
+  }
+}
+
+export async function setLibraryPaths(paths) {
+  try {
+    await invoke('set_library_paths', { paths });
+    await loadMidiFiles();
+  } catch (error) {
+    console.error('Failed to set library paths:', error);
+  }
+}
+
+// Library that new imports are copied into
+export async function setDefaultLibrary(path) {
+  try {
+    await invoke('set_default_library', { path });
+  } catch (error) {
+    console.error('Failed to set default library:', error);
+  }
+}
+
+// Import a MIDI file to the default library
 export async function importMidiFile(sourcePath) {
   try {
     const newFile = await invoke('import_midi_file', { sourcePath });
@@ -312,6 +350,18 @@ export async function importMidiFile(sourcePath) {
   }
 }
 
+// Import every MIDI file from a zip song pack
+export async function importMidiPack(zipPath) {
+  try {
+    const summary = await invoke('import_midi_pack', { zipPath });
+    midiFiles.update(files => [...files, ...summary.imported]);
+    return { success: summary.imported.length > 0, imported: summary.imported.length, skipped: summary.skipped };
+  } catch (error) {
+    console.error('Failed to import song pack:', error);
+    return { success: false, imported: 0, error: error.toString() };
+  }
+}
+
 // Play a MIDI file
 export async function playMidi(path) {
   try {
@@ -328,15 +378,84 @@ export async function playMidi(path) {
     await new Promise(resolve => setTimeout(resolve, 50));
 
     // Focus is now handled in the backend after playback starts
+    // Paused state comes from the backend: it may start paused waiting for the game to be focused
     await refreshPlaybackState();
     isPlaying.set(true);
-    isPaused.set(false);
     currentFile.set(path);
   } catch (error) {
     console.error('Failed to play MIDI:', error);
   }
 }
 
+// Experimental: play two parts together, part B starting offsetMs later.
+// Returns a warning string when the combined parts are too dense, otherwise null.
+export async function playDuet(pathA, pathB, offsetMs = 0) {
+  try {
+    delaySmartPause();
+    currentPosition.set(0);
+    isPlaying.set(false);
+    isPaused.set(false);
+
+    const warning = await invoke('play_duet', { pathA, pathB, offsetMs });
+
+    await new Promise(resolve => setTimeout(resolve, 50));
+    await refreshPlaybackState();
+    isPlaying.set(true);
+    currentFile.set(pathA);
+    return warning;
+  } catch (error) {
+    console.error('Failed to play duet:', error);
+    return null;
+  }
+}
+
+// Ms left before a delayed start (null when not counting in)
+export const startCountdown = writable(null);
+
+// Start after a count-in of delayMs, or -delayMs into the song when negative
+export async function playMidiDelayed(path, delayMs) {
+  try {
+    delaySmartPause();
+    currentPosition.set(0);
+    isPlaying.set(false);
+    isPaused.set(false);
+
+    await invoke('play_midi_delayed', { path, delayMs });
+
+    await new Promise(resolve => setTimeout(resolve, 50));
+    await refreshPlaybackState();
+    isPlaying.set(true);
+    currentFile.set(path);
+  } catch (error) {
+    console.error('Failed to play MIDI with delay:', error);
+  }
+}
+
+// Load without playing and return the auto-transpose with its alternatives,
+// e.g. { detected: 2, applied: 2, overridden: false, candidates: [{ transpose, score }] }
+export async function loadSong(path) {
+  try {
+    const detection = await invoke('load_song', { path });
+    currentFile.set(path);
+    return detection;
+  } catch (error) {
+    console.error('Failed to load song:', error);
+    return null;
+  }
+}
+
+// Play the song loaded by loadSong, after any transpose override
+export async function playLoaded() {
+  try {
+    delaySmartPause();
+    await invoke('play_loaded');
+    await refreshPlaybackState();
+    isPlaying.set(true);
+  } catch (error) {
+    console.error('Failed to play loaded song:', error);
+  }
+}
+
 // Pause/Resume playback
 export async function pauseResume() {
   try {
@@ -368,6 +487,25 @@ export async function stopPlayback() {
   }
 }
 
+// Set when playing without a heartbeat for over a second, i.e. the playback thread hung
+export const playbackStalled = writable(false);
+const STALL_TIMEOUT_MS = 1000;
+let lastHeartbeat = Date.now();
+
+// Abandon a hung playback thread and release every key
+export async function forceStop() {
+  try {
+    await invoke('force_stop');
+    isPlaying.set(false);
+    isPaused.set(false);
+    currentPosition.set(0);
+    currentFile.set(null);
+    playbackStalled.set(false);
+  } catch (error) {
+    console.error('Failed to force stop:', error);
+  }
+}
+
 // Toggle loop mode
 export async function toggleLoop() {
   const newLoopMode = !get(loopMode);
@@ -434,6 +572,117 @@ export async function endSeeking() {
   isSeeking.set(false);
 }
 
+// Whether the given file is the one playing: { playing, paused }
+export async function isFilePlaying(path) {
+  try {
+    return await invoke('is_file_playing', { path });
+  } catch (error) {
+    console.error('Failed to check playing file:', error);
+    return { playing: false, paused: false };
+  }
+}
+
+// Tracks of a MIDI file: [{ index, name, note_count, lowest_note, highest_note, program, instrument, percussion, melody }]
+export async function listTracks(path) {
+  try {
+    return await invoke('list_tracks', { path });
+  } catch (error) {
+    console.error('Failed to list tracks:', error);
+    return [];
+  }
+}
+
+// Play only these track indices of the loaded song ([] = every track)
+export async function setActiveTracks(indices) {
+  try {
+    await invoke('set_active_tracks', { indices });
+  } catch (error) {
+    console.error('Failed to set active tracks:', error);
+  }
+}
+
+// Keep one note of every chord: 'Off', 'Top', 'Bottom' or 'Root'
+export async function setChordReduction(mode) {
+  try {
+    await invoke('set_chord_reduction', { mode });
+  } catch (error) {
+    console.error('Failed to set chord reduction:', error);
+  }
+}
+
+// Skip notes on channels whose CC7 volume is below minVolume (0-127, 0 = off)
+export async function setMinChannelVolume(minVolume) {
+  try {
+    await invoke('set_min_channel_volume', { minVolume });
+  } catch (error) {
+    console.error('Failed to set minimum channel volume:', error);
+  }
+}
+
+// Test mode: keys light up the on-screen keyboard ('test-key-echo') instead of reaching the game
+export async function setTestMode(enabled) {
+  try {
+    await invoke('test_mode', { enabled });
+  } catch (error) {
+    console.error('Failed to set test mode:', error);
+  }
+}
+
+// Report every note -> key mapping as a 'debug-mapping' event while playing
+export async function setDebugLogging(enabled) {
+  try {
+    await invoke('set_debug_logging', { enabled });
+  } catch (error) {
+    console.error('Failed to set debug logging:', error);
+  }
+}
+
+// Latest failure reported by a background thread (playback-error / hotkey-error)
+export const backgroundError = writable(null);
+
+// Call and response: pause after every pass until resumed, for the student's turn
+export const yourTurn = writable(false);
+
+export async function setCallAndResponse(enabled) {
+  try {
+    await invoke('set_call_and_response', { enabled });
+  } catch (error) {
+    console.error('Failed to set call and response:', error);
+  }
+}
+
+// Seek by beat through the tempo map, so scrubbing through tempo changes stays musical
+export async function seekToBeat(beat) {
+  try {
+    const position = await invoke('seek_to_beat', { beat });
+    currentPosition.set(position);
+  } catch (error) {
+    console.error('Failed to seek to beat:', error);
+  }
+}
+
+// Jump to the next or previous note ('Next' / 'Previous')
+export async function seekToNote(direction) {
+  try {
+    const position = await invoke('seek_to_note', { direction });
+    currentPosition.set(position);
+  } catch (error) {
+    console.error('Failed to seek to note:', error);
+  }
+}
+
+// Restore every backend setting to its default (libraries and album order are kept)
+export async function resetSettings() {
+  try {
+    await invoke('reset_settings');
+    localStorage.removeItem(STORAGE_KEYS.NOTE_MODE);
+    noteMode.set("Closest");
+    octaveShift.set(0);
+  } catch (error) {
+    console.error('Failed to reset settings:', error);
+  }
+}
+
 // Set note calculation mode (Default or Detailed)
 export async function setNoteMode(mode) {
   try {
@@ -458,6 +707,150 @@ export async function setOctaveShift(shift) {
   }
 }
 
+// Nudge octave shift up/down by one (backend clamps to -2..+2)
+export async function octaveUp() {
+  try {
+    const state = await invoke('octave_up');
+    octaveShift.set(state.octave_shift);
+  } catch (error) {
+    console.error('Failed to shift octave up:', error);
+  }
+}
+
+export async function octaveDown() {
+  try {
+    const state = await invoke('octave_down');
+    octaveShift.set(state.octave_shift);
+  } catch (error) {
+    console.error('Failed to shift octave down:', error);
+  }
+}
+
+// Path of the file being previewed from the list, if any
+export const previewFile = writable(null);
+
+// Play the first few seconds of a file without loading it (pauses current playback)
+export async function previewMidi(path, seconds = 10) {
+  try {
+    await invoke('preview_midi', { path, seconds });
+    if (get(isPlaying)) {
+      isPaused.set(true);
+    }
+    previewFile.set(path);
+  } catch (error) {
+    console.error('Failed to preview MIDI:', error);
+  }
+}
+
+export async function stopPreview() {
+  try {
+    await invoke('stop_preview');
+    previewFile.set(null);
+  } catch (error) {
+    console.error('Failed to stop preview:', error);
+  }
+}
+
+// Manual step mode: load a song, then each step presses the next note group
+export const manualStep = writable(0);
+
+export async function startManualStep(path) {
+  try {
+    await stopPlayback();
+    const state = await invoke('start_manual_step', { path });
+    currentFile.set(path);
+    manualStep.set(state.manual_step);
+  } catch (error) {
+    console.error('Failed to start manual step:', error);
+  }
+}
+
+export async function stepNextNote() {
+  try {
+    const state = await invoke('step_next_note');
+    manualStep.set(state.manual_step);
+  } catch (error) {
+    console.error('Failed to step to next note:', error);
+  }
+}
+
+// Nudge the live transpose offset while listening (-12 to +12)
+export async function nudgeTranspose(delta) {
+  try {
+    const state = await invoke('nudge_transpose', { delta });
+    effectiveTranspose.set(state.effective_transpose);
+  } catch (error) {
+    console.error('Failed to nudge transpose:', error);
+  }
+}
+
+// Turn auto-advance on/off, optionally with a pause between songs
+export async function setAutoplayNext(enabled, gapMs = null) {
+  try {
+    await invoke('set_autoplay_next', { enabled, gapMs });
+    autoplayNext.set(enabled);
+    if (gapMs !== null) {
+      autoplayGapMs.set(gapMs);
+    }
+  } catch (error) {
+    console.error('Failed to set autoplay:', error);
+  }
+}
+
+// Remember the last song and position across restarts
+export async function setRememberLastSong(enabled) {
+  try {
+    await invoke('set_remember_last_song', { enabled });
+  } catch (error) {
+    console.error('Failed to set remember last song:', error);
+  }
+}
+
+// Last session's song and position ({ path, position, position_formatted }), or null
+export async function getResumeState() {
+  try {
+    return await invoke('get_resume_state');
+  } catch (error) {
+    console.error('Failed to get resume state:', error);
+    return null;
+  }
+}
+
+// Play the last session's song from where it left off
+export async function resumeLast() {
+  try {
+    const resume = await invoke('get_resume_state');
+    if (!resume) return;
+    delaySmartPause();
+    isPlaying.set(false);
+    isPaused.set(false);
+
+    const position = await invoke('resume_last');
+
+    currentPosition.set(position);
+    await new Promise(resolve => setTimeout(resolve, 50));
+    await refreshPlaybackState();
+    isPlaying.set(true);
+    currentFile.set(resume.path);
+  } catch (error) {
+    console.error('Failed to resume last song:', error);
+  }
+}
+
+// Toggle shuffle; turning it off continues in order from the current track
+export async function setShuffle(enabled) {
+  try {
+    await invoke('set_shuffle', {
+      enabled,
+      current: get(currentIndex),
+      count: get(playlist).length,
+    });
+    shuffle.set(enabled);
+  } catch (error) {
+    console.error('Failed to set shuffle:', error);
+  }
+}
+
 // Play next in playlist
 export async function playNext() {
   const $playlist = get(playlist);
@@ -465,7 +858,7 @@ export async function playNext() {
 
   if ($playlist.length === 0) return;
 
-  const nextIndex = ($currentIndex + 1) % $playlist.length;
+  const nextIndex = await invoke('next_track_index', { current: $currentIndex, count: $playlist.length });
   currentIndex.set(nextIndex);
 
   // Reset position immediately before starting new track
@@ -482,7 +875,7 @@ export async function playPrevious() {
 
   if ($playlist.length === 0) return;
 
-  const prevIndex = ($currentIndex - 1 + $playlist.length) % $playlist.length;
+  const prevIndex = await invoke('previous_track_index', { current: $currentIndex, count: $playlist.length });
   currentIndex.set(prevIndex);
 
   // Reset position immediately before starting new track
@@ -543,6 +936,13 @@ export function initializeListeners() {
   // Initialize storage first
   initializeStorage();
 
+  invoke('get_autoplay_next')
+    .then(settings => {
+      autoplayNext.set(settings.enabled);
+      autoplayGapMs.set(settings.gap_ms);
+    })
+    .catch(error => console.error('Failed to load autoplay settings:', error));
+
   // Listen for playback progress updates from backend (single source of truth)
   listen('playback-progress', (event) => {
     if (!get(isSeeking)) {
@@ -550,7 +950,54 @@ export function initializeListeners() {
     }
   });
 
-  // Listen for playback ended
+  // Songs added, removed or changed in a library folder
+  listen('album-changed', () => loadMidiFiles());
+
+  // Paused/resumed by the backend when the game loses or gets focus
+  listen('focus-lost', () => isPaused.set(true));
+  listen('focus-regained', () => isPaused.set(false));
+
+  // Counting in also shows the playback thread is alive
+  listen('playback-starting', (event) => {
+    lastHeartbeat = Date.now();
+    startCountdown.set(event.payload > 0 ? event.payload : null);
+  });
+
+  // Watch for a hung playback thread
+  listen('heartbeat', () => {
+    lastHeartbeat = Date.now();
+    playbackStalled.set(false);
+  });
+  isPlaying.subscribe(playing => {
+    lastHeartbeat = Date.now();
+    if (!playing) {
+      playbackStalled.set(false);
+      startCountdown.set(null);
+    }
+  });
+  setInterval(() => {
+    if (get(isPlaying) && Date.now() - lastHeartbeat > STALL_TIMEOUT_MS) {
+      playbackStalled.set(true);
+    }
+  }, STALL_TIMEOUT_MS / 2);
+
+  // Failures from the playback and hotkey threads, for the UI to show
+  listen('playback-error', (event) => backgroundError.set(event.payload));
+  listen('hotkey-error', (event) => backgroundError.set(event.payload));
+
+  listen('section-complete', () => {
+    isPaused.set(true);
+    yourTurn.set(true);
+  });
+  isPaused.subscribe(paused => {
+    if (!paused) yourTurn.set(false);
+  });
+
+  listen('preview-ended', () => {
+    previewFile.set(null);
+  });
+
+  // Listen for playback ended (only sent when a song finishes, never on stop)
   listen('playback-ended', async () => {
     const $playlist = get(playlist);
     const $loopMode = get(loopMode);
@@ -558,7 +1005,15 @@ export function initializeListeners() {
     if ($loopMode && $playlist.length === 1) {
       // Restart the same song
       await playMidi(get(currentFile));
-    } else if ($playlist.length > 1) {
+    } else if ($playlist.length > 1 && get(autoplayNext)) {
+      const endedFile = get(currentFile);
+      const gapMs = get(autoplayGapMs);
+      if (gapMs > 0) {
+        isPlaying.set(false);
+        await new Promise(resolve => setTimeout(resolve, gapMs));
+        // The user picked something else during the gap
+        if (get(isPlaying) || get(currentFile) !== endedFile) return;
+      }
       // Play next in playlist
       await playNext();
     } else {
@@ -624,6 +1079,9 @@ async function refreshPlaybackState() {
     if (state.octave_shift !== undefined) {
       octaveShift.set(state.octave_shift);
     }
+    if (state.effective_transpose !== undefined) {
+      effectiveTranspose.set(state.effective_transpose);
+    }
   } catch (error) {
     console.error('Failed to refresh playback status:', error);
   }